@@ -0,0 +1,175 @@
+//! Times [BlobVec] channels against the `Vec<T>` channels they replaced.
+//! Run with `cargo bench --features bench --bench channels`.
+
+use std::{
+    any::Any,
+    sync::RwLock,
+    time::{Duration, Instant},
+};
+
+use rust_ecs::*;
+
+const ROWS: usize = 100_000;
+
+#[derive(Clone, Copy)]
+struct Position([f32; 3]);
+
+/// A component with a heap allocation so drops aren't free. It's never read.
+#[allow(dead_code)]
+struct Name(String);
+
+fn time(name: &str, iterations: u32, mut f: impl FnMut()) {
+    let mut total = Duration::ZERO;
+    for _ in 0..iterations {
+        let start = Instant::now();
+        f();
+        total += start.elapsed();
+    }
+    println!("{:<40} {:?}", name, total / iterations);
+}
+
+/// The channel [BlobVec] replaced: a boxed `RwLock<Vec<T>>` that components are passed to
+/// as an `Option<T>` behind `dyn Any`.
+trait VecChannel: Send + Sync {
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+    fn push(&mut self, component: &mut dyn Any);
+    fn migrate(&mut self, other: &mut dyn VecChannel, index: usize);
+    fn swap_remove(&mut self, index: usize);
+}
+
+impl<T: Send + Sync + 'static> VecChannel for RwLock<Vec<T>> {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+    fn push(&mut self, component: &mut dyn Any) {
+        let component = component.downcast_mut::<Option<T>>().unwrap().take();
+        self.get_mut().unwrap().push(component.unwrap());
+    }
+    fn migrate(&mut self, other: &mut dyn VecChannel, index: usize) {
+        let component = self.get_mut().unwrap().swap_remove(index);
+        other
+            .as_any_mut()
+            .downcast_mut::<RwLock<Vec<T>>>()
+            .unwrap()
+            .get_mut()
+            .unwrap()
+            .push(component);
+    }
+    fn swap_remove(&mut self, index: usize) {
+        self.get_mut().unwrap().swap_remove(index);
+    }
+}
+
+fn vec_channels() -> Vec<Box<dyn VecChannel>> {
+    vec![
+        Box::new(RwLock::new(Vec::<Position>::new())),
+        Box::new(RwLock::new(Vec::<Name>::new())),
+    ]
+}
+
+fn blob_vec_channels() -> Vec<RwLock<BlobVec>> {
+    vec![
+        RwLock::new(blob_vec_bench::new::<Position>()),
+        RwLock::new(blob_vec_bench::new::<Name>()),
+    ]
+}
+
+/// Pushes, migrates, iterates and removes the same rows through both kinds of channel.
+fn main() {
+    println!("{} rows", ROWS);
+    time("Vec<T> channels: push", 10, || {
+        let mut channels = vec_channels();
+        for i in 0..ROWS {
+            channels[0].push(&mut Some(Position([i as f32, 0.0, 0.0])));
+            channels[1].push(&mut Some(Name(format!("Entity {}", i))));
+        }
+    });
+    time("BlobVec channels: push", 10, || {
+        let mut channels = blob_vec_channels();
+        for i in 0..ROWS {
+            // SAFETY: The first channel stores `Position`s and the second `Name`s.
+            unsafe {
+                blob_vec_bench::push(
+                    channels[0].get_mut().unwrap(),
+                    Position([i as f32, 0.0, 0.0]),
+                );
+                blob_vec_bench::push(
+                    channels[1].get_mut().unwrap(),
+                    Name(format!("Entity {}", i)),
+                );
+            }
+        }
+    });
+
+    let mut channels = vec_channels();
+    for i in 0..ROWS {
+        channels[0].push(&mut Some(Position([i as f32, 0.0, 0.0])));
+        channels[1].push(&mut Some(Name(format!("Entity {}", i))));
+    }
+    let mut other = vec_channels();
+    time("Vec<T> channels: migrate", 1, || {
+        for _ in 0..ROWS {
+            for (channel, other) in channels.iter_mut().zip(other.iter_mut()) {
+                channel.migrate(&mut **other, 0);
+            }
+        }
+    });
+    time("Vec<T> channels: iterate", 100, || {
+        let positions = other[0]
+            .as_any()
+            .downcast_ref::<RwLock<Vec<Position>>>()
+            .unwrap()
+            .read()
+            .unwrap();
+        let sum: f32 = positions.iter().map(|position| position.0[0]).sum();
+        std::hint::black_box(sum);
+    });
+    time("Vec<T> channels: swap_remove", 1, || {
+        for _ in 0..ROWS {
+            for channel in other.iter_mut() {
+                channel.swap_remove(0);
+            }
+        }
+    });
+
+    let mut channels = blob_vec_channels();
+    for i in 0..ROWS {
+        // SAFETY: The first channel stores `Position`s and the second `Name`s.
+        unsafe {
+            blob_vec_bench::push(
+                channels[0].get_mut().unwrap(),
+                Position([i as f32, 0.0, 0.0]),
+            );
+            blob_vec_bench::push(
+                channels[1].get_mut().unwrap(),
+                Name(format!("Entity {}", i)),
+            );
+        }
+    }
+    let mut other = blob_vec_channels();
+    time("BlobVec channels: migrate", 1, || {
+        for _ in 0..ROWS {
+            for (channel, other) in channels.iter_mut().zip(other.iter_mut()) {
+                blob_vec_bench::migrate(channel.get_mut().unwrap(), other.get_mut().unwrap(), 0);
+            }
+        }
+    });
+    time("BlobVec channels: iterate", 100, || {
+        let positions = other[0].read().unwrap();
+        // SAFETY: The first channel stores `Position`s.
+        let positions = unsafe { blob_vec_bench::as_slice::<Position>(&positions) };
+        let sum: f32 = positions.iter().map(|position| position.0[0]).sum();
+        std::hint::black_box(sum);
+    });
+    time("BlobVec channels: swap_remove", 1, || {
+        for _ in 0..ROWS {
+            for channel in other.iter_mut() {
+                blob_vec_bench::swap_remove(channel.get_mut().unwrap(), 0);
+            }
+        }
+    });
+}
@@ -0,0 +1,98 @@
+//! Times snapshotting and restoring a World for rollback against a 60 frames per second budget.
+//! Run with `cargo bench --bench rollback`.
+
+use std::time::{Duration, Instant};
+
+use rust_ecs::*;
+
+const ENTITIES: usize = 10_000;
+const FRAMES: u32 = 60;
+
+#[derive(Clone, Copy)]
+struct Position([f32; 3]);
+impl ComponentTrait for Position {
+    fn clone_vec(data: &[Self]) -> Option<Vec<Self>> {
+        Some(data.into())
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Velocity([f32; 3]);
+impl ComponentTrait for Velocity {
+    fn clone_vec(data: &[Self]) -> Option<Vec<Self>> {
+        Some(data.into())
+    }
+}
+
+/// A component with a heap allocation so clones aren't a plain copy.
+#[derive(Clone)]
+struct Name(String);
+impl ComponentTrait for Name {
+    fn clone_vec(data: &[Self]) -> Option<Vec<Self>> {
+        Some(data.into())
+    }
+}
+
+fn time(name: &str, iterations: u32, mut f: impl FnMut()) -> Duration {
+    let mut total = Duration::ZERO;
+    for _ in 0..iterations {
+        let start = Instant::now();
+        f();
+        total += start.elapsed();
+    }
+    println!("{:<40} {:?}", name, total / iterations);
+    total / iterations
+}
+
+fn main() {
+    println!("{} entities", ENTITIES);
+    let mut world = World::new();
+    for i in 0..ENTITIES {
+        let entity = world.spawn((Position([i as f32, 0.0, 0.0]), Velocity([1.0, 0.0, 0.0])));
+        if i % 4 == 0 {
+            world
+                .add_components(entity, Name(format!("Entity {}", i)))
+                .unwrap();
+        }
+    }
+
+    let snapshot = time("snapshot", FRAMES, || {
+        world.snapshot();
+    });
+    let saved = world.snapshot();
+    let restore = time("restore", FRAMES, || world.restore(&saved));
+
+    let mut ring = SnapshotRing::new(8);
+    let frame = time("simulate + record frame", FRAMES, || {
+        for (position, velocity) in world
+            .query_mut::<All<(&mut Position, &Velocity)>>()
+            .iter_mut()
+        {
+            position.0[0] += velocity.0[0];
+        }
+        ring.record(&mut world);
+    });
+    time("rollback 4 frames", 1, || {
+        ring.rollback(&mut world, 4);
+    });
+    let named = world
+        .query::<All<&Name>>()
+        .iter()
+        .filter(|name| !name.0.is_empty())
+        .count();
+    assert_eq!(named, ENTITIES / 4);
+
+    let budget = Duration::from_secs(1) / FRAMES;
+    println!(
+        "{:<40} {:.1}% of a {:?} frame",
+        "snapshot + restore",
+        (snapshot + restore).as_secs_f64() / budget.as_secs_f64() * 100.0,
+        budget
+    );
+    println!(
+        "{:<40} {:.1}% of a {:?} frame",
+        "simulate + record frame",
+        frame.as_secs_f64() / budget.as_secs_f64() * 100.0,
+        budget
+    );
+}
@@ -0,0 +1,122 @@
+//! Times the archetype storage hot paths: spawning, migrating, iterating and despawning.
+//! Run with `cargo bench --bench storage`.
+
+use std::time::{Duration, Instant};
+
+use rust_ecs::*;
+
+const ENTITIES: usize = 100_000;
+
+#[derive(Clone, Copy)]
+struct Position([f32; 3]);
+impl ComponentTrait for Position {
+    fn clone_vec(data: &[Self]) -> Option<Vec<Self>> {
+        Some(data.into())
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Velocity([f32; 3]);
+impl ComponentTrait for Velocity {
+    fn clone_vec(data: &[Self]) -> Option<Vec<Self>> {
+        Some(data.into())
+    }
+}
+
+/// A component with a heap allocation so drops aren't free.
+#[derive(Clone)]
+struct Name(String);
+impl ComponentTrait for Name {
+    fn clone_vec(data: &[Self]) -> Option<Vec<Self>> {
+        Some(data.into())
+    }
+}
+
+fn time(name: &str, iterations: u32, mut f: impl FnMut()) {
+    let mut total = Duration::ZERO;
+    for _ in 0..iterations {
+        let start = Instant::now();
+        f();
+        total += start.elapsed();
+    }
+    println!("{:<40} {:?}", name, total / iterations);
+}
+
+fn spawn_entities(world: &mut World) -> Vec<Entity> {
+    (0..ENTITIES)
+        .map(|i| {
+            world.spawn((
+                Position([i as f32, 0.0, 0.0]),
+                Name(format!("Entity {}", i)),
+            ))
+        })
+        .collect()
+}
+
+fn main() {
+    println!("{} entities", ENTITIES);
+    time("spawn", 10, || {
+        let mut world = World::new();
+        spawn_entities(&mut world);
+    });
+
+    time("spawn_batch", 10, || {
+        let mut world = World::new();
+        world
+            .spawn_batch((0..ENTITIES).map(|i| {
+                (
+                    Position([i as f32, 0.0, 0.0]),
+                    Name(format!("Entity {}", i)),
+                )
+            }))
+            .count();
+    });
+
+    let mut world = World::new();
+    let entities = spawn_entities(&mut world);
+    time("add component (migrate)", 1, || {
+        for entity in entities.iter() {
+            world
+                .add_components(*entity, Velocity([1.0, 0.0, 0.0]))
+                .unwrap();
+        }
+    });
+    time("iterate", 100, || {
+        for (position, velocity) in world
+            .query_mut::<All<(&mut Position, &Velocity)>>()
+            .iter_mut()
+        {
+            for (p, v) in position.0.iter_mut().zip(velocity.0.iter()) {
+                *p += v;
+            }
+        }
+    });
+    time("iterate names", 100, || {
+        let len: usize = world
+            .query::<All<&Name>>()
+            .iter()
+            .map(|name| name.0.len())
+            .sum();
+        std::hint::black_box(len);
+    });
+    time("remove component (migrate)", 1, || {
+        for entity in entities.iter() {
+            world.remove_components::<Velocity>(*entity).unwrap();
+        }
+    });
+    time("despawn", 1, || {
+        for entity in entities.iter() {
+            world.despawn(*entity).unwrap();
+        }
+    });
+
+    let mut world = World::new();
+    let entities = spawn_entities(&mut world);
+    time("despawn_batch", 1, || {
+        world.despawn_batch(&entities);
+    });
+    spawn_entities(&mut world);
+    time("despawn_matching", 1, || {
+        world.despawn_matching::<With<Position>>();
+    });
+}
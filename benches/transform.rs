@@ -0,0 +1,78 @@
+//! Times transform propagation on a 100k entity scene.
+//! Run with `cargo bench --features transform`.
+
+use std::time::{Duration, Instant};
+
+use rust_ecs::*;
+
+const ROOTS: usize = 1000;
+const CHILDREN: usize = 9;
+const GRANDCHILDREN: usize = 10;
+
+fn spawn_scene(world: &mut World) -> (Vec<Entity>, Vec<Entity>) {
+    let transform = || {
+        (
+            LocalTransform(Mat4::from_translation([1.0, 0.0, 0.0]) * Mat4::from_rotation_z(0.1)),
+            GlobalTransform::default(),
+        )
+    };
+    let mut roots = Vec::new();
+    let mut leaves = Vec::new();
+    for _ in 0..ROOTS {
+        let root = world.spawn(transform());
+        roots.push(root);
+        for _ in 0..CHILDREN {
+            let child = world.spawn(transform());
+            world.add_components(child, TransformParent(root)).unwrap();
+            for _ in 0..GRANDCHILDREN {
+                let grandchild = world.spawn(transform());
+                world
+                    .add_components(grandchild, TransformParent(child))
+                    .unwrap();
+                leaves.push(grandchild);
+            }
+        }
+    }
+    (roots, leaves)
+}
+
+fn time(name: &str, iterations: u32, mut f: impl FnMut()) {
+    let mut total = Duration::ZERO;
+    for _ in 0..iterations {
+        let start = Instant::now();
+        f();
+        total += start.elapsed();
+    }
+    println!("{:<40} {:?}", name, total / iterations);
+}
+
+fn main() {
+    let mut world = World::new();
+    let (roots, leaves) = spawn_scene(&mut world);
+    println!(
+        "{} entities",
+        world.query::<All<&LocalTransform>>().iter().count()
+    );
+
+    let mut propagation = TransformPropagation::new();
+    time("initial propagation", 1, || {
+        propagation.propagate(&mut world)
+    });
+    time("nothing dirty", 100, || propagation.propagate(&mut world));
+    time("one root dirty", 100, || {
+        world.get_mut::<LocalTransform>(roots[0]).unwrap();
+        propagation.propagate(&mut world)
+    });
+    time("every root dirty", 10, || {
+        for root in roots.iter() {
+            world.get_mut::<LocalTransform>(*root).unwrap();
+        }
+        propagation.propagate(&mut world)
+    });
+    time("1% of leaves dirty", 100, || {
+        for leaf in leaves.iter().step_by(100) {
+            world.get_mut::<LocalTransform>(*leaf).unwrap();
+        }
+        propagation.propagate(&mut world)
+    });
+}
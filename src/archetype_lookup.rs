@@ -1,4 +1,7 @@
-use super::{sparse_set, ComponentId};
+use super::{sparse_set, ComponentId, ComponentTypeId};
+
+/// The most [Filter]s a single query can match against.
+pub(crate) const MAX_FILTER_COUNT: usize = 12;
 
 /// [ArchetypeLookup] is used to efficiently find [Archetype]s that match [Filter]s
 pub(crate) struct ArchetypeLookup {
@@ -6,6 +9,9 @@ pub(crate) struct ArchetypeLookup {
     exact_component_ids_to_archetype: std::collections::HashMap<Vec<ComponentId>, usize>,
     component_id_to_archetypes:
         std::collections::HashMap<ComponentId, sparse_set::SparseSet<usize>>,
+    /// The [Archetype]s with any pair of a relation and the index of the first pair's channel.
+    relation_to_archetypes:
+        std::collections::HashMap<ComponentTypeId, sparse_set::SparseSet<usize>>,
     total_archetype_count: usize,
 }
 
@@ -14,6 +20,7 @@ impl ArchetypeLookup {
         Self {
             component_id_to_archetypes: std::collections::HashMap::new(),
             exact_component_ids_to_archetype: std::collections::HashMap::new(),
+            relation_to_archetypes: std::collections::HashMap::new(),
             total_archetype_count: 0,
         }
     }
@@ -26,13 +33,31 @@ impl ArchetypeLookup {
             let component_id_to_archetypes = self
                 .component_id_to_archetypes
                 .entry(component_id)
-                .or_insert_with(|| sparse_set::SparseSet::new());
-            component_id_to_archetypes.insert(archetype_index, index_within_archetype)
+                .or_default();
+            component_id_to_archetypes.insert(archetype_index, index_within_archetype);
+
+            if component_id.target.is_some() {
+                let relation_to_archetypes = self
+                    .relation_to_archetypes
+                    .entry(component_id.type_id)
+                    .or_default();
+                // Pairs of the same relation are sorted next to each other.
+                if !relation_to_archetypes.contains(archetype_index) {
+                    relation_to_archetypes.insert(archetype_index, index_within_archetype);
+                }
+            }
         }
         self.exact_component_ids_to_archetype
             .insert(component_ids.into(), archetype_index);
     }
 
+    /// Iterates the [Archetype]s that contain `component_id`, which may be a relation pair.
+    pub(crate) fn archetypes_with(&self, component_id: ComponentId) -> &[usize] {
+        self.component_id_to_archetypes
+            .get(&component_id)
+            .map_or(&[], |archetypes| archetypes.data_index_to_item_index())
+    }
+
     pub(crate) fn get_exact_archetype(&self, component_ids: &[ComponentId]) -> Option<usize> {
         self.exact_component_ids_to_archetype
             .get(component_ids)
@@ -41,26 +66,37 @@ impl ArchetypeLookup {
 
     /// Iterate matching [Archetype]s
     /// The indices of [Archetype]s returned are guaranteed to be in increasing order.
+    /// `FILTER_COUNT` must be at least `filters.len()`.
     pub(crate) fn matching_archetype_iter<const FILTER_COUNT: usize>(
         &self,
         filters: &[Filter],
-    ) -> MatchingArchetypeIterator<FILTER_COUNT> {
+    ) -> MatchingArchetypeIterator<'_, FILTER_COUNT> {
+        assert!(filters.len() <= FILTER_COUNT);
+
         let mut filter_info = [FilterInfo {
             filter_type: FilterType::With,
             component_id_to_archetypes: None,
+            channel_index: 0,
         }; FILTER_COUNT];
 
-        for (filter, filter_info) in filters.iter().zip(filter_info.iter_mut()) {
+        for (channel_index, (filter, filter_info)) in
+            filters.iter().zip(filter_info.iter_mut()).enumerate()
+        {
             filter_info.filter_type = filter.filter_type;
-            if let Some(component_id_to_archetypes) =
+            filter_info.channel_index = channel_index;
+            let component_id_to_archetypes = if filter.component_id.is_wildcard() {
+                self.relation_to_archetypes
+                    .get(&filter.component_id.type_id)
+            } else {
                 self.component_id_to_archetypes.get(&filter.component_id)
-            {
+            };
+            if let Some(component_id_to_archetypes) = component_id_to_archetypes {
                 filter_info.component_id_to_archetypes = Some(component_id_to_archetypes);
             }
         }
 
         // Sort so the most restrictive filters are searched first.
-        filter_info.sort_by_key(|f| match f.filter_type {
+        filter_info[..filters.len()].sort_by_key(|f| match f.filter_type {
             FilterType::With => f.component_id_to_archetypes.map_or(0, |f| f.len()),
             FilterType::Without => {
                 self.total_archetype_count - f.component_id_to_archetypes.map_or(0, |f| f.len())
@@ -71,6 +107,7 @@ impl ArchetypeLookup {
         MatchingArchetypeIterator {
             offset: 0,
             filter_info,
+            filter_count: filters.len(),
             total_archetypes_count: self.total_archetype_count,
         }
     }
@@ -80,6 +117,8 @@ impl ArchetypeLookup {
 struct FilterInfo<'a> {
     filter_type: FilterType,
     component_id_to_archetypes: Option<&'a sparse_set::SparseSet<usize>>,
+    /// The index of the [Filter] this was created from, which is also where its channel is returned.
+    channel_index: usize,
 }
 
 #[derive(Copy, Clone)]
@@ -98,6 +137,7 @@ pub enum FilterType {
 pub(crate) struct MatchingArchetypeIterator<'a, const FILTER_COUNT: usize> {
     offset: usize,
     filter_info: [FilterInfo<'a>; FILTER_COUNT],
+    filter_count: usize,
     total_archetypes_count: usize,
 }
 
@@ -111,9 +151,8 @@ impl<'a, const CHANNEL_COUNT: usize> Iterator for MatchingArchetypeIterator<'a,
             filter_info: &[FilterInfo],
             corresponding_channels: &mut [Option<usize>],
         ) -> Option<()> {
-            for (filter, output_channel) in
-                filter_info.iter().zip(corresponding_channels.iter_mut())
-            {
+            for filter in filter_info {
+                let output_channel = &mut corresponding_channels[filter.channel_index];
                 match filter.filter_type {
                     FilterType::With => {
                         let component_index_in_archetype =
@@ -141,9 +180,14 @@ impl<'a, const CHANNEL_COUNT: usize> Iterator for MatchingArchetypeIterator<'a,
             Some(())
         }
 
-        let first_filter = self.filter_info[0];
-        match self.filter_info[0].filter_type {
-            FilterType::With => {
+        let filter_info = &self.filter_info[..self.filter_count];
+        match filter_info.first() {
+            Some(
+                first_filter @ FilterInfo {
+                    filter_type: FilterType::With,
+                    ..
+                },
+            ) => {
                 if let Some(matching_archetypes) = first_filter.component_id_to_archetypes {
                     while let Some(&component_index_in_archetype) =
                         matching_archetypes.values().get(self.offset)
@@ -152,13 +196,14 @@ impl<'a, const CHANNEL_COUNT: usize> Iterator for MatchingArchetypeIterator<'a,
                             matching_archetypes.data_index_to_item_index()[self.offset];
 
                         let mut corresponding_channels = [None; CHANNEL_COUNT];
-                        corresponding_channels[0] = Some(component_index_in_archetype);
+                        corresponding_channels[first_filter.channel_index] =
+                            Some(component_index_in_archetype);
                         self.offset += 1;
 
                         if match_filters(
                             archetype_index,
-                            &self.filter_info[1..],
-                            &mut corresponding_channels[1..],
+                            &filter_info[1..],
+                            &mut corresponding_channels,
                         )
                         .is_some()
                         {
@@ -167,19 +212,17 @@ impl<'a, const CHANNEL_COUNT: usize> Iterator for MatchingArchetypeIterator<'a,
                     }
                 }
             }
-            FilterType::Without | FilterType::Optional => {
-                let mut corresponding_channels = [None; CHANNEL_COUNT];
-                for archetype_index in self.offset..self.total_archetypes_count {
-                    if match_filters(
-                        archetype_index,
-                        &self.filter_info,
-                        &mut corresponding_channels,
-                    )
-                    .is_some()
+            _ => {
+                while self.offset < self.total_archetypes_count {
+                    let archetype_index = self.offset;
+                    self.offset += 1;
+
+                    let mut corresponding_channels = [None; CHANNEL_COUNT];
+                    if match_filters(archetype_index, filter_info, &mut corresponding_channels)
+                        .is_some()
                     {
                         return Some((archetype_index, corresponding_channels));
                     }
-                    self.offset += 1;
                 }
             }
         }
@@ -0,0 +1,244 @@
+use std::collections::HashMap;
+
+use crate::{archetype_lookup::MAX_FILTER_COUNT, *};
+
+impl World {
+    /// Spawns an [Entity] for each bundle in `bundles` and returns them in order.
+    /// Faster than calling [World::spawn] repeatedly because the [Archetype] is only looked up
+    /// once and storage is reserved up front using the iterator's size hint.
+    /// Panics if the bundle has more than one of the same type of component.
+    pub fn spawn_batch<COMPONENTS: ComponentBundleTrait>(
+        &mut self,
+        bundles: impl IntoIterator<Item = COMPONENTS>,
+    ) -> impl Iterator<Item = Entity> {
+        self.try_spawn_batch(bundles)
+            .expect("Cannot spawn `Entity`s with multiple of the same component")
+    }
+
+    /// Like [World::spawn_batch] but errors with [ECSError::DuplicateComponent] instead of
+    /// panicking. Nothing is spawned if it errors.
+    pub fn try_spawn_batch<COMPONENTS: ComponentBundleTrait>(
+        &mut self,
+        bundles: impl IntoIterator<Item = COMPONENTS>,
+    ) -> Result<impl Iterator<Item = Entity>, ECSError> {
+        let mut bundles = bundles.into_iter();
+        let mut entities = Vec::with_capacity(bundles.size_hint().0);
+        let Some(first) = bundles.next() else {
+            return Ok(entities.into_iter());
+        };
+
+        // The first `Entity` is spawned normally to find or create the `Archetype`.
+        // Every bundle has its components in the same order so the channel each
+        // position goes to is recorded and reused. `None` is a sparse component.
+        let mut channel_indices: Vec<Option<usize>> = Vec::new();
+        let mut result = Ok(());
+        first.get_components_and_ids(|components_and_ids| {
+            let component_ids: Vec<(ComponentId, StorageKind)> = components_and_ids
+                .iter()
+                .map(|(component, id)| (*id, component.storage_kind()))
+                .collect();
+            let entity = match self.spawn_inner(components_and_ids, None) {
+                Ok(entity) => entity,
+                Err(error) => {
+                    result = Err(error);
+                    return;
+                }
+            };
+            entities.push(entity);
+            let archetype = &self.archetypes[self
+                .entity_manager
+                .get_entity_location(entity)
+                .unwrap()
+                .storage_index];
+            channel_indices.extend(component_ids.iter().map(|(id, storage_kind)| {
+                (*storage_kind != StorageKind::SparseSet).then(|| {
+                    archetype
+                        .channels
+                        .binary_search_by_key(id, |c| c.0)
+                        .unwrap()
+                })
+            }));
+        });
+        result?;
+        let archetype_index = self
+            .entity_manager
+            .get_entity_location(entities[0])
+            .unwrap()
+            .storage_index;
+
+        let additional = bundles.size_hint().0;
+        self.entity_manager.reserve(additional);
+        let archetype = &mut self.archetypes[archetype_index];
+        archetype.entity_indices.reserve(additional);
+        for ((_, channel), ticks) in archetype.row_channels() {
+            channel.get_mut().unwrap().reserve(additional);
+            ticks.added.reserve(additional);
+            ticks.changed.reserve(additional);
+        }
+
+        let change_tick = *self.change_tick.get_mut();
+        for bundle in bundles {
+            // Hooks may have reserved `Entity`s.
+            self.flush_reserved_entities();
+            bundle.get_components_and_ids(|components_and_ids| {
+                let archetype = &mut self.archetypes[archetype_index];
+                let entity = self.entity_manager.new_entity(EntityLocation {
+                    storage_index: archetype_index,
+                    index_within_storage: archetype.entity_indices.len(),
+                });
+                archetype.entity_indices.push(entity.index);
+                for ((component, component_id), channel_index) in
+                    components_and_ids.iter_mut().zip(channel_indices.iter())
+                {
+                    if let Some(channel_index) = channel_index {
+                        // Tags have no ticks and nothing to store.
+                        if let Some(ticks) = &mut archetype.channel_ticks[*channel_index] {
+                            component
+                                .push_into(archetype.channels[*channel_index].1.get_mut().unwrap());
+                            ticks.push(change_tick);
+                        }
+                    } else {
+                        self.sparse_storages
+                            .entry(*component_id)
+                            .or_insert_with(|| {
+                                self.components
+                                    .get_or_register(*component_id, || component.component_info());
+                                component.new_sparse_storage()
+                            })
+                            .insert(entity.index, *component, change_tick);
+                    }
+                }
+                self.run_hooks_for_entity(&[HookKind::Add, HookKind::Insert], entity);
+                entities.push(entity);
+            });
+        }
+        self.apply_hook_commands();
+        Ok(entities.into_iter())
+    }
+}
+
+impl World {
+    /// Despawns each [Entity] in `entities`, skipping any that no longer exist,
+    /// and returns how many were despawned.
+    /// Faster than calling [World::despawn] repeatedly because each [Archetype]'s columns are
+    /// truncated or compacted once instead of swap removing one row at a time.
+    pub fn despawn_batch(&mut self, entities: &[Entity]) -> usize {
+        self.flush_reserved_entities();
+        let mut rows: HashMap<usize, Vec<bool>> = HashMap::new();
+        for entity in entities {
+            if let Ok(entity_location) = self.entity_manager.get_entity_location(*entity) {
+                let archetype_len = self.archetypes[entity_location.storage_index]
+                    .entity_indices
+                    .len();
+                rows.entry(entity_location.storage_index)
+                    .or_insert_with(|| vec![false; archetype_len])
+                    [entity_location.index_within_storage] = true;
+            }
+        }
+        let count = self.despawn_rows(rows.into_iter().collect());
+        self.apply_hook_commands();
+        count
+    }
+
+    /// Despawns every [Entity] that matches `FILTERS`, such as `With<Dead>`,
+    /// and returns how many were despawned. [Added] and [Changed] filters match every
+    /// component, as with [World::query].
+    pub fn despawn_matching<FILTERS: QueryFilterTrait>(&mut self) -> usize {
+        self.flush_reserved_entities();
+        let ticks = self.query_ticks();
+        let mut rows = Vec::new();
+        let World {
+            archetypes,
+            archetype_lookup,
+            sparse_storages,
+            ..
+        } = &*self;
+        FILTERS::get_filters(|filters| {
+            for (archetype_index, matching_channels) in
+                archetype_lookup.matching_archetype_iter::<MAX_FILTER_COUNT>(filters)
+            {
+                let archetype = &archetypes[archetype_index];
+                let mut sparse_rows = None;
+                let filter = FILTERS::get_result(
+                    &archetype.channel_ticks,
+                    &matching_channels,
+                    &mut SparseContext {
+                        sparse_storages,
+                        entity_indices: &archetype.entity_indices,
+                        rows: &mut sparse_rows,
+                    },
+                    ticks,
+                );
+                rows.push((
+                    archetype_index,
+                    (0..archetype.entity_indices.len())
+                        .map(|row| {
+                            sparse_rows.as_ref().is_none_or(|rows| rows[row]) && filter.matches(row)
+                        })
+                        .collect(),
+                ));
+            }
+            Ok(())
+        })
+        .unwrap();
+        let count = self.despawn_rows(rows);
+        self.apply_hook_commands();
+        count
+    }
+
+    /// Despawns the rows that are `true` for each [Archetype] index.
+    fn despawn_rows(&mut self, rows: Vec<(usize, Vec<bool>)>) -> usize {
+        let entities: Vec<Vec<Entity>> = rows
+            .iter()
+            .map(|(archetype_index, remove)| {
+                remove
+                    .iter()
+                    .zip(self.archetypes[*archetype_index].entity_indices.iter())
+                    .filter(|(remove, _)| **remove)
+                    .map(|(_, index)| self.entity_manager.get_entity(*index))
+                    .collect()
+            })
+            .collect();
+        // Every hook runs before anything is removed so they all see the same `World`.
+        for entity in entities.iter().flatten() {
+            self.run_hooks_for_entity(&[HookKind::Replace, HookKind::Remove], *entity);
+        }
+        // Hooks may have reserved `Entity`s. They're added after the rows in `rows`.
+        self.flush_reserved_entities();
+        let parents: Vec<(Entity, Entity)> = entities
+            .iter()
+            .flatten()
+            .filter_map(|entity| Some((*entity, self.get::<Parent>(*entity).ok()?.get())))
+            .collect();
+
+        for ((archetype_index, remove), entities) in rows.iter().zip(entities.iter()) {
+            let archetype = &mut self.archetypes[*archetype_index];
+            for (component_id, _) in archetype.channels.iter() {
+                self.removed_components
+                    .entry(*component_id)
+                    .or_default()
+                    .entities
+                    .extend_from_slice(entities);
+            }
+            archetype.remove_rows(&mut self.entity_manager, remove);
+            self.entity_manager
+                .despawn_entities(entities.iter().map(|entity| entity.index));
+        }
+
+        if !self.sparse_storages.is_empty() {
+            for entity in entities.iter().flatten() {
+                for (component_id, storage) in self.sparse_storages.iter_mut() {
+                    if storage.remove(entity.index) {
+                        self.removed_components
+                            .entry(*component_id)
+                            .or_default()
+                            .entities
+                            .push(*entity);
+                    }
+                }
+            }
+        }
+        self.detach_despawned(&parents);
+        entities.iter().map(Vec::len).sum()
+    }
+}
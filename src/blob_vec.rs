@@ -0,0 +1,435 @@
+use std::{
+    alloc::Layout,
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+    ptr::NonNull,
+    sync::{RwLockReadGuard, RwLockWriteGuard},
+};
+
+/// Clones every item of a [BlobVec], or returns `None` if they can't be cloned.
+pub(crate) type CloneChannelFn = unsafe fn(&BlobVec) -> Option<BlobVec>;
+
+/// A type-erased `Vec` used for the components in each channel of an [Archetype](crate::Archetype).
+/// Components are moved in and out as bytes so pushing and migrating don't need
+/// virtual calls or downcasts.
+pub struct BlobVec {
+    item_layout: Layout,
+    capacity: usize,
+    len: usize,
+    data: NonNull<u8>,
+    drop: Option<unsafe fn(*mut u8)>,
+    clone: Option<CloneChannelFn>,
+}
+
+// SAFETY: Only components are stored, which are `Send + Sync`.
+unsafe impl Send for BlobVec {}
+unsafe impl Sync for BlobVec {}
+
+pub(crate) unsafe fn drop_ptr<T>(ptr: *mut u8) {
+    ptr.cast::<T>().drop_in_place()
+}
+
+impl BlobVec {
+    pub(crate) fn new<T>() -> Self {
+        Self::from_layout(
+            Layout::new::<T>(),
+            std::mem::needs_drop::<T>().then_some(drop_ptr::<T> as unsafe fn(*mut u8)),
+        )
+    }
+
+    pub(crate) fn from_layout(item_layout: Layout, drop: Option<unsafe fn(*mut u8)>) -> Self {
+        Self {
+            item_layout,
+            capacity: if item_layout.size() == 0 {
+                usize::MAX
+            } else {
+                0
+            },
+            len: 0,
+            data: dangling(item_layout.align()),
+            drop,
+            clone: None,
+        }
+    }
+
+    /// Sets the function [BlobVec::try_clone] uses.
+    ///
+    /// # Safety
+    /// `clone` must clone items of the stored type.
+    pub(crate) unsafe fn with_clone(mut self, clone: Option<CloneChannelFn>) -> Self {
+        self.clone = clone;
+        self
+    }
+
+    /// Clones every item, or returns `None` if the stored type can't be cloned.
+    pub(crate) fn try_clone(&self) -> Option<BlobVec> {
+        // SAFETY: `with_clone` checked that `clone` is for the stored type.
+        unsafe { self.clone?(self) }
+    }
+
+    /// Moves the items of `vec` into a new [BlobVec] with a single copy.
+    pub(crate) fn from_vec<T>(mut vec: Vec<T>) -> Self {
+        let mut blob_vec = Self::new::<T>();
+        blob_vec.reserve(vec.len());
+        // SAFETY: `blob_vec` stores `T`s and has room for `vec`'s items, which are forgotten
+        // by setting `vec`'s length to zero before its buffer is freed.
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                vec.as_ptr().cast::<u8>(),
+                blob_vec.get_ptr(0),
+                vec.len() * std::mem::size_of::<T>(),
+            );
+            blob_vec.len = vec.len();
+            vec.set_len(0);
+        }
+        blob_vec
+    }
+
+    /// Creates a new empty [BlobVec] that stores the same type of component.
+    pub(crate) fn new_same_type(&self) -> Self {
+        // SAFETY: `self.clone` is for the stored type.
+        unsafe { Self::from_layout(self.item_layout, self.drop).with_clone(self.clone) }
+    }
+
+    pub(crate) fn reserve(&mut self, additional: usize) {
+        let required = self.len + additional;
+        if required <= self.capacity {
+            return;
+        }
+        let new_capacity = required.max(self.capacity * 2).max(4);
+        let new_layout = self.array_layout(new_capacity);
+        // SAFETY: `capacity` is only non-zero for non-zero-sized items so `new_layout` isn't empty.
+        let data = unsafe {
+            if self.capacity == 0 {
+                std::alloc::alloc(new_layout)
+            } else {
+                std::alloc::realloc(
+                    self.data.as_ptr(),
+                    self.array_layout(self.capacity),
+                    new_layout.size(),
+                )
+            }
+        };
+        self.data =
+            NonNull::new(data).unwrap_or_else(|| std::alloc::handle_alloc_error(new_layout));
+        self.capacity = new_capacity;
+    }
+
+    fn array_layout(&self, capacity: usize) -> Layout {
+        Layout::from_size_align(self.item_layout.size() * capacity, self.item_layout.align())
+            .expect("Capacity overflow")
+    }
+
+    /// The pointer to the item at `index`, which may be one past the end.
+    fn get_ptr(&self, index: usize) -> *mut u8 {
+        debug_assert!(index <= self.len);
+        // SAFETY: `index` is within the allocation or one past the end.
+        unsafe { self.data.as_ptr().add(index * self.item_layout.size()) }
+    }
+
+    /// Moves the item at `value` onto the end.
+    ///
+    /// # Safety
+    /// `value` must point to an item of the stored type. It must not be used or dropped afterwards.
+    pub(crate) unsafe fn push(&mut self, value: *const u8) {
+        self.reserve(1);
+        std::ptr::copy_nonoverlapping(value, self.get_ptr(self.len), self.item_layout.size());
+        self.len += 1;
+    }
+
+    /// Calls `write` with the uninitialized slot past the end, and keeps the item if it returns `true`.
+    ///
+    /// # Safety
+    /// `write` must initialize the slot with an item of the stored type if it returns `true`.
+    pub(crate) unsafe fn push_with(&mut self, write: impl FnOnce(*mut u8) -> bool) -> bool {
+        self.reserve(1);
+        if !write(self.get_ptr(self.len)) {
+            return false;
+        }
+        self.len += 1;
+        true
+    }
+
+    /// Moves every item of `other` onto the end. `other` must store the same type and is left empty.
+    pub(crate) fn append(&mut self, other: &mut BlobVec) {
+        debug_assert_eq!(self.item_layout, other.item_layout);
+        self.reserve(other.len);
+        // SAFETY: Both store the same type, the allocations don't overlap and `other`'s items
+        // are forgotten after being moved.
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                other.get_ptr(0),
+                self.get_ptr(self.len),
+                other.len * self.item_layout.size(),
+            )
+        };
+        self.len += other.len;
+        other.len = 0;
+    }
+
+    /// Drops the item at `index` and moves the item at `value` into its place.
+    ///
+    /// # Safety
+    /// Same as [BlobVec::push].
+    pub(crate) unsafe fn replace(&mut self, index: usize, value: *const u8) {
+        /// Moves the new item in once the old one is dropped, even if its drop panics,
+        /// so the slot never holds a dropped item.
+        struct MoveIn {
+            ptr: *mut u8,
+            value: *const u8,
+            size: usize,
+        }
+        impl Drop for MoveIn {
+            fn drop(&mut self) {
+                // SAFETY: Same as `replace`.
+                unsafe { std::ptr::copy_nonoverlapping(self.value, self.ptr, self.size) };
+            }
+        }
+
+        assert!(index < self.len);
+        let ptr = self.get_ptr(index);
+        let move_in = MoveIn {
+            ptr,
+            value,
+            size: self.item_layout.size(),
+        };
+        if let Some(drop) = self.drop {
+            drop(ptr);
+        }
+        std::mem::drop(move_in);
+    }
+
+    /// Passes the item at `index` to `f`, which must take ownership of it,
+    /// then moves the last item into its place.
+    pub(crate) fn swap_remove_with(&mut self, index: usize, f: impl FnOnce(*const u8)) {
+        assert!(index < self.len);
+        f(self.get_ptr(index));
+        self.swap_remove_and_forget(index);
+    }
+
+    pub(crate) fn swap_remove(&mut self, index: usize) {
+        assert!(index < self.len);
+        if let Some(drop) = self.drop {
+            // SAFETY: The item is within bounds and is forgotten below.
+            unsafe { drop(self.get_ptr(index)) };
+        }
+        self.swap_remove_and_forget(index);
+    }
+
+    /// Moves the item at `index` onto the end of `other`.
+    /// `other` must store the same type of component.
+    pub(crate) fn migrate(&mut self, other: &mut BlobVec, index: usize) {
+        assert!(index < self.len);
+        debug_assert_eq!(self.item_layout, other.item_layout);
+        // SAFETY: Both store the same type and the item is forgotten here after being moved.
+        unsafe { other.push(self.get_ptr(index)) };
+        self.swap_remove_and_forget(index);
+    }
+
+    /// Moves the last item over the item at `index` without dropping it.
+    fn swap_remove_and_forget(&mut self, index: usize) {
+        let last = self.len - 1;
+        if index != last {
+            // SAFETY: Both items are in bounds and don't overlap.
+            unsafe {
+                std::ptr::copy_nonoverlapping(
+                    self.get_ptr(last),
+                    self.get_ptr(index),
+                    self.item_layout.size(),
+                )
+            };
+        }
+        self.len = last;
+    }
+
+    /// Drops every item from `len` onwards.
+    pub(crate) fn truncate(&mut self, len: usize) {
+        if len >= self.len {
+            return;
+        }
+        let old_len = self.len;
+        // Set first so a panicking drop leaks the rest instead of dropping them twice.
+        self.len = len;
+        if let Some(drop) = self.drop {
+            for i in len..old_len {
+                // SAFETY: Each item was in bounds and is dropped once.
+                unsafe { drop(self.data.as_ptr().add(i * self.item_layout.size())) };
+            }
+        }
+    }
+
+    /// Drops each item `keep` returns `false` for and moves the rest down
+    /// so they stay in the same order.
+    pub(crate) fn retain(&mut self, mut keep: impl FnMut(usize) -> bool) {
+        let old_len = self.len;
+        // Set first so a panicking drop leaks the rest instead of dropping them twice.
+        self.len = 0;
+        let size = self.item_layout.size();
+        let mut kept = 0;
+        for i in 0..old_len {
+            // SAFETY: `i` and `kept` are within the old length and `kept <= i`.
+            unsafe {
+                let ptr = self.data.as_ptr().add(i * size);
+                if keep(i) {
+                    if kept != i {
+                        std::ptr::copy_nonoverlapping(
+                            ptr,
+                            self.data.as_ptr().add(kept * size),
+                            size,
+                        );
+                    }
+                    kept += 1;
+                } else if let Some(drop) = self.drop {
+                    drop(ptr);
+                }
+            }
+        }
+        self.len = kept;
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    /// # Safety
+    /// `T` must be the stored type.
+    pub(crate) unsafe fn as_slice<T>(&self) -> &[T] {
+        debug_assert_eq!(Layout::new::<T>(), self.item_layout);
+        std::slice::from_raw_parts(self.data.as_ptr().cast::<T>(), self.len)
+    }
+
+    /// # Safety
+    /// `T` must be the stored type.
+    pub(crate) unsafe fn as_mut_slice<T>(&mut self) -> &mut [T] {
+        debug_assert_eq!(Layout::new::<T>(), self.item_layout);
+        std::slice::from_raw_parts_mut(self.data.as_ptr().cast::<T>(), self.len)
+    }
+
+    /// A pointer to the item at `index`, used to pass it to hooks.
+    /// Zero-sized items, such as tags whose channels are left empty, can be read at any index.
+    pub(crate) fn get(&self, index: usize) -> *const u8 {
+        assert!(index < self.len || self.item_layout.size() == 0);
+        self.data
+            .as_ptr()
+            .wrapping_add(index * self.item_layout.size())
+    }
+
+    pub(crate) fn get_mut(&mut self, index: usize) -> *mut u8 {
+        assert!(index < self.len || self.item_layout.size() == 0);
+        self.data
+            .as_ptr()
+            .wrapping_add(index * self.item_layout.size())
+    }
+}
+
+impl Drop for BlobVec {
+    fn drop(&mut self) {
+        if let Some(drop) = self.drop {
+            for i in 0..self.len {
+                // SAFETY: Each item is in bounds and dropped once.
+                unsafe { drop(self.get_ptr(i)) };
+            }
+        }
+        if self.item_layout.size() != 0 && self.capacity != 0 {
+            // SAFETY: The data was allocated with this layout.
+            unsafe { std::alloc::dealloc(self.data.as_ptr(), self.array_layout(self.capacity)) };
+        }
+    }
+}
+
+/// [BlobVec]'s crate-private operations, exposed only for benches/channels.rs.
+#[cfg(feature = "bench")]
+#[doc(hidden)]
+pub mod blob_vec_bench {
+    use super::BlobVec;
+
+    pub fn new<T>() -> BlobVec {
+        BlobVec::new::<T>()
+    }
+
+    /// Moves `component` onto the end of `channel`.
+    ///
+    /// # Safety
+    /// `channel` must store `T`s.
+    pub unsafe fn push<T>(channel: &mut BlobVec, component: T) {
+        let component = std::mem::ManuallyDrop::new(component);
+        // SAFETY: The caller ensures `channel` stores `T`s and `component` isn't dropped.
+        channel.push((&*component as *const T).cast())
+    }
+
+    pub fn migrate(channel: &mut BlobVec, other: &mut BlobVec, index: usize) {
+        channel.migrate(other, index);
+    }
+
+    pub fn swap_remove(channel: &mut BlobVec, index: usize) {
+        channel.swap_remove(index);
+    }
+
+    /// # Safety
+    /// `channel` must store `T`s.
+    pub unsafe fn as_slice<T>(channel: &BlobVec) -> &[T] {
+        channel.as_slice()
+    }
+}
+
+/// A well-aligned pointer for an empty or zero-sized allocation.
+fn dangling(align: usize) -> NonNull<u8> {
+    NonNull::new(std::ptr::without_provenance_mut(align)).unwrap()
+}
+
+/// A read lock on a channel of `T`s.
+pub struct ChannelReadGuard<'a, T> {
+    guard: RwLockReadGuard<'a, BlobVec>,
+    phantom: PhantomData<T>,
+}
+
+impl<'a, T> ChannelReadGuard<'a, T> {
+    /// # Safety
+    /// `T` must be the type stored in the [BlobVec].
+    pub(crate) unsafe fn new(guard: RwLockReadGuard<'a, BlobVec>) -> Self {
+        Self {
+            guard,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<T> Deref for ChannelReadGuard<'_, T> {
+    type Target = [T];
+    fn deref(&self) -> &[T] {
+        // SAFETY: Checked when created.
+        unsafe { self.guard.as_slice() }
+    }
+}
+
+/// A write lock on a channel of `T`s.
+pub struct ChannelWriteGuard<'a, T> {
+    guard: RwLockWriteGuard<'a, BlobVec>,
+    phantom: PhantomData<T>,
+}
+
+impl<'a, T> ChannelWriteGuard<'a, T> {
+    /// # Safety
+    /// `T` must be the type stored in the [BlobVec].
+    pub(crate) unsafe fn new(guard: RwLockWriteGuard<'a, BlobVec>) -> Self {
+        Self {
+            guard,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<T> Deref for ChannelWriteGuard<'_, T> {
+    type Target = [T];
+    fn deref(&self) -> &[T] {
+        // SAFETY: Checked when created.
+        unsafe { self.guard.as_slice() }
+    }
+}
+
+impl<T> DerefMut for ChannelWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        // SAFETY: Checked when created.
+        unsafe { self.guard.as_mut_slice() }
+    }
+}
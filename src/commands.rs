@@ -0,0 +1,132 @@
+use std::sync::Mutex;
+
+use crate::*;
+
+type BoxedComponents = Vec<(Box<dyn AnyComponentTrait + Send>, ComponentId)>;
+
+enum Command {
+    Spawn(Entity, BoxedComponents),
+    Despawn(Entity),
+    AddComponents(Entity, BoxedComponents),
+    /// Removing is done through a function so the component types don't need to be stored.
+    RemoveComponents(Entity, fn(&mut World, Entity) -> Result<(), ECSError>),
+}
+
+/// Records structural changes to a [World] so they can be made later with [World::apply_commands].
+/// This allows spawning and despawning while a query is borrowing the [World].
+/// [Commands] can be shared between threads.
+#[derive(Default)]
+pub struct Commands {
+    commands: Mutex<Vec<Command>>,
+}
+
+impl Commands {
+    pub fn new() -> Self {
+        Self {
+            commands: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Reserves an [Entity] in `world` that will be spawned with `components` when applied.
+    pub fn spawn<COMPONENTS: ComponentBundleTrait>(
+        &self,
+        world: &World,
+        components: COMPONENTS,
+    ) -> Entity {
+        let entity = world.reserve_entity();
+        self.spawn_reserved(entity, components);
+        entity
+    }
+
+    pub(crate) fn spawn_reserved<COMPONENTS: ComponentBundleTrait>(
+        &self,
+        entity: Entity,
+        components: COMPONENTS,
+    ) {
+        self.push(Command::Spawn(entity, box_components(components)));
+    }
+
+    pub fn despawn(&self, entity: Entity) {
+        self.push(Command::Despawn(entity));
+    }
+
+    pub fn add_components<COMPONENTS: ComponentBundleTrait>(
+        &self,
+        entity: Entity,
+        components: COMPONENTS,
+    ) {
+        self.push(Command::AddComponents(entity, box_components(components)));
+    }
+
+    pub fn remove_components<COMPONENTS: ComponentBundleTrait>(&self, entity: Entity) {
+        self.push(Command::RemoveComponents(entity, |world, entity| {
+            world.remove_components::<COMPONENTS>(entity).map(|_| ())
+        }));
+    }
+
+    pub fn len(&self) -> usize {
+        self.commands.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn push(&self, command: Command) {
+        self.commands.lock().unwrap().push(command);
+    }
+}
+
+fn box_components<COMPONENTS: ComponentBundleTrait>(components: COMPONENTS) -> BoxedComponents {
+    let mut boxed = Vec::new();
+    components.get_components_and_ids(|components_and_ids| {
+        boxed.extend(
+            components_and_ids
+                .iter_mut()
+                .map(|(component, component_id)| (component.take_boxed(), *component_id)),
+        );
+    });
+    boxed
+}
+
+impl World {
+    /// Applies all [Commands] in the order they were recorded and clears `commands`.
+    /// Every command is applied even if an earlier one fails. The first error is returned.
+    pub fn apply_commands(&mut self, commands: &mut Commands) -> Result<(), ECSError> {
+        let result = self.apply_commands_inner(commands);
+        self.apply_hook_commands();
+        result
+    }
+
+    /// Applies `commands` without applying the commands recorded by hooks.
+    pub(crate) fn apply_commands_inner(&mut self, commands: &mut Commands) -> Result<(), ECSError> {
+        self.flush_reserved_entities();
+
+        let mut result = Ok(());
+        for command in commands.commands.get_mut().unwrap().drain(..) {
+            let command_result = match command {
+                // Reserved `Entity`s start in the empty `Archetype` so spawning is just adding components.
+                Command::Spawn(entity, mut components)
+                | Command::AddComponents(entity, mut components) => {
+                    let mut components_and_ids: Vec<(&mut dyn AnyComponentTrait, ComponentId)> =
+                        components
+                            .iter_mut()
+                            .map(|(component, component_id)| {
+                                (
+                                    &mut **component as &mut dyn AnyComponentTrait,
+                                    *component_id,
+                                )
+                            })
+                            .collect();
+                    self.add_components_inner(entity, &mut components_and_ids)
+                }
+                Command::Despawn(entity) => self.despawn_inner(entity),
+                Command::RemoveComponents(entity, remove) => remove(self, entity),
+            };
+            if result.is_ok() {
+                result = command_result;
+            }
+        }
+        result
+    }
+}
@@ -0,0 +1,497 @@
+use std::{alloc::Layout, borrow::Cow, collections::HashMap, fmt, hash::Hash};
+
+use crate::{blob_vec::drop_ptr, *};
+
+/// Lets a component be written to and read back from bytes, for saving [World]s.
+/// Opt in with [ComponentRegistry::register_serialize].
+pub trait SerializeComponent: ComponentTrait {
+    fn serialize(&self, bytes: &mut Vec<u8>);
+    /// Reads a component written by `serialize` from the start of `bytes` and advances past it.
+    fn deserialize(bytes: &mut &[u8]) -> Option<Self>;
+}
+
+type CloneItemFn = unsafe fn(*const u8, &mut BlobVec) -> bool;
+type EqFn = unsafe fn(*const u8, *const u8) -> bool;
+type HashFn = unsafe fn(*const u8, &mut StateHasher);
+type DebugFn = unsafe fn(*const u8, &mut fmt::Formatter<'_>) -> fmt::Result;
+type SerializeFn = unsafe fn(*const u8, &mut Vec<u8>);
+type DeserializeFn = unsafe fn(&mut &[u8], *mut u8) -> bool;
+type ToFieldsFn = unsafe fn(*const u8, &mut SceneFields);
+type FromFieldsFn = unsafe fn(&SceneFields, *mut u8) -> bool;
+
+/// What a [World] knows about a type of component.
+/// Relation pairs share the [ComponentInfo] of their relation.
+#[derive(Clone)]
+pub struct ComponentInfo {
+    id: ComponentId,
+    name: Cow<'static, str>,
+    layout: Layout,
+    storage: StorageKind,
+    drop: Option<unsafe fn(*mut u8)>,
+    /// Clones a whole channel. Set for tags and if [ComponentTrait::clone_vec] returns `Some`,
+    /// and given to each [BlobVec] of this type for [BlobVec::try_clone].
+    clone: Option<CloneChannelFn>,
+    /// Clones one component onto the end of a channel. Set with `clone`.
+    clone_item: Option<CloneItemFn>,
+    eq: Option<EqFn>,
+    hash: Option<HashFn>,
+    debug: Option<DebugFn>,
+    serialize: Option<(SerializeFn, DeserializeFn)>,
+    scene: Option<(ToFieldsFn, FromFieldsFn)>,
+    sparse_storage: Option<fn() -> Box<dyn AnySparseStorage>>,
+}
+
+impl ComponentInfo {
+    pub(crate) fn new<T: ComponentTrait>() -> Self {
+        if T::STORAGE == StorageKind::Tag {
+            assert!(
+                std::mem::size_of::<T>() == 0 && !std::mem::needs_drop::<T>(),
+                "Tag components must be zero-sized and not implement `Drop`"
+            );
+        }
+        // Tags have no data, so they can always be cloned, compared, hashed and written.
+        let tag = T::STORAGE == StorageKind::Tag;
+        let cloneable = T::clone_vec(&[]).is_some();
+        Self {
+            id: T::component_id(),
+            name: Cow::Borrowed(std::any::type_name::<T>()),
+            layout: Layout::new::<T>(),
+            storage: T::STORAGE,
+            drop: std::mem::needs_drop::<T>().then_some(drop_ptr::<T> as unsafe fn(*mut u8)),
+            clone: match (tag, cloneable) {
+                (true, _) => Some(clone_tag_channel),
+                (false, true) => Some(clone_channel::<T>),
+                (false, false) => None,
+            },
+            clone_item: match (tag, cloneable) {
+                (true, _) => Some(clone_tag),
+                (false, true) => Some(clone_item::<T>),
+                (false, false) => None,
+            },
+            eq: tag.then_some(tag_eq as EqFn),
+            hash: tag.then_some(tag_hash as HashFn),
+            debug: None,
+            serialize: tag.then_some((
+                serialize_tag as SerializeFn,
+                deserialize_tag as DeserializeFn,
+            )),
+            scene: tag.then_some((tag_to_fields as ToFieldsFn, tag_from_fields as FromFieldsFn)),
+            sparse_storage: (T::STORAGE == StorageKind::SparseSet)
+                .then_some(new_sparse_storage::<T>),
+        }
+    }
+
+    pub fn id(&self) -> ComponentId {
+        self.id
+    }
+
+    /// The type's name from [std::any::type_name].
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    pub fn size(&self) -> usize {
+        self.layout.size()
+    }
+
+    pub fn align(&self) -> usize {
+        self.layout.align()
+    }
+
+    pub fn storage(&self) -> StorageKind {
+        self.storage
+    }
+
+    pub fn needs_drop(&self) -> bool {
+        self.drop.is_some()
+    }
+
+    pub fn is_cloneable(&self) -> bool {
+        self.clone.is_some()
+    }
+
+    /// Whether [World::diff] can compare and copy the component.
+    pub fn is_comparable(&self) -> bool {
+        self.eq.is_some() && self.clone_item.is_some()
+    }
+
+    /// Whether [World::state_hash] includes the component.
+    pub fn is_hashable(&self) -> bool {
+        self.hash.is_some()
+    }
+
+    pub fn is_serializable(&self) -> bool {
+        self.serialize.is_some()
+    }
+
+    pub fn is_scene_component(&self) -> bool {
+        self.scene.is_some()
+    }
+
+    pub(crate) fn drop_fn(&self) -> Option<unsafe fn(*mut u8)> {
+        self.drop
+    }
+
+    pub(crate) fn new_blob_vec(&self) -> BlobVec {
+        // SAFETY: `clone` is for this type.
+        unsafe { BlobVec::from_layout(self.layout, self.drop).with_clone(self.clone) }
+    }
+
+    /// Creates storage for a [StorageKind::SparseSet] type.
+    pub(crate) fn new_sparse_storage(&self) -> Option<Box<dyn AnySparseStorage>> {
+        self.sparse_storage.map(|new| new())
+    }
+
+    /// Returns something that formats the component at `component` with its [fmt::Debug]
+    /// implementation, or `None` if one wasn't registered with [ComponentRegistry::register_debug].
+    ///
+    /// # Safety
+    /// `component` must point to a component of this type that outlives the returned value.
+    pub unsafe fn debug(&self, component: *const u8) -> Option<impl fmt::Debug + '_> {
+        Some(DebugComponent {
+            component,
+            debug: self.debug?,
+        })
+    }
+
+    /// Clones the component at `component` onto the end of `blob_vec`.
+    /// Returns `false` if the type isn't cloneable or [ComponentTrait::clone_vec] returned `None`.
+    ///
+    /// # Safety
+    /// `component` must point to a component of this type and `blob_vec` must store this type.
+    pub(crate) unsafe fn clone_item(&self, component: *const u8, blob_vec: &mut BlobVec) -> bool {
+        let Some(clone_item) = self.clone_item else {
+            return false;
+        };
+        clone_item(component, blob_vec)
+    }
+
+    /// Compares two components with the [PartialEq] implementation registered with
+    /// [ComponentRegistry::register_eq], or returns `None` if there isn't one.
+    ///
+    /// # Safety
+    /// `a` and `b` must point to components of this type.
+    pub unsafe fn eq(&self, a: *const u8, b: *const u8) -> Option<bool> {
+        Some(self.eq?(a, b))
+    }
+
+    /// Feeds the component at `component` to `hasher`.
+    /// Returns `false` if it isn't registered with [ComponentRegistry::register_hash].
+    ///
+    /// # Safety
+    /// `component` must point to a component of this type.
+    pub(crate) unsafe fn hash(&self, component: *const u8, hasher: &mut StateHasher) -> bool {
+        let Some(hash) = self.hash else {
+            return false;
+        };
+        hash(component, hasher);
+        true
+    }
+
+    /// Writes the component at `component` to `bytes`.
+    /// Returns `false` if it isn't registered with [ComponentRegistry::register_serialize].
+    ///
+    /// # Safety
+    /// `component` must point to a component of this type.
+    pub unsafe fn serialize(&self, component: *const u8, bytes: &mut Vec<u8>) -> bool {
+        let Some((serialize, _)) = self.serialize else {
+            return false;
+        };
+        serialize(component, bytes);
+        true
+    }
+
+    /// Reads a component from the start of `bytes` into the uninitialized `component`.
+    /// Returns `false` if it couldn't be read or the type isn't serializable.
+    ///
+    /// # Safety
+    /// `component` must be valid for writes of this type.
+    pub unsafe fn deserialize(&self, bytes: &mut &[u8], component: *mut u8) -> bool {
+        self.serialize
+            .is_some_and(|(_, deserialize)| deserialize(bytes, component))
+    }
+
+    /// Writes the fields of the component at `component` to `fields`.
+    /// Returns `false` if it isn't registered with [ComponentRegistry::register_scene].
+    ///
+    /// # Safety
+    /// `component` must point to a component of this type.
+    pub unsafe fn to_fields(&self, component: *const u8, fields: &mut SceneFields) -> bool {
+        let Some((to_fields, _)) = self.scene else {
+            return false;
+        };
+        to_fields(component, fields);
+        true
+    }
+
+    /// Creates a component from `fields` in the uninitialized `component`.
+    /// Returns `false` if the fields are invalid or the type isn't a scene component.
+    ///
+    /// # Safety
+    /// `component` must be valid for writes of this type.
+    pub unsafe fn from_fields(&self, fields: &SceneFields, component: *mut u8) -> bool {
+        self.scene
+            .is_some_and(|(_, from_fields)| from_fields(fields, component))
+    }
+}
+
+impl fmt::Debug for ComponentInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ComponentInfo")
+            .field("name", &self.name)
+            .field("size", &self.size())
+            .field("align", &self.align())
+            .field("storage", &self.storage)
+            .finish_non_exhaustive()
+    }
+}
+
+struct DebugComponent {
+    component: *const u8,
+    debug: DebugFn,
+}
+
+impl fmt::Debug for DebugComponent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // SAFETY: Checked by `ComponentInfo::debug`.
+        unsafe { (self.debug)(self.component, f) }
+    }
+}
+
+unsafe fn clone_channel<T: ComponentTrait>(channel: &BlobVec) -> Option<BlobVec> {
+    let cloned = T::clone_vec(channel.as_slice::<T>())?;
+    assert_eq!(
+        cloned.len(),
+        channel.len(),
+        "`ComponentTrait::clone_vec` must return one clone of each component"
+    );
+    Some(BlobVec::from_vec(cloned).with_clone(Some(clone_channel::<T>)))
+}
+
+unsafe fn clone_tag_channel(channel: &BlobVec) -> Option<BlobVec> {
+    let mut cloned = channel.new_same_type();
+    for _ in 0..channel.len() {
+        cloned.push_with(|_| true);
+    }
+    Some(cloned)
+}
+
+unsafe fn clone_item<T: ComponentTrait>(component: *const u8, blob_vec: &mut BlobVec) -> bool {
+    let component = std::slice::from_ref(&*component.cast::<T>());
+    let Some(cloned) = T::clone_vec(component) else {
+        return false;
+    };
+    assert_eq!(
+        cloned.len(),
+        1,
+        "`ComponentTrait::clone_vec` must return one clone of each component"
+    );
+    for component in cloned {
+        Some(component).push_into(blob_vec);
+    }
+    true
+}
+
+unsafe fn clone_tag(_: *const u8, blob_vec: &mut BlobVec) -> bool {
+    blob_vec.push_with(|_| true)
+}
+
+unsafe fn eq_ptr<T: PartialEq>(a: *const u8, b: *const u8) -> bool {
+    *a.cast::<T>() == *b.cast::<T>()
+}
+
+unsafe fn tag_eq(_: *const u8, _: *const u8) -> bool {
+    true
+}
+
+unsafe fn hash_ptr<T: Hash>(component: *const u8, hasher: &mut StateHasher) {
+    (*component.cast::<T>()).hash(hasher)
+}
+
+unsafe fn tag_hash(_: *const u8, _: &mut StateHasher) {}
+
+unsafe fn debug_ptr<T: fmt::Debug>(ptr: *const u8, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    (*ptr.cast::<T>()).fmt(f)
+}
+
+unsafe fn serialize_ptr<T: SerializeComponent>(ptr: *const u8, bytes: &mut Vec<u8>) {
+    (*ptr.cast::<T>()).serialize(bytes)
+}
+
+unsafe fn deserialize_ptr<T: SerializeComponent>(bytes: &mut &[u8], ptr: *mut u8) -> bool {
+    T::deserialize(bytes)
+        .map(|c| ptr.cast::<T>().write(c))
+        .is_some()
+}
+
+unsafe fn serialize_tag(_: *const u8, _: &mut Vec<u8>) {}
+
+unsafe fn deserialize_tag(_: &mut &[u8], _: *mut u8) -> bool {
+    true
+}
+
+unsafe fn to_fields_ptr<T: SceneComponent>(ptr: *const u8, fields: &mut SceneFields) {
+    (*ptr.cast::<T>()).to_fields(fields)
+}
+
+unsafe fn from_fields_ptr<T: SceneComponent>(fields: &SceneFields, ptr: *mut u8) -> bool {
+    T::from_fields(fields)
+        .map(|c| ptr.cast::<T>().write(c))
+        .is_some()
+}
+
+unsafe fn tag_to_fields(_: *const u8, _: &mut SceneFields) {}
+
+unsafe fn tag_from_fields(_: &SceneFields, _: *mut u8) -> bool {
+    true
+}
+
+fn new_sparse_storage<T: ComponentTrait>() -> Box<dyn AnySparseStorage> {
+    Box::new(SparseStorage::<T>::new())
+}
+
+/// Every type of component a [World] has stored, in the order they were first stored.
+#[derive(Default)]
+pub struct ComponentRegistry {
+    infos: Vec<ComponentInfo>,
+    indices: HashMap<ComponentId, usize>,
+}
+
+impl ComponentRegistry {
+    /// Gets the [ComponentInfo] for `component_id`, or the relation's if it's a pair.
+    pub fn get(&self, component_id: ComponentId) -> Option<&ComponentInfo> {
+        let component_id = ComponentId {
+            target: None,
+            ..component_id
+        };
+        Some(&self.infos[*self.indices.get(&component_id)?])
+    }
+
+    /// Gets the first [ComponentInfo] registered with `name`.
+    pub fn get_by_name(&self, name: &str) -> Option<&ComponentInfo> {
+        self.infos.iter().find(|info| info.name == name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &ComponentInfo> {
+        self.infos.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.infos.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.infos.is_empty()
+    }
+
+    /// Gets the [ComponentInfo] for `component_id`, creating it with `info` if it's the first time.
+    pub(crate) fn get_or_register(
+        &mut self,
+        component_id: ComponentId,
+        info: impl FnOnce() -> ComponentInfo,
+    ) -> &ComponentInfo {
+        let component_id = ComponentId {
+            target: None,
+            ..component_id
+        };
+        let index = *self.indices.entry(component_id).or_insert_with(|| {
+            self.infos.push(info());
+            self.infos.len() - 1
+        });
+        &self.infos[index]
+    }
+
+    /// Registers `T` before it's first stored, so its [ComponentId] can be used
+    /// with [World::spawn_dynamic].
+    pub fn register<T: ComponentTrait>(&mut self) -> ComponentId {
+        self.register_mut::<T>().id
+    }
+
+    /// Declares a type of component at runtime, for scripting and modding.
+    /// It's stored in [Archetype] channels of `layout`, and `drop` is called on each
+    /// component before it's freed.
+    /// Spawn it with [World::spawn_dynamic].
+    pub fn register_dynamic(
+        &mut self,
+        name: impl Into<Cow<'static, str>>,
+        layout: Layout,
+        drop: Option<unsafe fn(*mut u8)>,
+    ) -> ComponentId {
+        let id = ComponentId {
+            type_id: ComponentTypeId::Dynamic(self.infos.len()),
+            target: None,
+        };
+        self.indices.insert(id, self.infos.len());
+        self.infos.push(ComponentInfo {
+            id,
+            name: name.into(),
+            layout,
+            storage: StorageKind::Table,
+            drop,
+            clone: None,
+            clone_item: None,
+            eq: None,
+            hash: None,
+            debug: None,
+            serialize: None,
+            scene: None,
+            sparse_storage: None,
+        });
+        id
+    }
+
+    fn register_mut<T: ComponentTrait>(&mut self) -> &mut ComponentInfo {
+        let index = *self.indices.entry(T::component_id()).or_insert_with(|| {
+            self.infos.push(ComponentInfo::new::<T>());
+            self.infos.len() - 1
+        });
+        &mut self.infos[index]
+    }
+
+    /// Lets `T` be compared with [ComponentInfo::eq] so [World::diff] can find changes to it.
+    /// Panics if `T` isn't cloneable, because [WorldDelta]s hold copies of changed components.
+    pub fn register_eq<T: ComponentTrait + PartialEq>(&mut self) {
+        let info = self.register_mut::<T>();
+        assert!(
+            info.clone_item.is_some(),
+            "Only components that `ComponentTrait::clone_vec` can clone can be compared"
+        );
+        info.eq = Some(eq_ptr::<T>);
+    }
+
+    /// Includes `T` in [World::state_hash].
+    pub fn register_hash<T: ComponentTrait + Hash>(&mut self) {
+        self.register_mut::<T>().hash = Some(hash_ptr::<T>);
+    }
+
+    /// Lets `T` be formatted with [ComponentInfo::debug].
+    pub fn register_debug<T: ComponentTrait + fmt::Debug>(&mut self) {
+        self.register_mut::<T>().debug = Some(debug_ptr::<T>);
+    }
+
+    /// Lets `T` be written with [ComponentInfo::serialize] and read with [ComponentInfo::deserialize].
+    pub fn register_serialize<T: SerializeComponent>(&mut self) {
+        self.register_mut::<T>().serialize = Some((serialize_ptr::<T>, deserialize_ptr::<T>));
+    }
+
+    /// Lets `T` be saved and loaded in scenes with [World::save_scene] and [World::load_scene].
+    pub fn register_scene<T: SceneComponent>(&mut self) {
+        self.register_mut::<T>().scene = Some((to_fields_ptr::<T>, from_fields_ptr::<T>));
+    }
+}
+
+impl World {
+    /// Info about every type of component this [World] has stored.
+    pub fn components(&self) -> &ComponentRegistry {
+        &self.components
+    }
+
+    /// Used to opt component types into the vtables in their [ComponentInfo].
+    pub fn components_mut(&mut self) -> &mut ComponentRegistry {
+        &mut self.components
+    }
+}
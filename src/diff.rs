@@ -0,0 +1,297 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::RwLockReadGuard,
+};
+
+use crate::*;
+
+/// The changes to one type of component between two [World]s.
+struct ComponentDelta {
+    component_id: ComponentId,
+    info: ComponentInfo,
+    added: Vec<Entity>,
+    /// The added components, in the same order as `added`.
+    added_values: BlobVec,
+    changed: Vec<Entity>,
+    /// The new values of the changed components, in the same order as `changed`.
+    changed_values: BlobVec,
+    removed: Vec<Entity>,
+}
+
+impl ComponentDelta {
+    fn new(component_id: ComponentId, info: ComponentInfo) -> Self {
+        Self {
+            component_id,
+            added: Vec::new(),
+            added_values: info.new_blob_vec(),
+            changed: Vec::new(),
+            changed_values: info.new_blob_vec(),
+            removed: Vec::new(),
+            info,
+        }
+    }
+}
+
+/// The changes that turn one [World] into another. Made by [World::diff] and
+/// reproduced by [World::apply_delta].
+///
+/// Only components registered with [ComponentRegistry::register_eq] and tags are compared.
+/// Changes are left out for components whose [ComponentTrait::clone_vec] returns `None`.
+/// The components of spawned [Entity]s are added, and despawned [Entity]s' components aren't
+/// listed as removed.
+#[derive(Default)]
+pub struct WorldDelta {
+    spawned: Vec<Entity>,
+    despawned: Vec<Entity>,
+    /// Sorted by [ComponentId].
+    components: Vec<ComponentDelta>,
+}
+
+impl WorldDelta {
+    pub fn spawned(&self) -> &[Entity] {
+        &self.spawned
+    }
+
+    pub fn despawned(&self) -> &[Entity] {
+        &self.despawned
+    }
+
+    /// Each component added to an [Entity], including those of spawned [Entity]s.
+    pub fn added(&self) -> impl Iterator<Item = (Entity, ComponentId)> + '_ {
+        self.components
+            .iter()
+            .flat_map(|c| c.added.iter().map(|entity| (*entity, c.component_id)))
+    }
+
+    /// Each component that's not equal to what it was.
+    pub fn changed(&self) -> impl Iterator<Item = (Entity, ComponentId)> + '_ {
+        self.components
+            .iter()
+            .flat_map(|c| c.changed.iter().map(|entity| (*entity, c.component_id)))
+    }
+
+    /// Each component removed from an [Entity] that wasn't despawned.
+    pub fn removed(&self) -> impl Iterator<Item = (Entity, ComponentId)> + '_ {
+        self.components
+            .iter()
+            .flat_map(|c| c.removed.iter().map(|entity| (*entity, c.component_id)))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.spawned.is_empty() && self.despawned.is_empty() && self.components.is_empty()
+    }
+}
+
+/// The [ComponentInfo] that can compare `component_id`, from either [World].
+fn comparable_info<'a>(
+    a: &'a World,
+    b: &'a World,
+    component_id: ComponentId,
+) -> Option<&'a ComponentInfo> {
+    [b, a]
+        .into_iter()
+        .filter_map(|world| world.components.get(component_id))
+        .find(|info| info.is_comparable())
+}
+
+/// Read access to the comparable components of one [World] while it's diffed.
+struct DiffView<'a> {
+    /// The comparable channels of each [Archetype].
+    channels: Vec<Vec<(ComponentId, RwLockReadGuard<'a, BlobVec>)>>,
+    sparse_storages: Vec<(ComponentId, &'a dyn AnySparseStorage)>,
+    /// The [Archetype] and row of each [Entity].
+    entities: HashMap<Entity, (usize, usize)>,
+}
+
+impl<'a> DiffView<'a> {
+    fn new(world: &'a World, other: &'a World) -> Self {
+        let comparable =
+            |component_id: &ComponentId| comparable_info(world, other, *component_id).is_some();
+        let channels = world
+            .archetypes
+            .iter()
+            .map(|archetype| {
+                archetype
+                    .channels
+                    .iter()
+                    .filter(|(component_id, _)| comparable(component_id))
+                    .map(|(component_id, channel)| {
+                        let channel = channel
+                            .try_read()
+                            .expect("Components can't be mutably borrowed while diffing");
+                        (*component_id, channel)
+                    })
+                    .collect()
+            })
+            .collect();
+        let mut sparse_storages: Vec<(ComponentId, &dyn AnySparseStorage)> = world
+            .sparse_storages
+            .iter()
+            .filter(|(component_id, _)| comparable(component_id))
+            .map(|(component_id, storage)| (*component_id, &**storage))
+            .collect();
+        sparse_storages.sort_unstable_by_key(|(component_id, _)| *component_id);
+        let entities = world
+            .archetypes
+            .iter()
+            .enumerate()
+            .flat_map(|(archetype_index, archetype)| {
+                archetype
+                    .entity_indices
+                    .iter()
+                    .enumerate()
+                    .map(move |(row, index)| {
+                        let entity = world.entity_manager.get_entity(*index);
+                        (entity, (archetype_index, row))
+                    })
+            })
+            .collect();
+        Self {
+            channels,
+            sparse_storages,
+            entities,
+        }
+    }
+
+    /// The comparable components of `entity`, sorted by [ComponentId].
+    fn components(&self, entity: Entity) -> Vec<(ComponentId, *const u8)> {
+        let Some(&(archetype_index, row)) = self.entities.get(&entity) else {
+            return Vec::new();
+        };
+        let mut components: Vec<(ComponentId, *const u8)> = self.channels[archetype_index]
+            .iter()
+            .map(|(component_id, channel)| (*component_id, channel.get(row)))
+            .collect();
+        for (component_id, storage) in &self.sparse_storages {
+            if let Ok(component) = storage.try_component_ptr(entity.index) {
+                components.push((*component_id, component));
+            }
+        }
+        components.sort_unstable_by_key(|(component_id, _)| *component_id);
+        components
+    }
+}
+
+impl World {
+    /// Finds the changes that turn this [World] into `other`, matching [Entity]s by id.
+    /// Useful for [World]s that share history, like one loaded from the other's snapshot.
+    /// Dynamic components must have been registered in the same order in both.
+    ///
+    /// Panics if a component is mutably borrowed by a query.
+    pub fn diff(&self, other: &World) -> WorldDelta {
+        let old = DiffView::new(self, other);
+        let new = DiffView::new(other, self);
+        let mut delta = WorldDelta::default();
+        let mut components: BTreeMap<ComponentId, ComponentDelta> = BTreeMap::new();
+        let component_delta = |component_id: ComponentId| {
+            let info = comparable_info(self, other, component_id).unwrap();
+            ComponentDelta::new(component_id, info.clone())
+        };
+
+        let mut new_entities: Vec<Entity> = new.entities.keys().copied().collect();
+        new_entities.sort_unstable();
+        for entity in new_entities {
+            if !old.entities.contains_key(&entity) {
+                delta.spawned.push(entity);
+            }
+            let old_components = old.components(entity);
+            let new_components = new.components(entity);
+            for (component_id, component) in &new_components {
+                let c = components
+                    .entry(*component_id)
+                    .or_insert_with(|| component_delta(*component_id));
+                // SAFETY: Both pointers are to components of `c.info`'s type, and the values
+                // are channels of that type.
+                unsafe {
+                    match old_components.binary_search_by_key(component_id, |c| c.0) {
+                        Ok(i) => {
+                            if !c.info.eq(old_components[i].1, *component).unwrap()
+                                && c.info.clone_item(*component, &mut c.changed_values)
+                            {
+                                c.changed.push(entity);
+                            }
+                        }
+                        Err(_) => {
+                            if c.info.clone_item(*component, &mut c.added_values) {
+                                c.added.push(entity);
+                            }
+                        }
+                    }
+                }
+            }
+            for (component_id, _) in &old_components {
+                if new_components
+                    .binary_search_by_key(component_id, |c| c.0)
+                    .is_err()
+                {
+                    components
+                        .entry(*component_id)
+                        .or_insert_with(|| component_delta(*component_id))
+                        .removed
+                        .push(entity);
+                }
+            }
+        }
+
+        delta.despawned = old
+            .entities
+            .keys()
+            .filter(|entity| !new.entities.contains_key(entity))
+            .copied()
+            .collect();
+        delta.despawned.sort_unstable();
+        delta.components = components
+            .into_values()
+            .filter(|c| !(c.added.is_empty() && c.changed.is_empty() && c.removed.is_empty()))
+            .collect();
+        delta
+    }
+
+    /// Applies the changes from [World::diff], so a [World] equal to the one it was called on
+    /// becomes equal to the `other` [World].
+    /// Errors if an [Entity] or component the `delta` changes is missing.
+    pub fn apply_delta(&mut self, delta: &WorldDelta) -> Result<(), ECSError> {
+        self.flush_reserved_entities();
+        for entity in &delta.despawned {
+            self.entity_manager.get_entity_location(*entity)?;
+        }
+        self.despawn_batch(&delta.despawned);
+        for entity in &delta.spawned {
+            self.spawn_inner(&mut [], Some(*entity))?;
+        }
+
+        let mut removed: BTreeMap<Entity, Vec<ComponentId>> = BTreeMap::new();
+        let mut inserted: BTreeMap<Entity, Vec<(ComponentId, BlobVec)>> = BTreeMap::new();
+        for c in &delta.components {
+            let info = self
+                .components
+                .get_or_register(c.component_id, || c.info.clone());
+            for entity in &c.removed {
+                removed.entry(*entity).or_default().push(c.component_id);
+            }
+            for (entities, values) in [(&c.added, &c.added_values), (&c.changed, &c.changed_values)]
+            {
+                for (i, entity) in entities.iter().enumerate() {
+                    let mut value = info.new_blob_vec();
+                    // SAFETY: `values` and `value` store components of `info`'s type.
+                    if !unsafe { info.clone_item(values.get(i), &mut value) } {
+                        continue;
+                    }
+                    inserted
+                        .entry(*entity)
+                        .or_default()
+                        .push((c.component_id, value));
+                }
+            }
+        }
+        for (entity, component_ids) in removed {
+            self.remove_components_by_id(entity, &component_ids)?;
+        }
+        for (entity, components) in inserted {
+            // SAFETY: Each `BlobVec` holds one component of its `ComponentId`'s type.
+            unsafe { self.add_blob_vec_components(entity, components)? };
+        }
+        self.apply_hook_commands();
+        Ok(())
+    }
+}
@@ -0,0 +1,285 @@
+use crate::*;
+
+/// A component of a type declared with [ComponentRegistry::register_dynamic], passed as bytes.
+struct DynamicComponent {
+    bytes: Option<Box<[u8]>>,
+    size: usize,
+}
+
+impl AnyComponentTrait for DynamicComponent {
+    fn component_info(&self) -> ComponentInfo {
+        unreachable!("Dynamic components are registered before they're spawned")
+    }
+    fn push_into(&mut self, channel: &mut BlobVec) {
+        let bytes = self.bytes.take().unwrap();
+        // SAFETY: `World::spawn_dynamic` checked the size and its caller ensures the bytes are valid.
+        unsafe { channel.push(bytes.as_ptr()) }
+    }
+    fn replace_in(&mut self, channel: &mut BlobVec, index: usize) {
+        let bytes = self.bytes.take().unwrap();
+        // SAFETY: Same as `push_into`.
+        unsafe { channel.replace(index, bytes.as_ptr()) }
+    }
+    unsafe fn move_from(&mut self, ptr: *const u8) {
+        self.bytes = Some(std::slice::from_raw_parts(ptr, self.size).into());
+    }
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+    fn take_boxed(&mut self) -> Box<dyn AnyComponentTrait + Send> {
+        Box::new(DynamicComponent {
+            bytes: self.bytes.take(),
+            size: self.size,
+        })
+    }
+    fn is_some(&self) -> bool {
+        self.bytes.is_some()
+    }
+    /// [StorageKind::SparseSet] components can't be spawned from bytes.
+    fn storage_kind(&self) -> StorageKind {
+        StorageKind::Table
+    }
+    fn new_sparse_storage(&self) -> Box<dyn AnySparseStorage> {
+        unreachable!("Dynamic components are stored in `Archetype`s")
+    }
+}
+
+/// Drops a table component as it's removed, for removing components by [ComponentId].
+struct DroppedComponent {
+    drop: Option<unsafe fn(*mut u8)>,
+}
+
+impl AnyComponentTrait for DroppedComponent {
+    fn component_info(&self) -> ComponentInfo {
+        unreachable!("Components are registered before they're removed")
+    }
+    fn push_into(&mut self, _channel: &mut BlobVec) {
+        unreachable!("`DroppedComponent`s are only removed")
+    }
+    fn replace_in(&mut self, _channel: &mut BlobVec, _index: usize) {
+        unreachable!("`DroppedComponent`s are only removed")
+    }
+    unsafe fn move_from(&mut self, ptr: *const u8) {
+        if let Some(drop) = self.drop {
+            drop(ptr.cast_mut());
+        }
+    }
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+    fn take_boxed(&mut self) -> Box<dyn AnyComponentTrait + Send> {
+        unreachable!("`DroppedComponent`s are only removed")
+    }
+    fn is_some(&self) -> bool {
+        false
+    }
+    fn storage_kind(&self) -> StorageKind {
+        StorageKind::Table
+    }
+    fn new_sparse_storage(&self) -> Box<dyn AnySparseStorage> {
+        unreachable!("`DroppedComponent`s are only removed")
+    }
+}
+
+impl World {
+    /// Spawns an [Entity] with components given as bytes, usually of types declared with
+    /// [ComponentRegistry::register_dynamic].
+    /// Rust types must have been stored before or registered with [ComponentRegistry::register].
+    ///
+    /// # Errors
+    /// - [ECSError::NoMatchingComponent] if a [ComponentId] isn't registered.
+    /// - [ECSError::ComponentLayoutMismatch] if its bytes aren't the size of the component.
+    /// - [ECSError::SparseComponentFromBytes] if it's a [StorageKind::SparseSet] component.
+    ///   Spawn the [Entity] with its other components and add the sparse ones as Rust types.
+    ///
+    /// # Safety
+    /// Each component's bytes must be a valid value of its type.
+    pub unsafe fn spawn_dynamic(
+        &mut self,
+        components: &[(ComponentId, &[u8])],
+    ) -> Result<Entity, ECSError> {
+        let mut dynamic_components = Vec::with_capacity(components.len());
+        for (component_id, bytes) in components {
+            let info = self
+                .components
+                .get(*component_id)
+                .ok_or(ECSError::NoMatchingComponent)?;
+            if info.storage() == StorageKind::SparseSet {
+                return Err(ECSError::SparseComponentFromBytes);
+            }
+            if bytes.len() != info.size() {
+                return Err(ECSError::ComponentLayoutMismatch);
+            }
+            dynamic_components.push(DynamicComponent {
+                bytes: Some((*bytes).into()),
+                size: bytes.len(),
+            });
+        }
+        let mut components_and_ids: Vec<(&mut dyn AnyComponentTrait, ComponentId)> =
+            dynamic_components
+                .iter_mut()
+                .zip(components)
+                .map(|(component, (component_id, _))| {
+                    (component as &mut dyn AnyComponentTrait, *component_id)
+                })
+                .collect();
+        let result = self.spawn_inner(&mut components_and_ids, None);
+        self.apply_hook_commands();
+        result
+    }
+
+    /// Adds components that were each written into a [BlobVec] of their type, replacing
+    /// existing components. Unlike [World::spawn_dynamic] this handles sparse components.
+    ///
+    /// # Safety
+    /// Each [BlobVec] must store components of its [ComponentId]'s type and hold exactly one.
+    pub(crate) unsafe fn add_blob_vec_components(
+        &mut self,
+        entity: Entity,
+        components: Vec<(ComponentId, BlobVec)>,
+    ) -> Result<(), ECSError> {
+        self.flush_reserved_entities();
+        self.entity_manager.get_entity_location(entity)?;
+        let change_tick = *self.change_tick.get_mut();
+        let mut dynamic_components = Vec::with_capacity(components.len());
+        let mut component_ids = Vec::with_capacity(components.len());
+        for (component_id, mut blob_vec) in components {
+            debug_assert_eq!(blob_vec.len(), 1);
+            let info = self
+                .components
+                .get(component_id)
+                .ok_or(ECSError::NoMatchingComponent)?;
+            let Some(new_storage) = info.new_sparse_storage() else {
+                let size = info.size();
+                let mut bytes = None;
+                blob_vec.swap_remove_with(0, |component| {
+                    bytes = Some(std::slice::from_raw_parts(component, size).into());
+                });
+                dynamic_components.push(DynamicComponent { bytes, size });
+                component_ids.push(component_id);
+                continue;
+            };
+
+            let storage = self
+                .sparse_storages
+                .entry(component_id)
+                .or_insert(new_storage);
+            let replaced = storage.contains(entity.index);
+            if replaced {
+                self.run_hooks(&[HookKind::Replace], entity, component_id);
+            }
+            let storage = self.sparse_storages.get_mut(&component_id).unwrap();
+            blob_vec.swap_remove_with(0, |component| {
+                storage.insert_ptr(entity.index, component, change_tick)
+            });
+            if replaced {
+                self.run_hooks(&[HookKind::Insert], entity, component_id);
+            } else {
+                self.run_hooks(&[HookKind::Add, HookKind::Insert], entity, component_id);
+            }
+        }
+        let mut components_and_ids: Vec<(&mut dyn AnyComponentTrait, ComponentId)> =
+            dynamic_components
+                .iter_mut()
+                .zip(component_ids)
+                .map(|(component, component_id)| {
+                    (component as &mut dyn AnyComponentTrait, component_id)
+                })
+                .collect();
+        let result = self.add_components_inner(entity, &mut components_and_ids);
+        self.apply_hook_commands();
+        result
+    }
+
+    /// Removes and drops an [Entity]'s components of any type, running hooks.
+    /// Errors with [ECSError::NoMatchingComponent] if the [Entity] is missing one.
+    pub(crate) fn remove_components_by_id(
+        &mut self,
+        entity: Entity,
+        component_ids: &[ComponentId],
+    ) -> Result<(), ECSError> {
+        self.flush_reserved_entities();
+        self.entity_manager.get_entity_location(entity)?;
+        let (sparse, table): (Vec<ComponentId>, Vec<ComponentId>) =
+            component_ids.iter().partition(|component_id| {
+                self.sparse_storages
+                    .get(component_id)
+                    .is_some_and(|storage| storage.contains(entity.index))
+            });
+        let mut dropped: Vec<DroppedComponent> = table
+            .iter()
+            .map(|component_id| DroppedComponent {
+                drop: self
+                    .components
+                    .get(*component_id)
+                    .and_then(|info| info.drop_fn()),
+            })
+            .collect();
+        let mut components_and_ids: Vec<(&mut dyn AnyComponentTrait, ComponentId)> = dropped
+            .iter_mut()
+            .zip(table)
+            .map(|(component, component_id)| {
+                (component as &mut dyn AnyComponentTrait, component_id)
+            })
+            .collect();
+        let result = self.remove_components_inner(entity, &mut components_and_ids);
+        if result.is_ok() {
+            for component_id in sparse {
+                self.run_hooks(&[HookKind::Replace, HookKind::Remove], entity, component_id);
+                self.sparse_storages
+                    .get_mut(&component_id)
+                    .unwrap()
+                    .remove(entity.index);
+                self.record_removal(entity, component_id);
+            }
+        }
+        self.apply_hook_commands();
+        result
+    }
+
+    /// Returns a pointer to an [Entity]'s component of any type.
+    /// It's valid until the [World] is next mutated and must not be read while
+    /// the component is mutably borrowed by a query.
+    pub fn get_by_id(
+        &self,
+        entity: Entity,
+        component_id: ComponentId,
+    ) -> Result<*const u8, ECSError> {
+        let entity_location = self.get_flushed_entity_location(entity)?;
+        if let Some(storage) = self.sparse_storages.get(&component_id) {
+            return storage.try_component_ptr(entity.index);
+        }
+        let (channel, _) = self.archetypes[entity_location.storage_index]
+            .channel(component_id)
+            .ok_or(ECSError::NoMatchingComponent)?;
+        let channel = channel
+            .try_read()
+            .map_err(|_| ECSError::ComponentAlreadyBorrowed)?;
+        Ok(channel.get(entity_location.index_within_storage))
+    }
+
+    /// Returns a mutable pointer to an [Entity]'s component of any type and marks it as changed.
+    /// It's valid until the [World] is next mutated.
+    pub fn get_mut_by_id(
+        &mut self,
+        entity: Entity,
+        component_id: ComponentId,
+    ) -> Result<*mut u8, ECSError> {
+        self.flush_reserved_entities();
+        let entity_location = self.entity_manager.get_entity_location(entity)?;
+        let change_tick = *self.change_tick.get_mut();
+        if let Some(storage) = self.sparse_storages.get_mut(&component_id) {
+            storage.mark_changed(entity.index, change_tick);
+            return storage
+                .component_ptr(entity.index)
+                .ok_or(ECSError::NoMatchingComponent);
+        }
+        let (channel, ticks) = self.archetypes[entity_location.storage_index]
+            .channel_mut(component_id)
+            .ok_or(ECSError::NoMatchingComponent)?;
+        if let Some(ticks) = ticks {
+            *ticks.changed[entity_location.index_within_storage].get_mut() = change_tick;
+        }
+        Ok(channel.get_mut(entity_location.index_within_storage))
+    }
+}
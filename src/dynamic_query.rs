@@ -0,0 +1,235 @@
+use crate::{
+    archetype_lookup::{Filter, FilterType, MAX_FILTER_COUNT},
+    entity_manager::EntityManager,
+    *,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Term {
+    With,
+    Without,
+    Optional,
+    Read,
+    Write,
+}
+
+/// A query built at runtime from [ComponentId]s, for editors, scripting and replication.
+/// Each [DynamicQuery::read], [DynamicQuery::write] and [DynamicQuery::optional] adds a
+/// pointer to every [DynamicRow], in the order they were added.
+#[derive(Clone, Default)]
+pub struct DynamicQuery {
+    terms: Vec<(ComponentId, Term)>,
+}
+
+impl DynamicQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn term(mut self, component_id: ComponentId, term: Term) -> Self {
+        assert!(
+            self.terms.len() < MAX_FILTER_COUNT,
+            "A `DynamicQuery` can't have more than {} terms",
+            MAX_FILTER_COUNT
+        );
+        self.terms.push((component_id, term));
+        self
+    }
+
+    /// Matches [Entity]s with the component without fetching it.
+    pub fn with(self, component_id: ComponentId) -> Self {
+        self.term(component_id, Term::With)
+    }
+
+    /// Matches [Entity]s without the component.
+    pub fn without(self, component_id: ComponentId) -> Self {
+        self.term(component_id, Term::Without)
+    }
+
+    /// Fetches the component if the [Entity] has it.
+    pub fn optional(self, component_id: ComponentId) -> Self {
+        self.term(component_id, Term::Optional)
+    }
+
+    /// Matches [Entity]s with the component and fetches it.
+    pub fn read(self, component_id: ComponentId) -> Self {
+        self.term(component_id, Term::Read)
+    }
+
+    /// Matches [Entity]s with the component, fetches it mutably and marks it as changed.
+    /// Tags have no change ticks, so they're never marked.
+    pub fn write(self, component_id: ComponentId) -> Self {
+        self.term(component_id, Term::Write)
+    }
+
+    /// Iterates every [Entity] that matches the query.
+    pub fn iter<'a>(&self, world: &'a mut World) -> DynamicQueryIter<'a> {
+        world.flush_reserved_entities();
+        let change_tick = *world.change_tick.get_mut();
+        let World {
+            archetypes,
+            archetype_lookup,
+            entity_manager,
+            sparse_storages,
+            components,
+            ..
+        } = world;
+
+        // Sparse components aren't in `Archetype`s so they're checked for each row instead.
+        let sparse: Vec<bool> = self
+            .terms
+            .iter()
+            .map(|(component_id, _)| {
+                components
+                    .get(*component_id)
+                    .is_some_and(|info| info.storage() == StorageKind::SparseSet)
+            })
+            .collect();
+        let filters: Vec<Filter> = self
+            .terms
+            .iter()
+            .zip(sparse.iter())
+            .map(|((component_id, term), sparse)| Filter {
+                component_id: *component_id,
+                filter_type: match term {
+                    _ if *sparse => FilterType::Optional,
+                    Term::With | Term::Read | Term::Write => FilterType::With,
+                    Term::Without => FilterType::Without,
+                    Term::Optional => FilterType::Optional,
+                },
+            })
+            .collect();
+        let matching_archetypes = archetype_lookup
+            .matching_archetype_iter::<MAX_FILTER_COUNT>(&filters)
+            .collect();
+
+        DynamicQueryIter {
+            terms: self.terms.clone(),
+            sparse,
+            matching_archetypes,
+            archetype: 0,
+            row: 0,
+            archetypes,
+            entity_manager,
+            sparse_storages,
+            change_tick,
+        }
+    }
+}
+
+/// An [Entity] matched by a [DynamicQuery] and pointers to its fetched components.
+/// The pointers are valid until the [World] is next mutated.
+pub struct DynamicRow {
+    pub entity: Entity,
+    components: [Option<*mut u8>; MAX_FILTER_COUNT],
+    writable: [bool; MAX_FILTER_COUNT],
+    len: usize,
+}
+
+impl DynamicRow {
+    /// The `index`th fetched component, or `None` if an optional component is missing.
+    pub fn get(&self, index: usize) -> Option<*const u8> {
+        assert!(index < self.len);
+        self.components[index].map(|ptr| ptr.cast_const())
+    }
+
+    /// The `index`th fetched component, which must have been added with [DynamicQuery::write].
+    pub fn get_mut(&self, index: usize) -> Option<*mut u8> {
+        assert!(
+            index < self.len && self.writable[index],
+            "Only components added with `DynamicQuery::write` can be written"
+        );
+        self.components[index]
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+pub struct DynamicQueryIter<'a> {
+    terms: Vec<(ComponentId, Term)>,
+    sparse: Vec<bool>,
+    matching_archetypes: Vec<(usize, [Option<usize>; MAX_FILTER_COUNT])>,
+    /// Index into `matching_archetypes`.
+    archetype: usize,
+    row: usize,
+    archetypes: &'a mut Vec<Archetype>,
+    entity_manager: &'a EntityManager,
+    sparse_storages: &'a mut SparseStorages,
+    change_tick: u32,
+}
+
+impl Iterator for DynamicQueryIter<'_> {
+    type Item = DynamicRow;
+
+    fn next(&mut self) -> Option<DynamicRow> {
+        'rows: loop {
+            let (archetype_index, channels) = self.matching_archetypes.get(self.archetype)?;
+            let archetype = &mut self.archetypes[*archetype_index];
+            let row = self.row;
+            let Some(&entity_index) = archetype.entity_indices.get(row) else {
+                self.archetype += 1;
+                self.row = 0;
+                continue;
+            };
+            self.row += 1;
+
+            let mut result = DynamicRow {
+                entity: self.entity_manager.get_entity(entity_index),
+                components: [None; MAX_FILTER_COUNT],
+                writable: [false; MAX_FILTER_COUNT],
+                len: 0,
+            };
+            for (((component_id, term), sparse), channel) in
+                self.terms.iter().zip(&self.sparse).zip(channels)
+            {
+                let ptr = if *sparse {
+                    self.sparse_storages
+                        .get_mut(component_id)
+                        .and_then(|storage| storage.component_ptr(entity_index))
+                } else {
+                    channel.map(|channel| {
+                        archetype.channels[channel]
+                            .1
+                            .get_mut()
+                            .unwrap()
+                            .get_mut(row)
+                    })
+                };
+                match (term, ptr) {
+                    (Term::With | Term::Read | Term::Write, None) => continue 'rows,
+                    (Term::Without, Some(_)) => continue 'rows,
+                    (Term::With | Term::Without, _) => {}
+                    (Term::Optional | Term::Read | Term::Write, ptr) => {
+                        result.components[result.len] = ptr;
+                        result.writable[result.len] = *term == Term::Write;
+                        result.len += 1;
+                    }
+                }
+            }
+            for (((component_id, term), sparse), channel) in
+                self.terms.iter().zip(&self.sparse).zip(channels)
+            {
+                match (term, sparse, channel) {
+                    (Term::Write, false, Some(channel)) => {
+                        if let Some(ticks) = &mut archetype.channel_ticks[*channel] {
+                            *ticks.changed[row].get_mut() = self.change_tick;
+                        }
+                    }
+                    (Term::Write, true, _) => {
+                        if let Some(storage) = self.sparse_storages.get_mut(component_id) {
+                            storage.mark_changed(entity_index, self.change_tick);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            return Some(result);
+        }
+    }
+}
@@ -1,8 +1,31 @@
+use std::sync::atomic::{AtomicIsize, Ordering};
+
 use super::{ECSError, Entity, EntityLocation};
 
+/// The `storage_index` of indices on the free list, so the next generation of a despawned
+/// [Entity] isn't mistaken for a live one.
+const FREE: usize = usize::MAX;
+
+/// The largest index [EntityManager::spawn_at] accepts, so an index from a save or the
+/// network can't make it allocate an enormous table.
+pub const MAX_SPAWN_AT_INDEX: usize = (1 << 24) - 1;
+
+/// The location of an index on the free list, which stores its position in `free_entities`
+/// so [EntityManager::spawn_at] can take it off the list without searching.
+fn free_location(position: usize) -> EntityLocation {
+    EntityLocation {
+        storage_index: FREE,
+        index_within_storage: position,
+    }
+}
+
 pub(crate) struct EntityManager {
     free_entities: Vec<usize>,
     entity_index_to_generation_and_location: Vec<(u32, EntityLocation)>,
+    /// Used to reserve [Entity]s without exclusive access.
+    /// Reservations count down from the end of `free_entities`. Once that is exhausted the
+    /// cursor goes negative and reserves indices past the end of `entity_index_to_generation_and_location`.
+    free_cursor: AtomicIsize,
 }
 
 impl EntityManager {
@@ -10,14 +33,16 @@ impl EntityManager {
         Self {
             free_entities: Vec::new(),
             entity_index_to_generation_and_location: Vec::new(),
+            free_cursor: AtomicIsize::new(0),
         }
     }
     pub(crate) fn new_entity(&mut self, entity_location: EntityLocation) -> Entity {
+        debug_assert!(!self.needs_flush());
         // Reuse an old Entity's index if possible, otherwise create a new index.
         if let Some(index) = self.free_entities.pop() {
+            *self.free_cursor.get_mut() = self.free_entities.len() as isize;
             // The generation was already incremented when the former Entity was despawned.
-            let (generation, location) =
-                &mut self.entity_index_to_generation_and_location[index as usize];
+            let (generation, location) = &mut self.entity_index_to_generation_and_location[index];
             *location = entity_location;
             Entity {
                 index,
@@ -33,13 +58,94 @@ impl EntityManager {
         }
     }
 
+    /// Makes `entity` alive with its exact index and generation. Its location must be set afterwards.
+    /// Indices skipped over to reach `entity`'s index are added to `free_entities`.
+    /// Errors with [ECSError::EntityNoLongerExists] if `entity` is older than its index's generation
+    /// and [ECSError::EntityIndexOutOfRange] if its index is above [MAX_SPAWN_AT_INDEX].
+    pub(crate) fn spawn_at(&mut self, entity: Entity) -> Result<(), ECSError> {
+        debug_assert!(!self.needs_flush());
+        let placeholder = EntityLocation {
+            storage_index: 0,
+            index_within_storage: 0,
+        };
+        if entity.index > MAX_SPAWN_AT_INDEX {
+            return Err(ECSError::EntityIndexOutOfRange);
+        }
+        let len = self.entity_index_to_generation_and_location.len();
+        if entity.index >= len {
+            for index in len..entity.index {
+                self.entity_index_to_generation_and_location
+                    .push((0, free_location(self.free_entities.len())));
+                self.free_entities.push(index);
+            }
+            self.entity_index_to_generation_and_location
+                .push((0, placeholder));
+        } else {
+            let (generation, location) = self.entity_index_to_generation_and_location[entity.index];
+            if location.storage_index != FREE {
+                return Err(ECSError::EntityIndexInUse);
+            }
+            if entity.generation < generation {
+                return Err(ECSError::EntityNoLongerExists);
+            }
+            let position = location.index_within_storage;
+            self.free_entities.swap_remove(position);
+            if let Some(moved) = self.free_entities.get(position) {
+                self.entity_index_to_generation_and_location[*moved].1 = free_location(position);
+            }
+        }
+        *self.free_cursor.get_mut() = self.free_entities.len() as isize;
+        self.entity_index_to_generation_and_location[entity.index] =
+            (entity.generation, placeholder);
+        Ok(())
+    }
+
+    /// The current generation of every index, whether it's alive or free.
+    pub(crate) fn generations(&self) -> impl ExactSizeIterator<Item = u32> + '_ {
+        self.entity_index_to_generation_and_location
+            .iter()
+            .map(|(generation, _)| *generation)
+    }
+
+    /// The free indices, in the order they are reused from the end.
+    pub(crate) fn free_entities(&self) -> &[usize] {
+        &self.free_entities
+    }
+
+    /// Replaces all state with `generations` and `free_entities` from another [EntityManager].
+    /// Locations of the live [Entity]s must be set afterwards.
+    pub(crate) fn restore(&mut self, generations: Vec<u32>, free_entities: Vec<usize>) {
+        let placeholder = EntityLocation {
+            storage_index: 0,
+            index_within_storage: 0,
+        };
+        self.entity_index_to_generation_and_location = generations
+            .into_iter()
+            .map(|generation| (generation, placeholder))
+            .collect();
+        for (position, index) in free_entities.iter().enumerate() {
+            self.entity_index_to_generation_and_location[*index].1 = free_location(position);
+        }
+        *self.free_cursor.get_mut() = free_entities.len() as isize;
+        self.free_entities = free_entities;
+    }
+
+    /// Reserves capacity for at least `additional` more [Entity]s.
+    pub(crate) fn reserve(&mut self, additional: usize) {
+        self.entity_index_to_generation_and_location
+            .reserve(additional.saturating_sub(self.free_entities.len()));
+    }
+
     pub(crate) fn get_entity_location(&self, entity: Entity) -> Result<EntityLocation, ECSError> {
         if let Some(&(generation, entity_location)) = self
             .entity_index_to_generation_and_location
             .get(entity.index)
         {
-            if generation == entity.generation {
+            if generation == entity.generation && entity_location.storage_index != FREE {
                 Ok(entity_location)
+            } else if generation == entity.generation {
+                // The index was freed and `entity` is the generation it'll be reused with.
+                Err(ECSError::NoMatchingEntity)
             } else {
                 Err(ECSError::EntityNoLongerExists)
             }
@@ -48,19 +154,44 @@ impl EntityManager {
         }
     }
 
+    /// Returns the live [Entity] at `index`.
+    pub(crate) fn get_entity(&self, index: usize) -> Entity {
+        Entity {
+            index,
+            generation: self.entity_index_to_generation_and_location[index].0,
+        }
+    }
+
     pub(crate) fn despawn_entity(&mut self, entity: Entity) {
-        if let Some((generation, _entity_location)) = self
+        if let Some((generation, entity_location)) = self
             .entity_index_to_generation_and_location
             .get_mut(entity.index)
         {
             if *generation == entity.generation {
                 // Increment the generation so that further attempts to reference this Entity will be invalid.
                 *generation += 1;
+                *entity_location = free_location(self.free_entities.len());
+                debug_assert!(!self.needs_flush());
                 self.free_entities.push(entity.index);
+                *self.free_cursor.get_mut() = self.free_entities.len() as isize;
             }
         }
     }
 
+    /// Despawns the live [Entity] at each index. Indices must not repeat.
+    pub(crate) fn despawn_entities(&mut self, indices: impl IntoIterator<Item = usize>) {
+        debug_assert!(!self.needs_flush());
+        for index in indices {
+            // Increment the generation so that further attempts to reference this Entity will be invalid.
+            let (generation, entity_location) =
+                &mut self.entity_index_to_generation_and_location[index];
+            *generation += 1;
+            *entity_location = free_location(self.free_entities.len());
+            self.free_entities.push(index);
+        }
+        *self.free_cursor.get_mut() = self.free_entities.len() as isize;
+    }
+
     pub(crate) fn update_entity_index_in_archetype(
         &mut self,
         entity_index: usize,
@@ -70,4 +201,82 @@ impl EntityManager {
             .1
             .index_within_storage = entity_index_in_archetype;
     }
+
+    pub(crate) fn update_entity_location(
+        &mut self,
+        entity_index: usize,
+        entity_location: EntityLocation,
+    ) {
+        self.entity_index_to_generation_and_location[entity_index].1 = entity_location;
+    }
+
+    /// Reserves an [Entity] without requiring exclusive access.
+    /// The [Entity] is not usable until `flush_reserved_entities` is called.
+    pub(crate) fn reserve_entity(&self) -> Entity {
+        let cursor = self.free_cursor.fetch_sub(1, Ordering::Relaxed);
+        if cursor > 0 {
+            let index = self.free_entities[cursor as usize - 1];
+            Entity {
+                index,
+                generation: self.entity_index_to_generation_and_location[index].0,
+            }
+        } else {
+            Entity {
+                index: self.entity_index_to_generation_and_location.len() + (-cursor) as usize,
+                generation: 0,
+            }
+        }
+    }
+
+    /// Reserves `count` [Entity]s at once. Like `reserve_entity` they must be flushed before use.
+    pub(crate) fn reserve_entities(&self, count: usize) -> impl Iterator<Item = Entity> + '_ {
+        let range_end = self
+            .free_cursor
+            .fetch_sub(count as isize, Ordering::Relaxed);
+        let range_start = range_end - count as isize;
+
+        let reused = self.free_entities[range_start.max(0) as usize..range_end.max(0) as usize]
+            .iter()
+            .map(|&index| Entity {
+                index,
+                generation: self.entity_index_to_generation_and_location[index].0,
+            });
+
+        let len = self.entity_index_to_generation_and_location.len();
+        let new = (len + (-range_end.min(0)) as usize..len + (-range_start.min(0)) as usize).map(
+            |index| Entity {
+                index,
+                generation: 0,
+            },
+        );
+        reused.chain(new)
+    }
+
+    /// Returns `true` if there are reserved [Entity]s that haven't been flushed.
+    pub(crate) fn needs_flush(&mut self) -> bool {
+        *self.free_cursor.get_mut() != self.free_entities.len() as isize
+    }
+
+    /// Makes all reserved [Entity]s valid, calling `entity_location` to get a location for each.
+    pub(crate) fn flush_reserved_entities(
+        &mut self,
+        mut entity_location: impl FnMut(usize) -> EntityLocation,
+    ) {
+        let free_cursor = self.free_cursor.get_mut();
+        let reused_start = if *free_cursor >= 0 {
+            *free_cursor as usize
+        } else {
+            let start = self.entity_index_to_generation_and_location.len();
+            for index in start..start + (-*free_cursor) as usize {
+                self.entity_index_to_generation_and_location
+                    .push((0, entity_location(index)));
+            }
+            0
+        };
+
+        for index in self.free_entities.drain(reused_start..) {
+            self.entity_index_to_generation_and_location[index].1 = entity_location(index);
+        }
+        *free_cursor = self.free_entities.len() as isize;
+    }
 }
@@ -0,0 +1,128 @@
+use std::{any::TypeId, collections::HashMap, marker::PhantomData};
+
+use crate::*;
+
+/// Events are double buffered so each event can be read for two frames.
+/// This allows readers that run before the sender in a frame to still see every event.
+pub(crate) struct EventQueue<E> {
+    /// Events sent before the last call to [World::update_events].
+    previous: Vec<E>,
+    /// Events sent since the last call to [World::update_events].
+    current: Vec<E>,
+    /// The id of the first event in `previous`. Ids count up from 0 for every event sent.
+    previous_start: usize,
+    /// The id of the first event in `current`.
+    current_start: usize,
+}
+
+impl<E> EventQueue<E> {
+    fn new() -> Self {
+        Self {
+            previous: Vec::new(),
+            current: Vec::new(),
+            previous_start: 0,
+            current_start: 0,
+        }
+    }
+}
+
+pub(crate) trait AnyEventQueue: Send + Sync {
+    fn as_any(&self) -> &dyn std::any::Any;
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any;
+    fn update(&mut self);
+}
+
+impl<E: 'static + Send + Sync> AnyEventQueue for EventQueue<E> {
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+    fn update(&mut self) {
+        // Reuse the old allocation for the next frame's events.
+        std::mem::swap(&mut self.previous, &mut self.current);
+        self.current.clear();
+        self.previous_start = self.current_start;
+        self.current_start += self.previous.len();
+    }
+}
+
+/// A cursor used to read the events of type `E` it hasn't seen yet.
+/// See [World::read_events].
+pub struct EventReader<E> {
+    cursor: usize,
+    phantom: PhantomData<fn() -> E>,
+}
+
+impl<E> EventReader<E> {
+    pub fn new() -> Self {
+        Self {
+            cursor: 0,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<E> Default for EventReader<E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub(crate) fn send_event<E: 'static + Send + Sync>(
+    events: &mut HashMap<TypeId, Box<dyn AnyEventQueue>>,
+    event: E,
+) {
+    events
+        .entry(TypeId::of::<E>())
+        .or_insert_with(|| Box::new(EventQueue::<E>::new()))
+        .as_any_mut()
+        .downcast_mut::<EventQueue<E>>()
+        .unwrap()
+        .current
+        .push(event)
+}
+
+impl World {
+    /// Sends an event that can be read with [World::read_events]
+    /// until the second call to [World::update_events].
+    pub fn send_event<E: 'static + Send + Sync>(&mut self, event: E) {
+        send_event(&mut self.events, event)
+    }
+
+    /// Iterates the events `event_reader` hasn't read yet, oldest first.
+    pub fn read_events<'a, E: 'static + Send + Sync>(
+        &'a self,
+        event_reader: &mut EventReader<E>,
+    ) -> impl Iterator<Item = &'a E> + 'a {
+        let (previous, current): (&[E], &[E]) =
+            if let Some(queue) = self.events.get(&TypeId::of::<E>()) {
+                let queue = queue.as_any().downcast_ref::<EventQueue<E>>().unwrap();
+                let skip_previous = event_reader
+                    .cursor
+                    .saturating_sub(queue.previous_start)
+                    .min(queue.previous.len());
+                let skip_current = event_reader
+                    .cursor
+                    .saturating_sub(queue.current_start)
+                    .min(queue.current.len());
+                event_reader.cursor = queue.current_start + queue.current.len();
+                (
+                    &queue.previous[skip_previous..],
+                    &queue.current[skip_current..],
+                )
+            } else {
+                (&[], &[])
+            };
+        previous.iter().chain(current.iter())
+    }
+
+    /// Swaps the event buffers. This should be called once per frame.
+    /// Events are dropped after two calls, so each event is visible for two frames.
+    pub fn update_events(&mut self) {
+        for queue in self.events.values_mut() {
+            queue.update();
+        }
+    }
+}
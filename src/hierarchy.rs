@@ -0,0 +1,149 @@
+use crate::*;
+
+/// The parent of an [Entity]. Set with [World::set_parent].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Parent(Entity);
+
+impl Parent {
+    pub fn get(&self) -> Entity {
+        self.0
+    }
+}
+
+impl ComponentTrait for Parent {
+    fn clone_vec(data: &[Self]) -> Option<Vec<Self>> {
+        Some(data.into())
+    }
+}
+
+/// The children of an [Entity], in the order they were added. Kept in sync with [Parent].
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Children(Vec<Entity>);
+
+impl std::ops::Deref for Children {
+    type Target = [Entity];
+    fn deref(&self) -> &[Entity] {
+        &self.0
+    }
+}
+
+impl ComponentTrait for Children {
+    fn clone_vec(data: &[Self]) -> Option<Vec<Self>> {
+        Some(data.into())
+    }
+}
+
+impl World {
+    /// Makes `child` a child of `parent`, removing it from its previous parent.
+    /// Errors with [ECSError::HierarchyCycle] if `parent` is `child` or one of its descendants.
+    pub fn set_parent(&mut self, child: Entity, parent: Entity) -> Result<(), ECSError> {
+        self.flush_reserved_entities();
+        self.entity_manager.get_entity_location(child)?;
+        self.entity_manager.get_entity_location(parent)?;
+        if parent == child || self.ancestors(parent).any(|e| e == child) {
+            return Err(ECSError::HierarchyCycle);
+        }
+
+        self.remove_parent(child)?;
+        self.add_components(child, Parent(parent))?;
+        match self.get_mut::<Children>(parent) {
+            Ok(children) => children.0.push(child),
+            Err(_) => self.add_components(parent, Children(vec![child]))?,
+        }
+        Ok(())
+    }
+
+    /// Detaches `child` from its parent and returns the former parent.
+    pub fn remove_parent(&mut self, child: Entity) -> Result<Option<Entity>, ECSError> {
+        let parent = match self.get::<Parent>(child) {
+            Ok(parent) => parent.0,
+            Err(ECSError::NoMatchingComponent) => return Ok(None),
+            Err(error) => return Err(error),
+        };
+        self.remove_components::<Parent>(child)?;
+
+        // The parent may have been despawned without `despawn_recursive`.
+        if let Ok(children) = self.get_mut::<Children>(parent) {
+            children.0.retain(|e| *e != child);
+            if children.0.is_empty() {
+                self.remove_components::<Children>(parent)?;
+            }
+        }
+        Ok(Some(parent))
+    }
+
+    /// Despawns `entity` and all of its descendants and detaches it from its parent.
+    /// Descendants that were already despawned are skipped.
+    pub fn despawn_recursive(&mut self, entity: Entity) -> Result<(), ECSError> {
+        self.remove_parent(entity)?;
+        let entities: Vec<Entity> = self.depth_first(entity).map(|(e, _)| e).collect();
+        self.despawn_batch(&entities);
+        Ok(())
+    }
+
+    /// Removes despawned `(child, parent)` pairs from the [Children] of parents that are still alive.
+    pub(crate) fn detach_despawned(&mut self, children: &[(Entity, Entity)]) {
+        for (child, parent) in children {
+            if let Ok(siblings) = self.get_mut::<Children>(*parent) {
+                siblings.0.retain(|e| e != child);
+                if siblings.0.is_empty() {
+                    let _ = self.remove_components::<Children>(*parent);
+                }
+            }
+        }
+    }
+
+    /// Iterates the parent of `entity`, then its parent, up to the root.
+    pub fn ancestors(&self, entity: Entity) -> Ancestors<'_> {
+        Ancestors {
+            world: self,
+            current: entity,
+        }
+    }
+
+    /// Iterates all descendants of `entity` in depth-first order, not including `entity`.
+    pub fn descendants(&self, entity: Entity) -> impl Iterator<Item = Entity> + '_ {
+        self.depth_first(entity).skip(1).map(|(e, _)| e)
+    }
+
+    /// Iterates `entity` and its descendants in depth-first order along with their depth below `entity`.
+    /// Children are visited in the order they were added.
+    pub fn depth_first(&self, entity: Entity) -> DepthFirst<'_> {
+        DepthFirst {
+            world: self,
+            stack: vec![(entity, 0)],
+        }
+    }
+}
+
+/// See [World::ancestors].
+pub struct Ancestors<'a> {
+    world: &'a World,
+    current: Entity,
+}
+
+impl<'a> Iterator for Ancestors<'a> {
+    type Item = Entity;
+    fn next(&mut self) -> Option<Entity> {
+        self.current = self.world.get::<Parent>(self.current).ok()?.0;
+        Some(self.current)
+    }
+}
+
+/// See [World::depth_first].
+pub struct DepthFirst<'a> {
+    world: &'a World,
+    stack: Vec<(Entity, usize)>,
+}
+
+impl<'a> Iterator for DepthFirst<'a> {
+    type Item = (Entity, usize);
+    fn next(&mut self) -> Option<(Entity, usize)> {
+        let (entity, depth) = self.stack.pop()?;
+        if let Ok(children) = self.world.get::<Children>(entity) {
+            self.stack
+                .extend(children.iter().rev().map(|child| (*child, depth + 1)));
+        }
+        Some((entity, depth))
+    }
+}
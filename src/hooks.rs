@@ -0,0 +1,189 @@
+use std::{any::TypeId, collections::HashMap};
+
+use crate::*;
+
+/// Hooks are passed a pointer to the component because table components are type-erased.
+type Hook = Box<dyn FnMut(Entity, *const u8, &mut HookWorld) + Send + Sync>;
+
+/// The hooks registered for a type of component.
+#[derive(Default)]
+pub(crate) struct ComponentHooks {
+    on_add: Option<Hook>,
+    on_insert: Option<Hook>,
+    on_replace: Option<Hook>,
+    on_remove: Option<Hook>,
+}
+
+#[derive(Clone, Copy)]
+pub(crate) enum HookKind {
+    Add,
+    Insert,
+    Replace,
+    Remove,
+}
+
+impl ComponentHooks {
+    fn get_mut(&mut self, hook_kind: HookKind) -> &mut Option<Hook> {
+        match hook_kind {
+            HookKind::Add => &mut self.on_add,
+            HookKind::Insert => &mut self.on_insert,
+            HookKind::Replace => &mut self.on_replace,
+            HookKind::Remove => &mut self.on_remove,
+        }
+    }
+}
+
+/// The restricted access to a [World] given to component hooks.
+/// Hooks run while the [World] is being changed so structural changes are deferred.
+/// They are applied after the change that ran the hook finishes.
+pub struct HookWorld<'a> {
+    entity_manager: &'a entity_manager::EntityManager,
+    commands: &'a Commands,
+    events: &'a mut HashMap<TypeId, Box<dyn AnyEventQueue>>,
+}
+
+impl<'a> HookWorld<'a> {
+    /// Reserves an [Entity] that will be spawned with `components`.
+    pub fn spawn<COMPONENTS: ComponentBundleTrait>(&self, components: COMPONENTS) -> Entity {
+        let entity = self.entity_manager.reserve_entity();
+        self.commands.spawn_reserved(entity, components);
+        entity
+    }
+
+    pub fn despawn(&self, entity: Entity) {
+        self.commands.despawn(entity)
+    }
+
+    pub fn add_components<COMPONENTS: ComponentBundleTrait>(
+        &self,
+        entity: Entity,
+        components: COMPONENTS,
+    ) {
+        self.commands.add_components(entity, components)
+    }
+
+    pub fn remove_components<COMPONENTS: ComponentBundleTrait>(&self, entity: Entity) {
+        self.commands.remove_components::<COMPONENTS>(entity)
+    }
+
+    /// Sends an event. See [World::send_event].
+    pub fn send_event<E: 'static + Send + Sync>(&mut self, event: E) {
+        events::send_event(self.events, event)
+    }
+}
+
+impl World {
+    /// Registers a hook that runs when a `T` is added to an [Entity] that didn't have one.
+    pub fn on_add<T: ComponentTrait>(
+        &mut self,
+        hook: impl FnMut(Entity, &T, &mut HookWorld) + Send + Sync + 'static,
+    ) {
+        self.set_hook(HookKind::Add, hook)
+    }
+
+    /// Registers a hook that runs when a `T` is added to an [Entity] or replaces an existing `T`.
+    /// Runs after the hook registered with [World::on_add].
+    pub fn on_insert<T: ComponentTrait>(
+        &mut self,
+        hook: impl FnMut(Entity, &T, &mut HookWorld) + Send + Sync + 'static,
+    ) {
+        self.set_hook(HookKind::Insert, hook)
+    }
+
+    /// Registers a hook that runs with the old value before a `T` is replaced or removed.
+    /// This is useful for cleaning up indices keyed by the component's value.
+    pub fn on_replace<T: ComponentTrait>(
+        &mut self,
+        hook: impl FnMut(Entity, &T, &mut HookWorld) + Send + Sync + 'static,
+    ) {
+        self.set_hook(HookKind::Replace, hook)
+    }
+
+    /// Registers a hook that runs before a `T` is removed from an [Entity] or the [Entity] is despawned.
+    /// Runs after the hook registered with [World::on_replace].
+    pub fn on_remove<T: ComponentTrait>(
+        &mut self,
+        hook: impl FnMut(Entity, &T, &mut HookWorld) + Send + Sync + 'static,
+    ) {
+        self.set_hook(HookKind::Remove, hook)
+    }
+
+    fn set_hook<T: ComponentTrait>(
+        &mut self,
+        hook_kind: HookKind,
+        mut hook: impl FnMut(Entity, &T, &mut HookWorld) + Send + Sync + 'static,
+    ) {
+        *self
+            .component_hooks
+            .entry(T::component_id())
+            .or_default()
+            .get_mut(hook_kind) = Some(Box::new(move |entity, component, world| {
+            // SAFETY: Hooks are only run with components of the type they're registered for.
+            hook(entity, unsafe { &*component.cast::<T>() }, world)
+        }));
+    }
+
+    /// Runs the hooks of `hook_kinds` for the `component_id` component of `entity`.
+    pub(crate) fn run_hooks(
+        &mut self,
+        hook_kinds: &[HookKind],
+        entity: Entity,
+        component_id: ComponentId,
+    ) {
+        let Some(hooks) = self.component_hooks.get_mut(&component_id) else {
+            return;
+        };
+        for hook_kind in hook_kinds {
+            if let Some(hook) = hooks.get_mut(*hook_kind) {
+                let component = if let Some(storage) = self.sparse_storages.get_mut(&component_id) {
+                    storage.component_ptr(entity.index).unwrap()
+                } else {
+                    let entity_location = self.entity_manager.get_entity_location(entity).unwrap();
+                    let (channel, _) = self.archetypes[entity_location.storage_index]
+                        .channel_mut(component_id)
+                        .unwrap();
+                    channel.get(entity_location.index_within_storage)
+                };
+                let mut hook_world = HookWorld {
+                    entity_manager: &self.entity_manager,
+                    commands: &self.hook_commands,
+                    events: &mut self.events,
+                };
+                hook(entity, component, &mut hook_world);
+            }
+        }
+    }
+
+    /// Runs the hooks of `hook_kinds` for every component of `entity`.
+    pub(crate) fn run_hooks_for_entity(&mut self, hook_kinds: &[HookKind], entity: Entity) {
+        if self.component_hooks.is_empty() {
+            return;
+        }
+        let entity_location = self.entity_manager.get_entity_location(entity).unwrap();
+        for i in 0..self.archetypes[entity_location.storage_index]
+            .channels
+            .len()
+        {
+            let component_id = self.archetypes[entity_location.storage_index].channels[i].0;
+            self.run_hooks(hook_kinds, entity, component_id);
+        }
+        let sparse_component_ids: Vec<ComponentId> = self
+            .sparse_storages
+            .iter()
+            .filter(|(_, storage)| storage.contains(entity.index))
+            .map(|(component_id, _)| *component_id)
+            .collect();
+        for component_id in sparse_component_ids {
+            self.run_hooks(hook_kinds, entity, component_id);
+        }
+    }
+
+    /// Applies the structural changes deferred by hooks.
+    /// Errors are ignored because the change that ran the hook has already succeeded.
+    pub(crate) fn apply_hook_commands(&mut self) {
+        while !self.hook_commands.is_empty() {
+            let mut commands = std::mem::take(&mut self.hook_commands);
+            let _ = self.apply_commands_inner(&mut commands);
+        }
+    }
+}
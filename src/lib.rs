@@ -1,9 +1,17 @@
-#![feature(generic_associated_types)]
-
 use std::{any::TypeId, sync::RwLock};
 
 mod archetype_lookup;
+mod batch;
+mod blob_vec;
+mod commands;
+mod component_registry;
+mod diff;
+mod dynamic;
+mod dynamic_query;
 mod entity_manager;
+mod events;
+mod hierarchy;
+mod hooks;
 
 #[macro_use]
 mod queries;
@@ -14,12 +22,39 @@ mod query_iterator;
 #[macro_use]
 mod multi_iterator;
 
+mod relations;
+mod removed_components;
+mod rollback;
+mod scene;
+#[cfg(feature = "serde")]
+pub mod serialize;
 mod sparse_set;
+mod sparse_storage;
+mod state_hash;
+#[cfg(feature = "transform")]
+mod transform;
 mod world;
 
+pub use blob_vec::*;
+pub use commands::*;
+pub use component_registry::*;
+pub use diff::*;
+pub use dynamic_query::*;
+pub use entity_manager::MAX_SPAWN_AT_INDEX;
+pub use events::*;
+pub use hierarchy::*;
+pub use hooks::*;
 pub use multi_iterator::*;
 pub use queries::*;
 pub use query_iterator::*;
+pub use relations::*;
+pub use removed_components::*;
+pub use rollback::*;
+pub use scene::*;
+pub use sparse_storage::*;
+pub use state_hash::*;
+#[cfg(feature = "transform")]
+pub use transform::*;
 pub use world::*;
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
@@ -29,14 +64,53 @@ pub struct Entity {
 }
 
 impl Entity {
+    /// A target that matches any [Entity]. See [ComponentId::wildcard_pair].
+    pub(crate) const WILDCARD: Entity = Entity {
+        index: usize::MAX,
+        generation: u32::MAX,
+    };
+
     pub fn from_index_and_generation(index: usize, generation: u32) -> Self {
         Self { index, generation }
     }
 }
 
-#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash)]
+/// Identifies a type of component or a (relation, target) pair.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash, Debug)]
 pub struct ComponentId {
-    type_id: TypeId,
+    type_id: ComponentTypeId,
+    /// The target [Entity] if this is a relation pair. See [World::relate].
+    target: Option<Entity>,
+}
+
+/// A Rust type, or a type declared at runtime with [ComponentRegistry::register_dynamic].
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash, Debug)]
+pub(crate) enum ComponentTypeId {
+    Rust(TypeId),
+    Dynamic(usize),
+}
+
+impl ComponentId {
+    /// The [ComponentId] of the relation `R` pointing at `target`.
+    pub fn pair<R: ComponentTrait>(target: Entity) -> Self {
+        Self {
+            type_id: ComponentTypeId::Rust(TypeId::of::<R>()),
+            target: Some(target),
+        }
+    }
+
+    /// Matches every pair of the relation `R` when used in a [archetype_lookup::Filter].
+    pub fn wildcard_pair<R: ComponentTrait>() -> Self {
+        Self::pair::<R>(Entity::WILDCARD)
+    }
+
+    pub fn target(&self) -> Option<Entity> {
+        self.target
+    }
+
+    pub(crate) fn is_wildcard(&self) -> bool {
+        self.target == Some(Entity::WILDCARD)
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -44,6 +118,23 @@ pub enum ECSError {
     NoMatchingComponent,
     NoMatchingEntity,
     EntityNoLongerExists,
+    ComponentAlreadyBorrowed,
+    /// A different generation of the [Entity] passed to [World::spawn_at] is alive.
+    EntityIndexInUse,
+    /// The [Entity] passed to [World::spawn_at] has an index above [MAX_SPAWN_AT_INDEX].
+    EntityIndexOutOfRange,
+    /// [World::set_parent] was asked to parent an [Entity] to itself or one of its descendants.
+    HierarchyCycle,
+    /// A bundle had more than one of the same type of component.
+    DuplicateComponent,
+    /// [World::try_insert] was called for a component the [Entity] already has.
+    ComponentAlreadyPresent,
+    /// The bytes passed to [World::spawn_dynamic] aren't the size of the component.
+    ComponentLayoutMismatch,
+    /// [World::spawn_dynamic] was given a [StorageKind::SparseSet] component.
+    SparseComponentFromBytes,
+    /// The bytes passed to [World::load_binary] aren't a valid snapshot.
+    InvalidSnapshot,
 }
 
 #[derive(Clone, Copy)]
@@ -52,14 +143,65 @@ pub struct EntityLocation {
     index_within_storage: usize,
 }
 
+/// How a type of component is stored.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StorageKind {
+    /// Stored in the columns of each [Archetype]. Fastest to iterate, but adding or removing
+    /// the component moves the [Entity]'s other components to another [Archetype].
+    Table,
+    /// Stored in one sparse set per type keyed by [Entity] index.
+    /// Not part of the [Archetype] so it is cheap to add and remove, but slower to iterate.
+    SparseSet,
+    /// For zero-sized marker components without a [Drop] implementation.
+    /// Part of the [Archetype] so it can be matched with [With] and [Without],
+    /// but nothing is stored for each [Entity] and no change ticks are kept.
+    /// Tags can't be query parameters:
+    ///
+    /// ```compile_fail
+    /// # use rust_ecs::*;
+    /// struct Marker;
+    /// impl ComponentTrait for Marker {
+    ///     const STORAGE: StorageKind = StorageKind::Tag;
+    ///     fn clone_vec(data: &[Self]) -> Option<Vec<Self>> {
+    ///         Some(data.iter().map(|_| Marker).collect())
+    ///     }
+    /// }
+    /// World::new().query::<All<&Marker>>();
+    /// ```
+    ///
+    /// Nor can they be filtered with [Added] or [Changed]:
+    ///
+    /// ```compile_fail
+    /// # use rust_ecs::*;
+    /// # struct Marker;
+    /// # impl ComponentTrait for Marker {
+    /// #     const STORAGE: StorageKind = StorageKind::Tag;
+    /// #     fn clone_vec(data: &[Self]) -> Option<Vec<Self>> {
+    /// #         Some(data.iter().map(|_| Marker).collect())
+    /// #     }
+    /// # }
+    /// struct Position;
+    /// impl ComponentTrait for Position {
+    ///     fn clone_vec(data: &[Self]) -> Option<Vec<Self>> {
+    ///         Some(data.iter().map(|_| Position).collect())
+    ///     }
+    /// }
+    /// World::new().query::<All<&Position, Added<Marker>>>();
+    /// ```
+    Tag,
+}
+
 pub trait ComponentTrait: 'static + Send + Sync + Sized {
+    const STORAGE: StorageKind = StorageKind::Table;
+
     fn clone_vec(data: &[Self]) -> Option<Vec<Self>>;
     fn make_vec() -> Vec<Self> {
         Vec::new()
     }
     fn component_id() -> ComponentId {
         ComponentId {
-            type_id: std::any::TypeId::of::<Self>(),
+            type_id: ComponentTypeId::Rust(TypeId::of::<Self>()),
+            target: None,
         }
     }
 }
@@ -70,6 +212,12 @@ pub trait ComponentBundleTrait {
         f: impl FnOnce(&mut [(&mut dyn AnyComponentTrait, ComponentId)]),
     );
     fn append_component_ids(&self, component_ids: &mut Vec<ComponentId>);
+    /// Constructs the bundle from components written into each `Option<COMPONENT>` by `f`.
+    fn from_components(
+        f: impl FnOnce(&mut [(&mut dyn AnyComponentTrait, ComponentId)]) -> Result<(), ECSError>,
+    ) -> Result<Self, ECSError>
+    where
+        Self: Sized;
 }
 
 impl<A: ComponentTrait> ComponentBundleTrait for A {
@@ -83,6 +231,14 @@ impl<A: ComponentTrait> ComponentBundleTrait for A {
     fn append_component_ids(&self, component_ids: &mut Vec<ComponentId>) {
         component_ids.push(A::component_id())
     }
+
+    fn from_components(
+        f: impl FnOnce(&mut [(&mut dyn AnyComponentTrait, ComponentId)]) -> Result<(), ECSError>,
+    ) -> Result<Self, ECSError> {
+        let mut a = None;
+        f(&mut [(&mut a, A::component_id())])?;
+        Ok(a.unwrap())
+    }
 }
 
 impl<A: ComponentTrait, B: ComponentTrait> ComponentBundleTrait for (A, B) {
@@ -97,22 +253,71 @@ impl<A: ComponentTrait, B: ComponentTrait> ComponentBundleTrait for (A, B) {
     }
 
     fn append_component_ids(&self, component_ids: &mut Vec<ComponentId>) {
-        component_ids.push(A::component_id())
+        component_ids.push(A::component_id());
+        component_ids.push(B::component_id());
+    }
+
+    fn from_components(
+        f: impl FnOnce(&mut [(&mut dyn AnyComponentTrait, ComponentId)]) -> Result<(), ECSError>,
+    ) -> Result<Self, ECSError> {
+        let (mut a, mut b) = (None, None);
+        f(&mut [(&mut a, A::component_id()), (&mut b, B::component_id())])?;
+        Ok((a.unwrap(), b.unwrap()))
     }
 }
 
 pub trait AnyComponentTrait: std::any::Any {
-    fn new_archetype_channel(&self) -> Box<dyn ArchetypeComponentChannel>;
+    fn component_info(&self) -> ComponentInfo;
+    /// Moves the component onto the end of `channel`, which must store the component's type.
+    fn push_into(&mut self, channel: &mut BlobVec);
+    /// Moves the component into `channel` at `index`, dropping the component it replaces.
+    fn replace_in(&mut self, channel: &mut BlobVec, index: usize);
+    /// Moves the component at `ptr` into `self`.
+    ///
+    /// # Safety
+    /// `ptr` must point to a component of this type, which must not be used afterwards.
+    unsafe fn move_from(&mut self, ptr: *const u8);
     fn as_any_mut(&mut self) -> &mut dyn std::any::Any;
+    /// Moves the component out into a new allocation so it can be stored for later.
+    fn take_boxed(&mut self) -> Box<dyn AnyComponentTrait + Send>;
+    /// Returns `false` if the component has already been taken.
+    fn is_some(&self) -> bool;
+    fn storage_kind(&self) -> StorageKind;
+    fn new_sparse_storage(&self) -> Box<dyn AnySparseStorage>;
 }
 
 impl<COMPONENT: ComponentTrait> AnyComponentTrait for Option<COMPONENT> {
-    fn new_archetype_channel(&self) -> Box<dyn ArchetypeComponentChannel> {
-        Box::new(RwLock::new(Vec::<COMPONENT>::new()))
+    fn component_info(&self) -> ComponentInfo {
+        ComponentInfo::new::<COMPONENT>()
+    }
+    fn push_into(&mut self, channel: &mut BlobVec) {
+        let component = std::mem::ManuallyDrop::new(self.take().unwrap());
+        // SAFETY: The caller ensures `channel` stores `COMPONENT`s. `component` isn't dropped.
+        unsafe { channel.push((&*component as *const COMPONENT).cast()) }
+    }
+    fn replace_in(&mut self, channel: &mut BlobVec, index: usize) {
+        let component = std::mem::ManuallyDrop::new(self.take().unwrap());
+        // SAFETY: Same as `push_into`.
+        unsafe { channel.replace(index, (&*component as *const COMPONENT).cast()) }
+    }
+    unsafe fn move_from(&mut self, ptr: *const u8) {
+        *self = Some(ptr.cast::<COMPONENT>().read());
     }
     fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
         self
     }
+    fn take_boxed(&mut self) -> Box<dyn AnyComponentTrait + Send> {
+        Box::new(self.take())
+    }
+    fn is_some(&self) -> bool {
+        Option::is_some(self)
+    }
+    fn storage_kind(&self) -> StorageKind {
+        COMPONENT::STORAGE
+    }
+    fn new_sparse_storage(&self) -> Box<dyn AnySparseStorage> {
+        Box::new(SparseStorage::<COMPONENT>::new())
+    }
 }
 macro_rules! tuple_impls {
     ( $count: tt, $( ($index: tt, $tuple:ident) ),*) => {
@@ -13,6 +13,10 @@ macro_rules! multi_iterator_impl {
             fn next(&mut self) -> Option<Self::Item> {
                 Some(($( self.0.$index.next()?,)*))
             }
+            #[allow(unused_variables, clippy::unused_unit)]
+            fn nth(&mut self, n: usize) -> Option<Self::Item> {
+                Some(($( self.0.$index.nth(n)?,)*))
+            }
         }
     }
 }
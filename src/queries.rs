@@ -1,46 +1,98 @@
-use std::sync::{RwLockReadGuard, RwLockWriteGuard};
+use std::{marker::PhantomData, sync::RwLock};
 
 use crate::{
-    archetype_lookup::Filter, get_rwlock_from_channel, get_vec_from_channel, query_iterator::*,
-    Archetype, ArchetypeComponentChannel, ComponentId,
+    archetype_lookup::{Filter, FilterType, MAX_FILTER_COUNT},
+    entity_manager::EntityManager,
+    query_iterator::*,
+    Archetype, BlobVec, ChannelReadGuard, ChannelTicks, ChannelWriteGuard, ComponentId,
+    SparseContext, StorageKind,
 };
 
 use super::{ComponentTrait, ECSError, World};
 
 pub trait QueryTrait {
     type Result<'a>;
-    fn get_result<'a>(world: &'a World) -> Result<Self::Result<'a>, ECSError>;
+    fn get_result<'a>(world: &'a World, ticks: QueryTicks) -> Result<Self::Result<'a>, ECSError>;
 }
 
 pub trait MutQueryTrait {
     type Result<'a>;
-    fn get_result_mut<'a>(world: &'a mut World) -> Result<Self::Result<'a>, ECSError>;
+    fn get_result_mut<'a>(
+        world: &'a mut World,
+        ticks: QueryTicks,
+    ) -> Result<Self::Result<'a>, ECSError>;
+}
+
+/// Remembers when a query last ran so its [Added] and [Changed] filters only match
+/// changes made since then. Each system keeps its own, like [crate::RemovedComponents].
+/// See [World::query_with_state].
+#[derive(Default)]
+pub struct QueryState {
+    pub(crate) last_run: u32,
+}
+
+impl QueryState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+/// The change ticks a query compares against and records mutable accesses with.
+#[derive(Copy, Clone)]
+pub struct QueryTicks {
+    pub(crate) last_run: u32,
+    pub(crate) this_run: u32,
+}
+
+impl QueryTicks {
+    /// Returns `true` if `tick` is more recent than the last time the query ran.
+    /// Compared relative to `this_run` so that ticks can wrap around.
+    pub fn is_changed(&self, tick: u32) -> bool {
+        self.this_run.wrapping_sub(tick) < self.this_run.wrapping_sub(self.last_run)
+    }
 }
 
 /// Info about the [Entity]s in each Archetype
 pub struct ArchetypeInfo<'a> {
+    #[allow(dead_code)]
     archetype_index: usize,
-    archetype_entities: &'a Vec<usize>,
+    pub(crate) archetype_entities: &'a Vec<usize>,
+    pub(crate) entity_manager: &'a EntityManager,
+    /// `false` for each row missing a [StorageKind::SparseSet] component the query reads.
+    /// `None` if no rows are missing one.
+    pub(crate) sparse_rows: Option<Vec<bool>>,
 }
-pub struct All<'a, PARAMETERS: QueryParametersTrait> {
-    pub(crate) borrow: Vec<(ArchetypeInfo<'a>, PARAMETERS::ResultMut<'a>)>,
+
+impl ArchetypeInfo<'_> {
+    pub(crate) fn has_sparse_components(&self, row: usize) -> bool {
+        self.sparse_rows.as_ref().is_none_or(|rows| rows[row])
+    }
+}
+pub struct All<'a, PARAMETERS: QueryParametersTrait, FILTERS: QueryFilterTrait = ()> {
+    pub(crate) borrow: Vec<(
+        ArchetypeInfo<'a>,
+        PARAMETERS::ResultMut<'a>,
+        FILTERS::Result<'a>,
+    )>,
 }
 
 pub struct One<'a, PARAMETERS: QueryParametersTrait> {
     borrow: (ArchetypeInfo<'a>, PARAMETERS::ResultMut<'a>),
+    /// The first row of the archetype that has all of the query's components.
+    row: usize,
 }
 
 impl<'a, PARAMETERS: QueryParametersTrait> One<'a, PARAMETERS> {
     pub fn get<'b>(
         &'b self,
     ) -> <<PARAMETERS::ResultMut<'a> as GetIteratorsTrait>::Iterator<'b> as Iterator>::Item {
-        self.borrow.1.get_iterator().next().unwrap()
+        self.borrow.1.get_component(self.row)
     }
 
     pub fn get_mut<'b>(
         &'b mut self,
     ) -> <<PARAMETERS::ResultMut<'a> as GetIteratorsTrait>::IteratorMut<'b> as Iterator>::Item {
-        self.borrow.1.get_iterator_mut().next().unwrap()
+        self.borrow.1.get_component_mut(self.row)
     }
 }
 
@@ -56,7 +108,7 @@ impl<'a, PARAMETERS: QueryParametersTrait> std::ops::Deref for One<'a, PARAMETER
 
 // I am not a fan of these huge types.
 
-impl<'a, PARAMETERS: QueryParametersTrait> All<'a, PARAMETERS> {
+impl<'a, PARAMETERS: QueryParametersTrait, FILTERS: QueryFilterTrait> All<'a, PARAMETERS, FILTERS> {
     pub fn archetypes_len(&self) -> usize {
         self.borrow.len()
     }
@@ -65,54 +117,159 @@ impl<'a, PARAMETERS: QueryParametersTrait> All<'a, PARAMETERS> {
 pub trait QueryParameterTrait {
     type Result<'a>: GetIteratorsTrait;
     type ResultMut<'a>: GetIteratorsTrait;
+    /// [StorageKind::SparseSet] parameters don't have a channel in the [Archetype].
+    /// They're fetched with [QueryParameterTrait::get_sparse_result] instead.
+    const STORAGE: StorageKind = StorageKind::Table;
     fn get_component_id() -> ComponentId;
     fn get_result<'a>(
-        channel: &'a dyn ArchetypeComponentChannel,
+        component_id: ComponentId,
+        channel: &'a RwLock<BlobVec>,
+        channel_ticks: Option<&'a ChannelTicks>,
+        ticks: QueryTicks,
     ) -> Result<Self::Result<'a>, ECSError>;
 
     fn get_result_mut<'a>(
-        channel: &'a mut dyn ArchetypeComponentChannel,
+        component_id: ComponentId,
+        channel: &'a mut BlobVec,
+        channel_ticks: Option<&'a ChannelTicks>,
+        ticks: QueryTicks,
     ) -> Result<Self::ResultMut<'a>, ECSError>;
+
+    fn get_sparse_result<'a>(
+        _sparse: &mut SparseContext<'a, '_>,
+        _ticks: QueryTicks,
+    ) -> Result<Self::Result<'a>, ECSError> {
+        Err(ECSError::NoMatchingComponent)
+    }
+
+    fn get_sparse_result_mut<'a>(
+        _sparse: &mut SparseContext<'a, '_>,
+        _ticks: QueryTicks,
+    ) -> Result<Self::ResultMut<'a>, ECSError> {
+        Err(ECSError::NoMatchingComponent)
+    }
 }
 
 impl<A: ComponentTrait> QueryParameterTrait for &A {
-    type Result<'a> = RwLockReadGuard<'a, Vec<A>>;
-    type ResultMut<'a> = &'a [A];
+    type Result<'a> = Column<'a, A, ChannelReadGuard<'a, A>>;
+    type ResultMut<'a> = Column<'a, A, &'a [A]>;
+    const STORAGE: StorageKind = A::STORAGE;
 
     fn get_component_id() -> ComponentId {
-        A::component_id()
+        borrowed_component_id::<A>()
     }
 
     fn get_result<'a>(
-        channel: &'a dyn ArchetypeComponentChannel,
+        _component_id: ComponentId,
+        channel: &'a RwLock<BlobVec>,
+        _channel_ticks: Option<&'a ChannelTicks>,
+        _ticks: QueryTicks,
     ) -> Result<Self::Result<'a>, ECSError> {
-        Ok(get_rwlock_from_channel::<A>(channel).read().unwrap())
+        // SAFETY: The channel for `A`'s `ComponentId` stores `A`s.
+        Ok(Column::Table(unsafe {
+            ChannelReadGuard::new(channel.read().unwrap())
+        }))
     }
 
     fn get_result_mut<'a>(
-        channel: &'a mut dyn ArchetypeComponentChannel,
+        _component_id: ComponentId,
+        channel: &'a mut BlobVec,
+        _channel_ticks: Option<&'a ChannelTicks>,
+        _ticks: QueryTicks,
+    ) -> Result<Self::ResultMut<'a>, ECSError> {
+        // SAFETY: The channel for `A`'s `ComponentId` stores `A`s.
+        Ok(Column::Table(unsafe { channel.as_slice() }))
+    }
+
+    fn get_sparse_result<'a>(
+        sparse: &mut SparseContext<'a, '_>,
+        _ticks: QueryTicks,
+    ) -> Result<Self::Result<'a>, ECSError> {
+        Ok(Column::Sparse(sparse.read::<A>()?))
+    }
+
+    fn get_sparse_result_mut<'a>(
+        sparse: &mut SparseContext<'a, '_>,
+        _ticks: QueryTicks,
     ) -> Result<Self::ResultMut<'a>, ECSError> {
-        Ok(get_vec_from_channel::<A>(channel))
+        Ok(Column::Sparse(sparse.read::<A>()?))
     }
 }
 impl<A: ComponentTrait> QueryParameterTrait for &mut A {
-    type Result<'a> = RwLockWriteGuard<'a, Vec<A>>;
-    type ResultMut<'a> = &'a [A];
+    type Result<'a> = ColumnMut<'a, A, ChannelWriteGuard<'a, A>>;
+    type ResultMut<'a> = ColumnMut<'a, A, &'a mut [A]>;
+    const STORAGE: StorageKind = A::STORAGE;
 
     fn get_component_id() -> ComponentId {
-        A::component_id()
+        borrowed_component_id::<A>()
     }
 
     fn get_result<'a>(
-        channel: &'a dyn ArchetypeComponentChannel,
+        _component_id: ComponentId,
+        channel: &'a RwLock<BlobVec>,
+        channel_ticks: Option<&'a ChannelTicks>,
+        ticks: QueryTicks,
     ) -> Result<Self::Result<'a>, ECSError> {
-        Ok(get_rwlock_from_channel::<A>(channel).write().unwrap())
+        Ok(ColumnMut::Table(ComponentsMut {
+            // SAFETY: The channel for `A`'s `ComponentId` stores `A`s.
+            components: unsafe { ChannelWriteGuard::new(channel.write().unwrap()) },
+            changed_ticks: &tracked(channel_ticks).changed,
+            change_tick: ticks.this_run,
+        }))
     }
 
     fn get_result_mut<'a>(
-        channel: &'a mut dyn ArchetypeComponentChannel,
+        _component_id: ComponentId,
+        channel: &'a mut BlobVec,
+        channel_ticks: Option<&'a ChannelTicks>,
+        ticks: QueryTicks,
     ) -> Result<Self::ResultMut<'a>, ECSError> {
-        Ok(get_vec_from_channel::<A>(channel))
+        Ok(ColumnMut::Table(ComponentsMut {
+            // SAFETY: The channel for `A`'s `ComponentId` stores `A`s.
+            components: unsafe { channel.as_mut_slice() },
+            changed_ticks: &tracked(channel_ticks).changed,
+            change_tick: ticks.this_run,
+        }))
+    }
+
+    fn get_sparse_result<'a>(
+        sparse: &mut SparseContext<'a, '_>,
+        ticks: QueryTicks,
+    ) -> Result<Self::Result<'a>, ECSError> {
+        Ok(ColumnMut::Sparse(sparse.write::<A>(ticks.this_run)?))
+    }
+
+    fn get_sparse_result_mut<'a>(
+        sparse: &mut SparseContext<'a, '_>,
+        ticks: QueryTicks,
+    ) -> Result<Self::ResultMut<'a>, ECSError> {
+        Ok(ColumnMut::Sparse(sparse.write::<A>(ticks.this_run)?))
+    }
+}
+
+/// The [ComponentId] of a component borrowed by a query. [StorageKind::Tag] components store
+/// nothing to borrow, so borrowing one fails to compile.
+fn borrowed_component_id<A: ComponentTrait>() -> ComponentId {
+    const {
+        assert!(
+            !matches!(A::STORAGE, StorageKind::Tag),
+            "Tags can't be borrowed by queries. Use `With` instead."
+        )
+    };
+    A::component_id()
+}
+
+/// The [Filter] that matches the archetypes a parameter can be fetched from.
+/// [StorageKind::SparseSet] components aren't in any archetype so every archetype
+/// is a candidate and rows without the component are skipped.
+fn parameter_filter<A: QueryParameterTrait>() -> Filter {
+    Filter {
+        filter_type: match A::STORAGE {
+            StorageKind::Table => FilterType::With,
+            StorageKind::SparseSet => FilterType::Optional,
+            StorageKind::Tag => unreachable!("Tags are rejected by `borrowed_component_id`"),
+        },
+        component_id: A::get_component_id(),
     }
 }
 
@@ -123,12 +280,18 @@ pub trait QueryParametersTrait {
 
     fn get_filters(f: impl FnOnce(&[Filter]) -> Result<(), ECSError>) -> Result<(), ECSError>;
     fn get_result<'a>(
-        archetype_channels: &'a Vec<(ComponentId, Box<dyn ArchetypeComponentChannel>)>,
+        archetype_channels: &'a [(ComponentId, RwLock<BlobVec>)],
+        channel_ticks: &'a [Option<ChannelTicks>],
         matching_channels: &[Option<usize>],
+        sparse: &mut SparseContext<'a, '_>,
+        ticks: QueryTicks,
     ) -> Result<Self::Result<'a>, ECSError>;
     fn get_result_mut<'a>(
-        archetype_channels: &'a mut Vec<(ComponentId, Box<dyn ArchetypeComponentChannel>)>,
+        archetype_channels: &'a mut [(ComponentId, RwLock<BlobVec>)],
+        channel_ticks: &'a [Option<ChannelTicks>],
         matching_channels: &[Option<usize>],
+        sparse: &mut SparseContext<'a, '_>,
+        ticks: QueryTicks,
     ) -> Result<Self::ResultMut<'a>, ECSError>;
 }
 
@@ -138,22 +301,58 @@ impl<A: QueryParameterTrait> QueryParametersTrait for A {
     const FILTER_COUNT: usize = 1;
 
     fn get_filters(f: impl FnOnce(&[Filter]) -> Result<(), ECSError>) -> Result<(), ECSError> {
-        f(&[Filter {
-            filter_type: crate::archetype_lookup::FilterType::With,
-            component_id: A::get_component_id(),
-        }])
+        f(&[parameter_filter::<A>()])
     }
     fn get_result<'a>(
-        archetype_channels: &'a Vec<(ComponentId, Box<dyn ArchetypeComponentChannel>)>,
+        archetype_channels: &'a [(ComponentId, RwLock<BlobVec>)],
+        channel_ticks: &'a [Option<ChannelTicks>],
         matching_channels: &[Option<usize>],
+        sparse: &mut SparseContext<'a, '_>,
+        ticks: QueryTicks,
     ) -> Result<Self::Result<'a>, ECSError> {
-        A::get_result(&*archetype_channels[matching_channels[0].unwrap()].1)
+        get_parameter_result::<A>(
+            archetype_channels,
+            channel_ticks,
+            matching_channels[0],
+            sparse,
+            ticks,
+        )
     }
     fn get_result_mut<'a>(
-        archetype_channels: &'a mut Vec<(ComponentId, Box<dyn ArchetypeComponentChannel>)>,
+        archetype_channels: &'a mut [(ComponentId, RwLock<BlobVec>)],
+        channel_ticks: &'a [Option<ChannelTicks>],
         matching_channels: &[Option<usize>],
+        sparse: &mut SparseContext<'a, '_>,
+        ticks: QueryTicks,
     ) -> Result<Self::ResultMut<'a>, ECSError> {
-        A::get_result_mut(&mut *archetype_channels[matching_channels[0].unwrap()].1)
+        match matching_channels[0] {
+            Some(a) => A::get_result_mut(
+                archetype_channels[a].0,
+                archetype_channels[a].1.get_mut().unwrap(),
+                channel_ticks[a].as_ref(),
+                ticks,
+            ),
+            None => A::get_sparse_result_mut(sparse, ticks),
+        }
+    }
+}
+
+/// Fetches `A` from the channel at `channel_index`, or from its sparse storage if there's no channel.
+fn get_parameter_result<'a, A: QueryParameterTrait>(
+    archetype_channels: &'a [(ComponentId, RwLock<BlobVec>)],
+    channel_ticks: &'a [Option<ChannelTicks>],
+    channel_index: Option<usize>,
+    sparse: &mut SparseContext<'a, '_>,
+    ticks: QueryTicks,
+) -> Result<A::Result<'a>, ECSError> {
+    match channel_index {
+        Some(a) => A::get_result(
+            archetype_channels[a].0,
+            &archetype_channels[a].1,
+            channel_ticks[a].as_ref(),
+            ticks,
+        ),
+        None => A::get_sparse_result(sparse, ticks),
     }
 }
 
@@ -163,77 +362,179 @@ impl<A: QueryParameterTrait, B: QueryParameterTrait> QueryParametersTrait for (A
     const FILTER_COUNT: usize = 2;
 
     fn get_filters(f: impl FnOnce(&[Filter]) -> Result<(), ECSError>) -> Result<(), ECSError> {
-        f(&[
-            Filter {
-                filter_type: crate::archetype_lookup::FilterType::With,
-                component_id: A::get_component_id(),
-            },
-            Filter {
-                filter_type: crate::archetype_lookup::FilterType::With,
-                component_id: B::get_component_id(),
-            },
-        ])
+        f(&[parameter_filter::<A>(), parameter_filter::<B>()])
     }
     fn get_result<'a>(
-        archetype_channels: &'a Vec<(ComponentId, Box<dyn ArchetypeComponentChannel>)>,
+        archetype_channels: &'a [(ComponentId, RwLock<BlobVec>)],
+        channel_ticks: &'a [Option<ChannelTicks>],
         matching_channels: &[Option<usize>],
+        sparse: &mut SparseContext<'a, '_>,
+        ticks: QueryTicks,
     ) -> Result<Self::Result<'a>, ECSError> {
         Ok((
-            A::get_result(&*archetype_channels[matching_channels[0].unwrap()].1)?,
-            B::get_result(&*archetype_channels[matching_channels[1].unwrap()].1)?,
+            get_parameter_result::<A>(
+                archetype_channels,
+                channel_ticks,
+                matching_channels[0],
+                sparse,
+                ticks,
+            )?,
+            get_parameter_result::<B>(
+                archetype_channels,
+                channel_ticks,
+                matching_channels[1],
+                sparse,
+                ticks,
+            )?,
         ))
     }
     fn get_result_mut<'a>(
-        archetype_channels: &'a mut Vec<(ComponentId, Box<dyn ArchetypeComponentChannel>)>,
+        archetype_channels: &'a mut [(ComponentId, RwLock<BlobVec>)],
+        channel_ticks: &'a [Option<ChannelTicks>],
         matching_channels: &[Option<usize>],
+        sparse: &mut SparseContext<'a, '_>,
+        ticks: QueryTicks,
     ) -> Result<Self::ResultMut<'a>, ECSError> {
-        println!("MATCHING CHANNELS: {:?}", matching_channels);
-        // How can both channels be indexed safely?
-        todo!()
-        /*
-        Ok((
-            A::get_result_mut(&mut *archetype_channels[matching_channels[0].unwrap()].1)?,
-            B::get_result_mut(&mut *archetype_channels[matching_channels[1].unwrap()].1)?,
-        ))
-        */
+        match (matching_channels[0], matching_channels[1]) {
+            (Some(a), Some(b)) => {
+                let [channel_a, channel_b] = archetype_channels
+                    .get_disjoint_mut([a, b])
+                    .map_err(|_| ECSError::ComponentAlreadyBorrowed)?;
+                Ok((
+                    A::get_result_mut(
+                        channel_a.0,
+                        channel_a.1.get_mut().unwrap(),
+                        channel_ticks[a].as_ref(),
+                        ticks,
+                    )?,
+                    B::get_result_mut(
+                        channel_b.0,
+                        channel_b.1.get_mut().unwrap(),
+                        channel_ticks[b].as_ref(),
+                        ticks,
+                    )?,
+                ))
+            }
+            (Some(a), None) => Ok((
+                A::get_result_mut(
+                    archetype_channels[a].0,
+                    archetype_channels[a].1.get_mut().unwrap(),
+                    channel_ticks[a].as_ref(),
+                    ticks,
+                )?,
+                B::get_sparse_result_mut(sparse, ticks)?,
+            )),
+            (None, Some(b)) => Ok((
+                A::get_sparse_result_mut(sparse, ticks)?,
+                B::get_result_mut(
+                    archetype_channels[b].0,
+                    archetype_channels[b].1.get_mut().unwrap(),
+                    channel_ticks[b].as_ref(),
+                    ticks,
+                )?,
+            )),
+            (None, None) => Ok((
+                A::get_sparse_result_mut(sparse, ticks)?,
+                B::get_sparse_result_mut(sparse, ticks)?,
+            )),
+        }
     }
 }
 
-impl<PARAMETERS: QueryParametersTrait> QueryTrait for All<'_, PARAMETERS> {
-    type Result<'a> = AllBorrow<'a, PARAMETERS>;
+/// Calls `f` with the [Filter]s in `a` followed by the [Filter]s in `b`.
+fn concat_filters(
+    a: &[Filter],
+    b: &[Filter],
+    f: impl FnOnce(&[Filter]) -> Result<(), ECSError>,
+) -> Result<(), ECSError> {
+    match (a.first(), b.first()) {
+        (_, None) => f(a),
+        (None, _) => f(b),
+        (Some(&first), Some(_)) => {
+            let filter_count = a.len() + b.len();
+            assert!(
+                filter_count <= MAX_FILTER_COUNT,
+                "Queries can have at most {} parameters and filters",
+                MAX_FILTER_COUNT
+            );
+            let mut filters = [first; MAX_FILTER_COUNT];
+            filters[..a.len()].copy_from_slice(a);
+            filters[a.len()..filter_count].copy_from_slice(b);
+            f(&filters[..filter_count])
+        }
+    }
+}
 
-    fn get_result<'a>(world: &'a World) -> Result<Self::Result<'a>, ECSError> {
+/// Calls `f` with the [Filter]s for `PARAMETERS` followed by the [Filter]s for `FILTERS`.
+fn get_query_filters<PARAMETERS: QueryParametersTrait, FILTERS: QueryFilterTrait>(
+    f: impl FnOnce(&[Filter]) -> Result<(), ECSError>,
+) -> Result<(), ECSError> {
+    PARAMETERS::get_filters(|parameter_filters| {
+        FILTERS::get_filters(|filters| concat_filters(parameter_filters, filters, f))
+    })
+}
+
+impl<PARAMETERS: QueryParametersTrait, FILTERS: QueryFilterTrait> QueryTrait
+    for All<'_, PARAMETERS, FILTERS>
+{
+    type Result<'a> = AllBorrow<'a, PARAMETERS, FILTERS>;
+
+    fn get_result<'a>(world: &'a World, ticks: QueryTicks) -> Result<Self::Result<'a>, ECSError> {
         // I'd like to figure out how to avoid this `Vec::new()`
         // But probably it can't be done without unsafe.
         let mut borrow = Vec::new();
         let World {
             archetypes,
             archetype_lookup,
+            entity_manager,
+            sparse_storages,
             ..
         } = world;
         {
             let borrow = &mut borrow;
             let mut archetypes: &[Archetype] = archetypes;
+            let mut archetypes_offset = 0;
 
-            PARAMETERS::get_filters(move |filters| {
-                // Todo: I need to figure out how to get rid of this 8. It's incorrect.
-                let iter = archetype_lookup.matching_archetype_iter::<8>(filters);
+            get_query_filters::<PARAMETERS, FILTERS>(move |filters| {
+                let iter = archetype_lookup.matching_archetype_iter::<MAX_FILTER_COUNT>(filters);
                 for (archetype_index, matching_channels) in iter {
                     // We must use splitting borrows to appease the borrow checker.
                     // Fortunately the indices returned by `matching_archetype_iter` increase.
-                    let (left, right) = archetypes.split_at(archetype_index + 1);
+                    let (left, right) =
+                        archetypes.split_at(archetype_index + 1 - archetypes_offset);
                     archetypes = right;
+                    archetypes_offset = archetype_index + 1;
                     let Archetype {
                         channels,
+                        channel_ticks,
                         entity_indices,
-                    } = &left[0];
-                    let result = PARAMETERS::get_result(channels, &matching_channels)?;
+                    } = left.last().unwrap();
+                    let (parameter_channels, filter_channels) =
+                        matching_channels.split_at(PARAMETERS::FILTER_COUNT);
+                    let mut sparse_rows = None;
+                    let mut sparse = SparseContext {
+                        sparse_storages,
+                        entity_indices,
+                        rows: &mut sparse_rows,
+                    };
+                    let result = PARAMETERS::get_result(
+                        channels,
+                        channel_ticks,
+                        parameter_channels,
+                        &mut sparse,
+                        ticks,
+                    )?;
+                    let filter_result =
+                        FILTERS::get_result(channel_ticks, filter_channels, &mut sparse, ticks);
                     borrow.push((
                         ArchetypeInfo {
                             archetype_entities: entity_indices,
-                            archetype_index: archetype_index,
+                            entity_manager,
+                            archetype_index,
+                            sparse_rows,
                         },
                         result,
+                        filter_result,
                     ))
                 }
                 Ok(())
@@ -244,10 +545,15 @@ impl<PARAMETERS: QueryParametersTrait> QueryTrait for All<'_, PARAMETERS> {
     }
 }
 
-impl<PARAMETERS: QueryParametersTrait> MutQueryTrait for All<'_, PARAMETERS> {
-    type Result<'a> = All<'a, PARAMETERS>;
+impl<PARAMETERS: QueryParametersTrait, FILTERS: QueryFilterTrait> MutQueryTrait
+    for All<'_, PARAMETERS, FILTERS>
+{
+    type Result<'a> = All<'a, PARAMETERS, FILTERS>;
 
-    fn get_result_mut<'a>(world: &'a mut World) -> Result<Self::Result<'a>, ECSError> {
+    fn get_result_mut<'a>(
+        world: &'a mut World,
+        ticks: QueryTicks,
+    ) -> Result<Self::Result<'a>, ECSError> {
         // I'd like to figure out how to avoid this `Vec::new()`
         // But probably it can't be done without unsafe.
 
@@ -255,30 +561,57 @@ impl<PARAMETERS: QueryParametersTrait> MutQueryTrait for All<'_, PARAMETERS> {
         let World {
             archetypes,
             archetype_lookup,
+            entity_manager,
+            sparse_storages,
             ..
         } = world;
+        let sparse_storages = &*sparse_storages;
         {
             let borrow = &mut borrow;
             let mut archetypes: &mut [Archetype] = archetypes;
+            let mut archetypes_offset = 0;
 
-            PARAMETERS::get_filters(move |filters| {
-                let iter = archetype_lookup.matching_archetype_iter::<1>(filters);
+            get_query_filters::<PARAMETERS, FILTERS>(move |filters| {
+                let iter = archetype_lookup.matching_archetype_iter::<MAX_FILTER_COUNT>(filters);
                 for (archetype_index, matching_channels) in iter {
                     // We must use splitting borrows to appease the borrow checker.
                     // Fortunately the indices returned by `matching_archetype_iter` increase.
-                    let (left, right) = archetypes.split_at_mut(archetype_index + 1);
+                    let (left, right) =
+                        archetypes.split_at_mut(archetype_index + 1 - archetypes_offset);
                     archetypes = right;
+                    archetypes_offset = archetype_index + 1;
                     let Archetype {
                         channels,
+                        channel_ticks,
                         entity_indices,
-                    } = &mut left[0];
-                    let result = PARAMETERS::get_result_mut(channels, &matching_channels)?;
+                    } = left.last_mut().unwrap();
+                    let entity_indices: &Vec<usize> = entity_indices;
+                    let (parameter_channels, filter_channels) =
+                        matching_channels.split_at(PARAMETERS::FILTER_COUNT);
+                    let mut sparse_rows = None;
+                    let mut sparse = SparseContext {
+                        sparse_storages,
+                        entity_indices,
+                        rows: &mut sparse_rows,
+                    };
+                    let result = PARAMETERS::get_result_mut(
+                        channels,
+                        channel_ticks,
+                        parameter_channels,
+                        &mut sparse,
+                        ticks,
+                    )?;
+                    let filter_result =
+                        FILTERS::get_result(channel_ticks, filter_channels, &mut sparse, ticks);
                     borrow.push((
                         ArchetypeInfo {
                             archetype_entities: entity_indices,
-                            archetype_index: archetype_index,
+                            entity_manager,
+                            archetype_index,
+                            sparse_rows,
                         },
                         result,
+                        filter_result,
                     ))
                 }
                 Ok(())
@@ -292,7 +625,10 @@ impl<PARAMETERS: QueryParametersTrait> MutQueryTrait for All<'_, PARAMETERS> {
 impl<PARAMETERS: QueryParametersTrait> MutQueryTrait for One<'_, PARAMETERS> {
     type Result<'a> = One<'a, PARAMETERS>;
 
-    fn get_result_mut<'a>(world: &'a mut World) -> Result<Self::Result<'a>, ECSError> {
+    fn get_result_mut<'a>(
+        world: &'a mut World,
+        ticks: QueryTicks,
+    ) -> Result<Self::Result<'a>, ECSError> {
         // I'd like to figure out how to avoid this `Vec::new()`
         // But probably it can't be done without unsafe.
 
@@ -300,32 +636,53 @@ impl<PARAMETERS: QueryParametersTrait> MutQueryTrait for One<'_, PARAMETERS> {
         let World {
             archetypes,
             archetype_lookup,
+            entity_manager,
+            sparse_storages,
             ..
         } = world;
+        let sparse_storages = &*sparse_storages;
         {
             let borrow = &mut borrow;
             let mut archetypes: &mut [Archetype] = archetypes;
+            let mut archetypes_offset = 0;
 
             PARAMETERS::get_filters(move |filters| {
-                let iter = archetype_lookup.matching_archetype_iter::<1>(filters);
+                let iter = archetype_lookup.matching_archetype_iter::<MAX_FILTER_COUNT>(filters);
                 for (archetype_index, matching_channels) in iter {
                     // We must use splitting borrows to appease the borrow checker.
                     // Fortunately the indices returned by `matching_archetype_iter` increase.
-                    let (left, right) = archetypes.split_at_mut(archetype_index + 1);
+                    let (left, right) =
+                        archetypes.split_at_mut(archetype_index + 1 - archetypes_offset);
                     archetypes = right;
+                    archetypes_offset = archetype_index + 1;
                     let Archetype {
                         channels,
+                        channel_ticks,
                         entity_indices,
-                    } = &mut left[0];
-                    let result = PARAMETERS::get_result_mut(channels, &matching_channels)?;
-                    if !entity_indices.is_empty() {
-                        *borrow = Ok((
-                            ArchetypeInfo {
-                                archetype_entities: entity_indices,
-                                archetype_index: archetype_index,
-                            },
-                            result,
-                        ));
+                    } = left.last_mut().unwrap();
+                    let entity_indices: &Vec<usize> = entity_indices;
+                    let mut sparse_rows = None;
+                    let result = PARAMETERS::get_result_mut(
+                        channels,
+                        channel_ticks,
+                        &matching_channels,
+                        &mut SparseContext {
+                            sparse_storages,
+                            entity_indices,
+                            rows: &mut sparse_rows,
+                        },
+                        ticks,
+                    )?;
+                    let info = ArchetypeInfo {
+                        archetype_entities: entity_indices,
+                        entity_manager,
+                        archetype_index,
+                        sparse_rows,
+                    };
+                    if let Some(row) =
+                        (0..entity_indices.len()).find(|row| info.has_sparse_components(*row))
+                    {
+                        *borrow = Ok((info, result, row));
                         break;
                     }
                 }
@@ -333,6 +690,201 @@ impl<PARAMETERS: QueryParametersTrait> MutQueryTrait for One<'_, PARAMETERS> {
             })?
         }
 
-        Ok(One { borrow: borrow? })
+        let (info, result, row) = borrow?;
+        Ok(One {
+            borrow: (info, result),
+            row,
+        })
+    }
+}
+
+/// Filters which [Entity]s a query matches without borrowing their components.
+pub trait QueryFilterTrait {
+    type Result<'a>: RowFilterTrait;
+    const FILTER_COUNT: usize;
+
+    fn get_filters(f: impl FnOnce(&[Filter]) -> Result<(), ECSError>) -> Result<(), ECSError>;
+    fn get_result<'a>(
+        channel_ticks: &'a [Option<ChannelTicks>],
+        matching_channels: &[Option<usize>],
+        sparse: &mut SparseContext<'a, '_>,
+        ticks: QueryTicks,
+    ) -> Self::Result<'a>;
+}
+
+impl QueryFilterTrait for () {
+    type Result<'a> = ();
+    const FILTER_COUNT: usize = 0;
+
+    fn get_filters(f: impl FnOnce(&[Filter]) -> Result<(), ECSError>) -> Result<(), ECSError> {
+        f(&[])
+    }
+    fn get_result<'a>(
+        _channel_ticks: &'a [Option<ChannelTicks>],
+        _matching_channels: &[Option<usize>],
+        _sparse: &mut SparseContext<'a, '_>,
+        _ticks: QueryTicks,
+    ) -> Self::Result<'a> {
+    }
+}
+
+/// Matches components added since the query last ran with the same [QueryState].
+/// Without one every component matches.
+pub struct Added<T: ComponentTrait>(PhantomData<T>);
+
+/// Matches components added or mutably accessed since the query last ran with the same
+/// [QueryState]. Without one every component matches.
+pub struct Changed<T: ComponentTrait>(PhantomData<T>);
+
+impl<T: ComponentTrait> QueryFilterTrait for Added<T> {
+    type Result<'a> = TickFilter<'a>;
+    const FILTER_COUNT: usize = 1;
+
+    fn get_filters(f: impl FnOnce(&[Filter]) -> Result<(), ECSError>) -> Result<(), ECSError> {
+        f(&[tracked_component_filter::<T>()])
+    }
+    fn get_result<'a>(
+        channel_ticks: &'a [Option<ChannelTicks>],
+        matching_channels: &[Option<usize>],
+        sparse: &mut SparseContext<'a, '_>,
+        ticks: QueryTicks,
+    ) -> Self::Result<'a> {
+        match matching_channels[0].and_then(|channel| channel_ticks[channel].as_ref()) {
+            Some(channel_ticks) => TickFilter {
+                ticks: Some(&channel_ticks.added),
+                query_ticks: ticks,
+            },
+            None => {
+                sparse.filter_ticks::<T>(false, ticks);
+                TickFilter {
+                    ticks: None,
+                    query_ticks: ticks,
+                }
+            }
+        }
+    }
+}
+
+impl<T: ComponentTrait> QueryFilterTrait for Changed<T> {
+    type Result<'a> = TickFilter<'a>;
+    const FILTER_COUNT: usize = 1;
+
+    fn get_filters(f: impl FnOnce(&[Filter]) -> Result<(), ECSError>) -> Result<(), ECSError> {
+        f(&[tracked_component_filter::<T>()])
+    }
+    fn get_result<'a>(
+        channel_ticks: &'a [Option<ChannelTicks>],
+        matching_channels: &[Option<usize>],
+        sparse: &mut SparseContext<'a, '_>,
+        ticks: QueryTicks,
+    ) -> Self::Result<'a> {
+        match matching_channels[0].and_then(|channel| channel_ticks[channel].as_ref()) {
+            Some(channel_ticks) => TickFilter {
+                ticks: Some(&channel_ticks.changed),
+                query_ticks: ticks,
+            },
+            None => {
+                sparse.filter_ticks::<T>(true, ticks);
+                TickFilter {
+                    ticks: None,
+                    query_ticks: ticks,
+                }
+            }
+        }
+    }
+}
+
+/// The [Filter] for a filter on `T`. [StorageKind::SparseSet] components aren't in any
+/// archetype so every archetype is a candidate and the filter checks each row instead.
+fn component_filter<T: ComponentTrait>(filter_type: FilterType) -> Filter {
+    Filter {
+        filter_type: match T::STORAGE {
+            StorageKind::SparseSet => FilterType::Optional,
+            StorageKind::Table | StorageKind::Tag => filter_type,
+        },
+        component_id: T::component_id(),
+    }
+}
+
+/// The ticks of a channel borrowed as a parameter. Only tag channels have no ticks
+/// and [borrowed_component_id] keeps them out of queries.
+fn tracked(channel_ticks: Option<&ChannelTicks>) -> &ChannelTicks {
+    channel_ticks.expect("Tags can't be borrowed by queries")
+}
+
+/// The [Filter] for [Added] or [Changed] on `T`. [StorageKind::Tag] components have no change
+/// ticks, so filtering on their changes fails to compile.
+fn tracked_component_filter<T: ComponentTrait>() -> Filter {
+    const {
+        assert!(
+            !matches!(T::STORAGE, StorageKind::Tag),
+            "Tags' changes aren't tracked. Use `With` instead."
+        )
+    };
+    component_filter::<T>(FilterType::With)
+}
+
+/// Matches [Entity]s that have a `T` without borrowing it.
+pub struct With<T: ComponentTrait>(PhantomData<T>);
+
+/// Matches [Entity]s that don't have a `T`.
+pub struct Without<T: ComponentTrait>(PhantomData<T>);
+
+impl<T: ComponentTrait> QueryFilterTrait for With<T> {
+    type Result<'a> = ();
+    const FILTER_COUNT: usize = 1;
+
+    fn get_filters(f: impl FnOnce(&[Filter]) -> Result<(), ECSError>) -> Result<(), ECSError> {
+        f(&[component_filter::<T>(FilterType::With)])
+    }
+    fn get_result<'a>(
+        _channel_ticks: &'a [Option<ChannelTicks>],
+        _matching_channels: &[Option<usize>],
+        sparse: &mut SparseContext<'a, '_>,
+        _ticks: QueryTicks,
+    ) -> Self::Result<'a> {
+        if T::STORAGE == StorageKind::SparseSet {
+            sparse.filter::<T>(true);
+        }
+    }
+}
+
+impl<T: ComponentTrait> QueryFilterTrait for Without<T> {
+    type Result<'a> = ();
+    const FILTER_COUNT: usize = 1;
+
+    fn get_filters(f: impl FnOnce(&[Filter]) -> Result<(), ECSError>) -> Result<(), ECSError> {
+        f(&[component_filter::<T>(FilterType::Without)])
+    }
+    fn get_result<'a>(
+        _channel_ticks: &'a [Option<ChannelTicks>],
+        _matching_channels: &[Option<usize>],
+        sparse: &mut SparseContext<'a, '_>,
+        _ticks: QueryTicks,
+    ) -> Self::Result<'a> {
+        if T::STORAGE == StorageKind::SparseSet {
+            sparse.filter::<T>(false);
+        }
+    }
+}
+
+impl<A: QueryFilterTrait, B: QueryFilterTrait> QueryFilterTrait for (A, B) {
+    type Result<'a> = (A::Result<'a>, B::Result<'a>);
+    const FILTER_COUNT: usize = A::FILTER_COUNT + B::FILTER_COUNT;
+
+    fn get_filters(f: impl FnOnce(&[Filter]) -> Result<(), ECSError>) -> Result<(), ECSError> {
+        A::get_filters(|a| B::get_filters(|b| concat_filters(a, b, f)))
+    }
+    fn get_result<'a>(
+        channel_ticks: &'a [Option<ChannelTicks>],
+        matching_channels: &[Option<usize>],
+        sparse: &mut SparseContext<'a, '_>,
+        ticks: QueryTicks,
+    ) -> Self::Result<'a> {
+        let (a, b) = matching_channels.split_at(A::FILTER_COUNT);
+        (
+            A::get_result(channel_ticks, a, sparse, ticks),
+            B::get_result(channel_ticks, b, sparse, ticks),
+        )
     }
 }
@@ -1,137 +1,385 @@
-use std::sync::{RwLockReadGuard, RwLockWriteGuard};
+use std::{
+    ops::{Deref, DerefMut},
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        RwLockReadGuard,
+    },
+};
 
 use crate::*;
 
-pub type QueryBorrowIter<'a, 'b, PARAMETERS> = std::iter::FlatMap<
+pub type QueryBorrowIter<'a, 'b, PARAMETERS, FILTERS> = std::iter::FlatMap<
     std::slice::Iter<
         'b,
         (
             ArchetypeInfo<'a>,
             <PARAMETERS as QueryParametersTrait>::Result<'a>,
+            <FILTERS as QueryFilterTrait>::Result<'a>,
         ),
     >,
-    <<PARAMETERS as QueryParametersTrait>::Result<'a> as GetIteratorsTrait>::Iterator<'b>,
+    FilteredIterator<
+        'b,
+        <<PARAMETERS as QueryParametersTrait>::Result<'a> as GetIteratorsTrait>::Iterator<'b>,
+        <FILTERS as QueryFilterTrait>::Result<'a>,
+    >,
     fn(
         &'b (
             ArchetypeInfo<'a>,
             <PARAMETERS as QueryParametersTrait>::Result<'a>,
+            <FILTERS as QueryFilterTrait>::Result<'a>,
         ),
-    )
-        -> <<PARAMETERS as QueryParametersTrait>::Result<'a> as GetIteratorsTrait>::Iterator<'b>,
+    ) -> FilteredIterator<
+        'b,
+        <<PARAMETERS as QueryParametersTrait>::Result<'a> as GetIteratorsTrait>::Iterator<'b>,
+        <FILTERS as QueryFilterTrait>::Result<'a>,
+    >,
 >;
 
-pub type QueryBorrowIterMut<'a, 'b, PARAMETERS> = std::iter::FlatMap<
+pub type QueryBorrowIterMut<'a, 'b, PARAMETERS, FILTERS> = std::iter::FlatMap<
     std::slice::IterMut<
         'b,
         (
             ArchetypeInfo<'a>,
             <PARAMETERS as QueryParametersTrait>::Result<'a>,
+            <FILTERS as QueryFilterTrait>::Result<'a>,
         ),
     >,
-    <<PARAMETERS as QueryParametersTrait>::Result<'a> as GetIteratorsTrait>::IteratorMut<'b>,
+    FilteredIterator<
+        'b,
+        <<PARAMETERS as QueryParametersTrait>::Result<'a> as GetIteratorsTrait>::IteratorMut<'b>,
+        <FILTERS as QueryFilterTrait>::Result<'a>,
+    >,
     fn(
         &'b mut (
             ArchetypeInfo<'a>,
             <PARAMETERS as QueryParametersTrait>::Result<'a>,
+            <FILTERS as QueryFilterTrait>::Result<'a>,
         ),
-    )
-        -> <<PARAMETERS as QueryParametersTrait>::Result<'a> as GetIteratorsTrait>::IteratorMut<'b>,
+    ) -> FilteredIterator<
+        'b,
+        <<PARAMETERS as QueryParametersTrait>::Result<'a> as GetIteratorsTrait>::IteratorMut<'b>,
+        <FILTERS as QueryFilterTrait>::Result<'a>,
+    >,
 >;
 
-pub type QueryIter<'a, 'b, PARAMETERS> = std::iter::FlatMap<
+pub type QueryIter<'a, 'b, PARAMETERS, FILTERS> = std::iter::FlatMap<
     std::slice::Iter<
         'b,
         (
             ArchetypeInfo<'a>,
             <PARAMETERS as QueryParametersTrait>::ResultMut<'a>,
+            <FILTERS as QueryFilterTrait>::Result<'a>,
         ),
     >,
-    <<PARAMETERS as QueryParametersTrait>::ResultMut<'a> as GetIteratorsTrait>::Iterator<'b>,
+    FilteredIterator<
+        'b,
+        <<PARAMETERS as QueryParametersTrait>::ResultMut<'a> as GetIteratorsTrait>::Iterator<'b>,
+        <FILTERS as QueryFilterTrait>::Result<'a>,
+    >,
     fn(
         &'b (
             ArchetypeInfo,
             <PARAMETERS as QueryParametersTrait>::ResultMut<'a>,
+            <FILTERS as QueryFilterTrait>::Result<'a>,
         ),
-    )
-        -> <<PARAMETERS as QueryParametersTrait>::ResultMut<'a> as GetIteratorsTrait>::Iterator<'b>,
+    ) -> FilteredIterator<
+        'b,
+        <<PARAMETERS as QueryParametersTrait>::ResultMut<'a> as GetIteratorsTrait>::Iterator<'b>,
+        <FILTERS as QueryFilterTrait>::Result<'a>,
+    >,
 >;
 
-pub type QueryIterMut<'a, 'b, PARAMETERS> = std::iter::FlatMap<
+pub type QueryIterMut<'a, 'b, PARAMETERS, FILTERS> = std::iter::FlatMap<
     std::slice::IterMut<
         'b,
         (
             ArchetypeInfo<'a>,
             <PARAMETERS as QueryParametersTrait>::ResultMut<'a>,
+            <FILTERS as QueryFilterTrait>::Result<'a>,
         ),
     >,
-    <<PARAMETERS as QueryParametersTrait>::ResultMut<'a> as GetIteratorsTrait>::IteratorMut<'b>,
+    FilteredIterator<
+        'b,
+        <<PARAMETERS as QueryParametersTrait>::ResultMut<'a> as GetIteratorsTrait>::IteratorMut<'b>,
+        <FILTERS as QueryFilterTrait>::Result<'a>,
+    >,
     fn(
         &'b mut (
             ArchetypeInfo,
             <PARAMETERS as QueryParametersTrait>::ResultMut<'a>,
+            <FILTERS as QueryFilterTrait>::Result<'a>,
         ),
-    )
-        -> <<PARAMETERS as QueryParametersTrait>::ResultMut<'a> as GetIteratorsTrait>::IteratorMut<
+    ) -> FilteredIterator<
         'b,
+        <<PARAMETERS as QueryParametersTrait>::ResultMut<'a> as GetIteratorsTrait>::IteratorMut<'b>,
+        <FILTERS as QueryFilterTrait>::Result<'a>,
     >,
 >;
 
-impl<'a, 'b, PARAMETERS: QueryParametersTrait> IntoIterator for &'b All<'a, PARAMETERS> {
-    type Item = <QueryIter<'a, 'b, PARAMETERS> as Iterator>::Item;
-    type IntoIter = QueryIter<'a, 'b, PARAMETERS>;
+impl<'a, 'b, PARAMETERS: QueryParametersTrait, FILTERS: QueryFilterTrait> IntoIterator
+    for &'b All<'a, PARAMETERS, FILTERS>
+{
+    type Item = <QueryIter<'a, 'b, PARAMETERS, FILTERS> as Iterator>::Item;
+    type IntoIter = QueryIter<'a, 'b, PARAMETERS, FILTERS>;
     fn into_iter(self) -> Self::IntoIter {
-        self.borrow.iter().flat_map(|v| v.1.get_iterator())
+        self.borrow
+            .iter()
+            .flat_map(|v| FilteredIterator::new(v.1.get_iterator(), &v.2, &v.0))
     }
 }
 
-impl<'a, 'b, PARAMETERS: QueryParametersTrait> IntoIterator for &'b mut All<'a, PARAMETERS> {
-    type Item = <QueryIterMut<'a, 'b, PARAMETERS> as Iterator>::Item;
-    type IntoIter = QueryIterMut<'a, 'b, PARAMETERS>;
+impl<'a, 'b, PARAMETERS: QueryParametersTrait, FILTERS: QueryFilterTrait> IntoIterator
+    for &'b mut All<'a, PARAMETERS, FILTERS>
+{
+    type Item = <QueryIterMut<'a, 'b, PARAMETERS, FILTERS> as Iterator>::Item;
+    type IntoIter = QueryIterMut<'a, 'b, PARAMETERS, FILTERS>;
     fn into_iter(self) -> Self::IntoIter {
-        self.borrow.iter_mut().flat_map(|v| v.1.get_iterator_mut())
+        self.borrow
+            .iter_mut()
+            .flat_map(|v| FilteredIterator::new(v.1.get_iterator_mut(), &v.2, &v.0))
     }
 }
 // The type of iterator returned is relatively complex.
 // I'm not even sure if it can be expressed in a way to implement IntoIterator.
 // Is there a better approach?
-impl<'a, PARAMETERS: QueryParametersTrait> All<'a, PARAMETERS> {
-    pub fn iter<'b>(&'b self) -> QueryIter<'a, 'b, PARAMETERS> {
+impl<'a, PARAMETERS: QueryParametersTrait, FILTERS: QueryFilterTrait> All<'a, PARAMETERS, FILTERS> {
+    pub fn iter<'b>(&'b self) -> QueryIter<'a, 'b, PARAMETERS, FILTERS> {
         self.into_iter()
     }
 
-    pub fn iter_mut<'b>(&'b mut self) -> QueryIterMut<'a, 'b, PARAMETERS> {
+    pub fn iter_mut<'b>(&'b mut self) -> QueryIterMut<'a, 'b, PARAMETERS, FILTERS> {
         self.into_iter()
     }
+
+    /// Iterates the [Entity] of each result in the same order as [All::iter].
+    pub fn entities<'b>(
+        &'b self,
+    ) -> impl Iterator<Item = Entity> + use<'a, 'b, PARAMETERS, FILTERS> {
+        self.borrow.iter().flat_map(|v| v.0.entities(&v.2))
+    }
 }
 
-pub struct AllBorrow<'a, PARAMETERS: QueryParametersTrait> {
-    pub(crate) borrow: Vec<(ArchetypeInfo<'a>, PARAMETERS::Result<'a>)>,
+pub struct AllBorrow<'a, PARAMETERS: QueryParametersTrait, FILTERS: QueryFilterTrait = ()> {
+    pub(crate) borrow: Vec<(
+        ArchetypeInfo<'a>,
+        PARAMETERS::Result<'a>,
+        FILTERS::Result<'a>,
+    )>,
 }
 
-impl<'a, 'b, PARAMETERS: QueryParametersTrait> IntoIterator for &'b AllBorrow<'a, PARAMETERS> {
-    type Item = <QueryBorrowIter<'a, 'b, PARAMETERS> as Iterator>::Item;
-    type IntoIter = QueryBorrowIter<'a, 'b, PARAMETERS>;
+impl<'a, 'b, PARAMETERS: QueryParametersTrait, FILTERS: QueryFilterTrait> IntoIterator
+    for &'b AllBorrow<'a, PARAMETERS, FILTERS>
+{
+    type Item = <QueryBorrowIter<'a, 'b, PARAMETERS, FILTERS> as Iterator>::Item;
+    type IntoIter = QueryBorrowIter<'a, 'b, PARAMETERS, FILTERS>;
     fn into_iter(self) -> Self::IntoIter {
-        self.borrow.iter().flat_map(|v| v.1.get_iterator())
+        self.borrow
+            .iter()
+            .flat_map(|v| FilteredIterator::new(v.1.get_iterator(), &v.2, &v.0))
     }
 }
 
-impl<'a, 'b, PARAMETERS: QueryParametersTrait> IntoIterator for &'b mut AllBorrow<'a, PARAMETERS> {
-    type Item = <QueryBorrowIterMut<'a, 'b, PARAMETERS> as Iterator>::Item;
-    type IntoIter = QueryBorrowIterMut<'a, 'b, PARAMETERS>;
+impl<'a, 'b, PARAMETERS: QueryParametersTrait, FILTERS: QueryFilterTrait> IntoIterator
+    for &'b mut AllBorrow<'a, PARAMETERS, FILTERS>
+{
+    type Item = <QueryBorrowIterMut<'a, 'b, PARAMETERS, FILTERS> as Iterator>::Item;
+    type IntoIter = QueryBorrowIterMut<'a, 'b, PARAMETERS, FILTERS>;
     fn into_iter(self) -> Self::IntoIter {
-        self.borrow.iter_mut().flat_map(|v| v.1.get_iterator_mut())
+        self.borrow
+            .iter_mut()
+            .flat_map(|v| FilteredIterator::new(v.1.get_iterator_mut(), &v.2, &v.0))
     }
 }
 
-impl<'a, PARAMETERS: QueryParametersTrait> AllBorrow<'a, PARAMETERS> {
-    pub fn iter<'b>(&'b self) -> QueryBorrowIter<'a, 'b, PARAMETERS> {
+impl<'a, PARAMETERS: QueryParametersTrait, FILTERS: QueryFilterTrait>
+    AllBorrow<'a, PARAMETERS, FILTERS>
+{
+    pub fn iter<'b>(&'b self) -> QueryBorrowIter<'a, 'b, PARAMETERS, FILTERS> {
         self.into_iter()
     }
 
-    pub fn iter_mut<'b>(&'b mut self) -> QueryBorrowIterMut<'a, 'b, PARAMETERS> {
+    pub fn iter_mut<'b>(&'b mut self) -> QueryBorrowIterMut<'a, 'b, PARAMETERS, FILTERS> {
         self.into_iter()
     }
+
+    /// Iterates the [Entity] of each result in the same order as [AllBorrow::iter].
+    pub fn entities<'b>(
+        &'b self,
+    ) -> impl Iterator<Item = Entity> + use<'a, 'b, PARAMETERS, FILTERS> {
+        self.borrow.iter().flat_map(|v| v.0.entities(&v.2))
+    }
+}
+
+impl ArchetypeInfo<'_> {
+    /// Iterates the [Entity] of each row that passes `filter`.
+    fn entities<'b>(
+        &'b self,
+        filter: &'b impl RowFilterTrait,
+    ) -> impl Iterator<Item = Entity> + 'b {
+        self.archetype_entities
+            .iter()
+            .enumerate()
+            .filter(move |(row, _)| self.has_sparse_components(*row) && filter.matches(*row))
+            .map(move |(_, index)| self.entity_manager.get_entity(*index))
+    }
+}
+
+/// Decides which rows of an [Archetype] a query returns.
+pub trait RowFilterTrait {
+    fn matches(&self, index: usize) -> bool;
+}
+
+impl RowFilterTrait for () {
+    fn matches(&self, _index: usize) -> bool {
+        true
+    }
+}
+
+impl<A: RowFilterTrait, B: RowFilterTrait> RowFilterTrait for (A, B) {
+    fn matches(&self, index: usize) -> bool {
+        self.0.matches(index) && self.1.matches(index)
+    }
+}
+
+/// Matches rows whose tick changed since the query last ran.
+pub struct TickFilter<'a> {
+    /// `None` for a [StorageKind::SparseSet] component, whose ticks are checked by
+    /// [SparseContext::filter_ticks] instead.
+    pub(crate) ticks: Option<&'a [AtomicU32]>,
+    pub(crate) query_ticks: QueryTicks,
+}
+
+impl RowFilterTrait for TickFilter<'_> {
+    fn matches(&self, index: usize) -> bool {
+        match self.ticks {
+            Some(ticks) => self
+                .query_ticks
+                .is_changed(ticks[index].load(Ordering::Relaxed)),
+            None => true,
+        }
+    }
+}
+
+/// Iterates the rows of an [Archetype] that pass a [RowFilterTrait].
+/// Rows that don't match are skipped with `nth` so they aren't marked as changed.
+pub struct FilteredIterator<'b, ITERATOR, FILTER> {
+    iterator: ITERATOR,
+    filter: &'b FILTER,
+    info: &'b ArchetypeInfo<'b>,
+    next_row: usize,
+    /// The row `iterator` will return next.
+    iterator_row: usize,
+    len: usize,
+}
+
+impl<'b, ITERATOR, FILTER> FilteredIterator<'b, ITERATOR, FILTER> {
+    pub(crate) fn new(iterator: ITERATOR, filter: &'b FILTER, info: &'b ArchetypeInfo) -> Self {
+        Self {
+            iterator,
+            filter,
+            info,
+            next_row: 0,
+            iterator_row: 0,
+            len: info.archetype_entities.len(),
+        }
+    }
+}
+
+impl<ITERATOR: Iterator, FILTER: RowFilterTrait> Iterator
+    for FilteredIterator<'_, ITERATOR, FILTER>
+{
+    type Item = ITERATOR::Item;
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.next_row < self.len {
+            let row = self.next_row;
+            self.next_row += 1;
+            if self.info.has_sparse_components(row) && self.filter.matches(row) {
+                let skip = row - self.iterator_row;
+                self.iterator_row = row + 1;
+                return self.iterator.nth(skip);
+            }
+        }
+        None
+    }
+}
+
+/// Mutable access to a channel's components.
+/// Components are marked as changed when they're accessed mutably.
+pub struct ComponentsMut<'a, COMPONENTS> {
+    pub(crate) components: COMPONENTS,
+    pub(crate) changed_ticks: &'a [AtomicU32],
+    pub(crate) change_tick: u32,
+}
+
+pub struct ComponentsIterMut<'a, T> {
+    components: std::slice::IterMut<'a, T>,
+    changed_ticks: std::slice::Iter<'a, AtomicU32>,
+    change_tick: u32,
+}
+
+impl<'a, T> Iterator for ComponentsIterMut<'a, T> {
+    type Item = &'a mut T;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.nth(0)
+    }
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        let component = self.components.nth(n)?;
+        self.changed_ticks
+            .nth(n)
+            .unwrap()
+            .store(self.change_tick, Ordering::Relaxed);
+        Some(component)
+    }
+}
+
+/// The components of a query parameter in one [Archetype].
+/// [StorageKind::SparseSet] components are locked individually and are `None` for rows
+/// without the component. Those rows are skipped by [FilteredIterator].
+pub enum Column<'a, T, COMPONENTS> {
+    Table(COMPONENTS),
+    Sparse(Vec<Option<RwLockReadGuard<'a, T>>>),
+}
+
+/// Like [Column] but for mutable parameters.
+pub enum ColumnMut<'a, T, COMPONENTS> {
+    Table(ComponentsMut<'a, COMPONENTS>),
+    Sparse(Vec<Option<SparseWriteGuard<'a, T>>>),
+}
+
+pub enum ColumnIter<'b, T, GUARD> {
+    Table(std::slice::Iter<'b, T>),
+    Sparse(std::slice::Iter<'b, Option<GUARD>>),
+}
+
+impl<'b, T, GUARD: Deref<Target = T>> Iterator for ColumnIter<'b, T, GUARD> {
+    type Item = &'b T;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.nth(0)
+    }
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        match self {
+            ColumnIter::Table(iter) => iter.nth(n),
+            ColumnIter::Sparse(iter) => Some(iter.nth(n)?.as_deref().unwrap()),
+        }
+    }
+}
+
+pub enum ColumnIterMut<'b, T, GUARD> {
+    Table(ComponentsIterMut<'b, T>),
+    Sparse(std::slice::IterMut<'b, Option<GUARD>>),
+}
+
+impl<'b, T, GUARD: DerefMut<Target = T>> Iterator for ColumnIterMut<'b, T, GUARD> {
+    type Item = &'b mut T;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.nth(0)
+    }
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        match self {
+            ColumnIterMut::Table(iter) => iter.nth(n),
+            ColumnIterMut::Sparse(iter) => Some(iter.nth(n)?.as_deref_mut().unwrap()),
+        }
+    }
 }
 
 pub trait GetIteratorsTrait {
@@ -151,118 +399,124 @@ pub trait GetIteratorsTrait {
     ) -> <Self::IteratorMut<'a> as Iterator>::Item;
 }
 
-impl<T: ComponentTrait> GetIteratorsTrait for &'_ [T] {
-    type Iterator<'b> = std::slice::Iter<'b, T>  where Self: 'b;
-    type IteratorMut<'b> = std::slice::Iter<'b, T>  where Self: 'b;
-
-    fn get_iterator<'b>(&'b self) -> Self::Iterator<'b> {
-        self.iter()
-    }
-    fn get_iterator_mut<'b>(&'b mut self) -> Self::IteratorMut<'b> {
-        self.iter()
-    }
-    fn get_component<'b>(&'b self, index: usize) -> <Self::Iterator<'b> as Iterator>::Item {
-        &self[index]
-    }
-    fn get_component_mut<'b>(
-        &'b mut self,
-        index: usize,
-    ) -> <Self::IteratorMut<'b> as Iterator>::Item {
-        &self[index]
-    }
+/// The target of a relation for each [Entity] in an [Archetype]. See [Related].
+pub struct RelationTargets {
+    pub(crate) target: Entity,
 }
 
-impl<T: ComponentTrait> GetIteratorsTrait for &'_ mut [T] {
-    type Iterator<'b> = std::slice::Iter<'b, T>  where Self: 'b;
-    type IteratorMut<'b> = std::slice::IterMut<'b, T>  where Self: 'b;
-
-    fn get_iterator<'b>(&'b self) -> Self::Iterator<'b> {
-        self.iter()
-    }
-    fn get_iterator_mut<'b>(&'b mut self) -> Self::IteratorMut<'b> {
-        self.iter_mut()
-    }
-    fn get_component<'b>(&'b self, index: usize) -> <Self::Iterator<'b> as Iterator>::Item {
-        &self[index]
-    }
-    fn get_component_mut<'b>(
-        &'b mut self,
-        index: usize,
-    ) -> <Self::IteratorMut<'b> as Iterator>::Item {
-        &mut self[index]
-    }
-}
-
-impl<T: ComponentTrait> GetIteratorsTrait for RwLockReadGuard<'_, Vec<T>> {
-    type Iterator<'b> = std::slice::Iter<'b, T>  where Self: 'b;
-    type IteratorMut<'b> = std::slice::Iter<'b, T>  where Self: 'b;
+impl GetIteratorsTrait for RelationTargets {
+    type Iterator<'b>
+        = std::iter::Repeat<Entity>
+    where
+        Self: 'b;
+    type IteratorMut<'b>
+        = std::iter::Repeat<Entity>
+    where
+        Self: 'b;
 
     fn get_iterator<'b>(&'b self) -> Self::Iterator<'b> {
-        self.iter()
+        std::iter::repeat(self.target)
     }
     fn get_iterator_mut<'b>(&'b mut self) -> Self::IteratorMut<'b> {
-        self.iter()
+        std::iter::repeat(self.target)
     }
-    fn get_component<'b>(&'b self, index: usize) -> <Self::Iterator<'b> as Iterator>::Item {
-        &self[index]
+    fn get_component<'b>(&'b self, _index: usize) -> <Self::Iterator<'b> as Iterator>::Item {
+        self.target
     }
     fn get_component_mut<'b>(
         &'b mut self,
-        index: usize,
+        _index: usize,
     ) -> <Self::IteratorMut<'b> as Iterator>::Item {
-        &self[index]
+        self.target
     }
 }
 
-impl<T: ComponentTrait> GetIteratorsTrait for RwLockWriteGuard<'_, Vec<T>> {
-    type Iterator<'b> = std::slice::Iter<'b, T> where Self: 'b;
-    type IteratorMut<'b> = std::slice::Iter<'b, T> where Self: 'b;
+impl<'a, T: ComponentTrait, COMPONENTS: Deref<Target = [T]>> GetIteratorsTrait
+    for Column<'a, T, COMPONENTS>
+{
+    type Iterator<'b>
+        = ColumnIter<'b, T, RwLockReadGuard<'a, T>>
+    where
+        Self: 'b;
+    type IteratorMut<'b>
+        = ColumnIter<'b, T, RwLockReadGuard<'a, T>>
+    where
+        Self: 'b;
 
     fn get_iterator<'b>(&'b self) -> Self::Iterator<'b> {
-        self.iter()
+        match self {
+            Column::Table(components) => ColumnIter::Table(components.iter()),
+            Column::Sparse(components) => ColumnIter::Sparse(components.iter()),
+        }
     }
     fn get_iterator_mut<'b>(&'b mut self) -> Self::IteratorMut<'b> {
-        self.iter()
+        self.get_iterator()
     }
     fn get_component<'b>(&'b self, index: usize) -> <Self::Iterator<'b> as Iterator>::Item {
-        &self[index]
+        match self {
+            Column::Table(components) => &components[index],
+            Column::Sparse(components) => components[index].as_deref().unwrap(),
+        }
     }
     fn get_component_mut<'b>(
         &'b mut self,
         index: usize,
     ) -> <Self::IteratorMut<'b> as Iterator>::Item {
-        &self[index]
+        self.get_component(index)
     }
 }
 
-impl<A: GetIteratorsTrait, B: GetIteratorsTrait> GetIteratorsTrait for (A, B) {
-    type Iterator<'b> = std::iter::Zip<A::Iterator<'b>, B::Iterator<'b>> where A: 'b, B: 'b;
-    type IteratorMut<'b> = std::iter::Zip<A::IteratorMut<'b>, B::IteratorMut<'b>> where A: 'b, B: 'b;
+impl<'a, T: ComponentTrait, COMPONENTS: DerefMut<Target = [T]>> GetIteratorsTrait
+    for ColumnMut<'a, T, COMPONENTS>
+{
+    type Iterator<'b>
+        = ColumnIter<'b, T, SparseWriteGuard<'a, T>>
+    where
+        Self: 'b;
+    type IteratorMut<'b>
+        = ColumnIterMut<'b, T, SparseWriteGuard<'a, T>>
+    where
+        Self: 'b;
 
     fn get_iterator<'b>(&'b self) -> Self::Iterator<'b> {
-        self.0.get_iterator().zip(self.1.get_iterator())
+        match self {
+            ColumnMut::Table(table) => ColumnIter::Table(table.components.iter()),
+            ColumnMut::Sparse(components) => ColumnIter::Sparse(components.iter()),
+        }
     }
     fn get_iterator_mut<'b>(&'b mut self) -> Self::IteratorMut<'b> {
-        self.0.get_iterator_mut().zip(self.1.get_iterator_mut())
+        match self {
+            ColumnMut::Table(table) => ColumnIterMut::Table(ComponentsIterMut {
+                components: table.components.iter_mut(),
+                changed_ticks: table.changed_ticks.iter(),
+                change_tick: table.change_tick,
+            }),
+            ColumnMut::Sparse(components) => ColumnIterMut::Sparse(components.iter_mut()),
+        }
     }
     fn get_component<'b>(&'b self, index: usize) -> <Self::Iterator<'b> as Iterator>::Item {
-        (self.0.get_component(index), self.1.get_component(index))
+        match self {
+            ColumnMut::Table(table) => &table.components[index],
+            ColumnMut::Sparse(components) => components[index].as_deref().unwrap(),
+        }
     }
     fn get_component_mut<'b>(
         &'b mut self,
         index: usize,
     ) -> <Self::IteratorMut<'b> as Iterator>::Item {
-        (
-            self.0.get_component_mut(index),
-            self.1.get_component_mut(index),
-        )
+        match self {
+            ColumnMut::Table(table) => {
+                table.changed_ticks[index].store(table.change_tick, Ordering::Relaxed);
+                &mut table.components[index]
+            }
+            ColumnMut::Sparse(components) => components[index].as_deref_mut().unwrap(),
+        }
     }
 }
 
 macro_rules! query_iterator_impls {
-    // These first two cases are implemented manually so skip them in this macro.
+    // The single parameter case is implemented manually so skip it in this macro.
     ($count: tt, ($index0: tt, $tuple0:ident)) => {};
-    ($count: tt, ($index0: tt, $tuple0:ident), ($index1: tt, $tuple1:ident)) => {};
     ($count: tt, $( ($index: tt, $tuple:ident) ),* ) => {
         #[allow(unused)]
         impl<$( $tuple: GetIteratorsTrait,)*> GetIteratorsTrait for ($( $tuple,)*) {
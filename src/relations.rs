@@ -0,0 +1,102 @@
+use std::marker::PhantomData;
+
+use crate::*;
+
+/// A query parameter that yields the target of an [Entity]'s `R` relation.
+/// If an [Entity] has multiple `R` relations the target that sorts first is returned.
+pub struct Related<R: ComponentTrait>(PhantomData<R>);
+
+impl<R: ComponentTrait> QueryParameterTrait for Related<R> {
+    type Result<'a> = RelationTargets;
+    type ResultMut<'a> = RelationTargets;
+
+    fn get_component_id() -> ComponentId {
+        ComponentId::wildcard_pair::<R>()
+    }
+
+    fn get_result<'a>(
+        component_id: ComponentId,
+        _channel: &'a std::sync::RwLock<BlobVec>,
+        _channel_ticks: Option<&'a ChannelTicks>,
+        _ticks: QueryTicks,
+    ) -> Result<Self::Result<'a>, ECSError> {
+        // Every `Entity` in an `Archetype` has the same target.
+        Ok(RelationTargets {
+            target: component_id.target().unwrap(),
+        })
+    }
+
+    fn get_result_mut<'a>(
+        component_id: ComponentId,
+        _channel: &'a mut BlobVec,
+        _channel_ticks: Option<&'a ChannelTicks>,
+        _ticks: QueryTicks,
+    ) -> Result<Self::ResultMut<'a>, ECSError> {
+        Ok(RelationTargets {
+            target: component_id.target().unwrap(),
+        })
+    }
+}
+
+impl World {
+    /// Adds the relation `R` from `source` to `target`.
+    /// The pair of `R` and `target` becomes part of `source`'s [Archetype] so
+    /// [World::related_to] can find every [Entity] related to `target` without scanning.
+    pub fn relate<R: ComponentTrait + Default>(
+        &mut self,
+        source: Entity,
+        target: Entity,
+    ) -> Result<(), ECSError> {
+        self.flush_reserved_entities();
+        self.entity_manager.get_entity_location(target)?;
+        let result = self.add_components_inner(
+            source,
+            &mut [(&mut Some(R::default()), ComponentId::pair::<R>(target))],
+        );
+        self.apply_hook_commands();
+        result
+    }
+
+    /// Removes the relation `R` from `source` to `target`.
+    pub fn unrelate<R: ComponentTrait>(
+        &mut self,
+        source: Entity,
+        target: Entity,
+    ) -> Result<R, ECSError> {
+        let mut relation = None::<R>;
+        let result = self.remove_components_inner(
+            source,
+            &mut [(&mut relation, ComponentId::pair::<R>(target))],
+        );
+        self.apply_hook_commands();
+        result.map(|_| relation.unwrap())
+    }
+
+    /// Returns `true` if `source` has the relation `R` to `target`.
+    pub fn has_relation<R: ComponentTrait>(&self, source: Entity, target: Entity) -> bool {
+        self.entity_manager
+            .get_entity_location(source)
+            .is_ok_and(|location| {
+                self.archetypes[location.storage_index]
+                    .channel(ComponentId::pair::<R>(target))
+                    .is_some()
+            })
+    }
+
+    /// Iterates every [Entity] with the relation `R` to `target`.
+    /// Relations to despawned [Entity]s are not removed automatically.
+    pub fn related_to<R: ComponentTrait>(
+        &self,
+        target: Entity,
+    ) -> impl Iterator<Item = Entity> + '_ {
+        self.archetype_lookup
+            .archetypes_with(ComponentId::pair::<R>(target))
+            .iter()
+            .flat_map(move |archetype_index| {
+                self.archetypes[*archetype_index]
+                    .entity_indices
+                    .iter()
+                    .map(move |index| self.entity_manager.get_entity(*index))
+            })
+    }
+}
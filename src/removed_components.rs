@@ -0,0 +1,71 @@
+use std::marker::PhantomData;
+
+use crate::*;
+
+/// Records the [Entity]s that had a type of component removed.
+#[derive(Default)]
+pub(crate) struct RemovalLog {
+    pub(crate) entities: Vec<Entity>,
+    /// How many removals were cleared before the first one in `entities`.
+    start: usize,
+}
+
+/// A cursor used to read the [Entity]s that had a `T` removed since it was last read.
+/// See [World::removed].
+pub struct RemovedComponents<T: ComponentTrait> {
+    cursor: usize,
+    phantom: PhantomData<fn() -> T>,
+}
+
+impl<T: ComponentTrait> RemovedComponents<T> {
+    pub fn new() -> Self {
+        Self {
+            cursor: 0,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<T: ComponentTrait> Default for RemovedComponents<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl World {
+    /// Returns the [Entity]s that had a `T` removed or were despawned with a `T`
+    /// since `removed_components` last read them.
+    /// Removals cleared by [World::clear_removed_components] before being read are skipped.
+    pub fn removed<T: ComponentTrait>(
+        &self,
+        removed_components: &mut RemovedComponents<T>,
+    ) -> &[Entity] {
+        if let Some(log) = self.removed_components.get(&T::component_id()) {
+            let skip = removed_components
+                .cursor
+                .saturating_sub(log.start)
+                .min(log.entities.len());
+            removed_components.cursor = log.start + log.entities.len();
+            &log.entities[skip..]
+        } else {
+            &[]
+        }
+    }
+
+    /// Forgets all recorded removals. This should be called once per frame after
+    /// everything that reads removals has run.
+    pub fn clear_removed_components(&mut self) {
+        for log in self.removed_components.values_mut() {
+            log.start += log.entities.len();
+            log.entities.clear();
+        }
+    }
+
+    pub(crate) fn record_removal(&mut self, entity: Entity, component_id: ComponentId) {
+        self.removed_components
+            .entry(component_id)
+            .or_default()
+            .entities
+            .push(entity);
+    }
+}
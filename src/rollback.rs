@@ -0,0 +1,249 @@
+use std::collections::VecDeque;
+
+use crate::*;
+
+/// The cloned rows of every [Archetype] with the same cloneable components.
+struct ArchetypeSnapshot {
+    /// Sorted, matching `channels`.
+    component_ids: Vec<ComponentId>,
+    entity_indices: Vec<usize>,
+    channels: Vec<BlobVec>,
+}
+
+/// A copy of a [World]'s [Entity]s and cloneable components, made by [World::snapshot]
+/// and brought back with [World::restore].
+///
+/// Only components whose [ComponentTrait::clone_vec] returns `Some` and tags are copied.
+/// A channel it returns `None` for is left out as if the type weren't cloneable.
+/// Components are cloned a whole channel at a time so a snapshot is cheap enough to take
+/// every frame for rollback.
+pub struct WorldSnapshot {
+    generations: Vec<u32>,
+    free_entities: Vec<usize>,
+    archetypes: Vec<ArchetypeSnapshot>,
+    sparse_storages: Vec<(ComponentId, Box<dyn AnySparseStorage>)>,
+    /// The types of the copied components, so they can be restored into another [World].
+    components: Vec<ComponentInfo>,
+}
+
+impl WorldSnapshot {
+    /// The number of [Entity]s that were alive.
+    pub fn len(&self) -> usize {
+        self.generations.len() - self.free_entities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl World {
+    /// Copies every [Entity] and its cloneable components. See [WorldSnapshot].
+    pub fn snapshot(&mut self) -> WorldSnapshot {
+        self.flush_reserved_entities();
+        let mut snapshot = WorldSnapshot {
+            generations: self.entity_manager.generations().collect(),
+            free_entities: self.entity_manager.free_entities().to_vec(),
+            archetypes: Vec::new(),
+            sparse_storages: Vec::new(),
+            components: Vec::new(),
+        };
+        let mut copy_info = |info: &ComponentInfo| {
+            if !snapshot.components.iter().any(|c| c.id() == info.id()) {
+                snapshot.components.push(info.clone());
+            }
+        };
+
+        for archetype in &mut self.archetypes {
+            if archetype.entity_indices.is_empty() {
+                continue;
+            }
+            let channels: Vec<(ComponentId, &ComponentInfo, BlobVec)> = archetype
+                .channels
+                .iter_mut()
+                .filter_map(|(component_id, channel)| {
+                    let info = self.components.get(*component_id)?;
+                    let cloned = channel.get_mut().unwrap().try_clone()?;
+                    Some((*component_id, info, cloned))
+                })
+                .collect();
+            let component_ids: Vec<ComponentId> = channels
+                .iter()
+                .map(|(component_id, _, _)| *component_id)
+                .collect();
+            for (_, info, _) in &channels {
+                copy_info(info);
+            }
+            let channels = channels.into_iter().map(|(_, _, channel)| channel);
+
+            // Archetypes that only differ by components that can't be cloned share a snapshot.
+            match snapshot
+                .archetypes
+                .iter()
+                .position(|group| group.component_ids == component_ids)
+            {
+                Some(group) => {
+                    let group = &mut snapshot.archetypes[group];
+                    group.entity_indices.extend(&archetype.entity_indices);
+                    for (group_channel, mut channel) in group.channels.iter_mut().zip(channels) {
+                        group_channel.append(&mut channel);
+                    }
+                }
+                None => snapshot.archetypes.push(ArchetypeSnapshot {
+                    component_ids,
+                    entity_indices: archetype.entity_indices.clone(),
+                    channels: channels.collect(),
+                }),
+            }
+        }
+
+        for (component_id, storage) in &self.sparse_storages {
+            if let Some(cloned) = storage.try_clone(self.change_tick()) {
+                snapshot.sparse_storages.push((*component_id, cloned));
+                copy_info(self.components.get(*component_id).unwrap());
+            }
+        }
+        snapshot
+    }
+
+    /// Replaces every [Entity] and component with those in `snapshot`, so [Entity]s have the
+    /// same ids and [Archetype]s the same rows in the same order as when it was taken.
+    /// Components that weren't copied are dropped.
+    ///
+    /// Components whose [ComponentTrait::clone_vec] returns `None` this time are dropped too.
+    /// Hooks aren't run, and restored components are marked as added and changed at the current
+    /// change tick. Dynamic components must have been registered in the same order as in the
+    /// [World] the snapshot was taken from.
+    pub fn restore(&mut self, snapshot: &WorldSnapshot) {
+        self.flush_reserved_entities();
+        for archetype in &mut self.archetypes {
+            archetype.clear();
+        }
+        self.sparse_storages.clear();
+        for info in &snapshot.components {
+            self.components.get_or_register(info.id(), || info.clone());
+        }
+        self.entity_manager
+            .restore(snapshot.generations.clone(), snapshot.free_entities.clone());
+
+        let change_tick = *self.change_tick.get_mut();
+        for group in &snapshot.archetypes {
+            let (component_ids, mut channels): (Vec<ComponentId>, Vec<BlobVec>) = group
+                .component_ids
+                .iter()
+                .zip(&group.channels)
+                .filter_map(|(component_id, channel)| Some((*component_id, channel.try_clone()?)))
+                .unzip();
+            let archetype_index = match self.archetype_lookup.get_exact_archetype(&component_ids) {
+                Some(archetype_index) => archetype_index,
+                None => {
+                    let mut archetype = Archetype::new();
+                    for component_id in &component_ids {
+                        archetype.push_channel(
+                            *component_id,
+                            self.components.get(*component_id).unwrap(),
+                        );
+                    }
+                    self.push_archetype(&component_ids, archetype)
+                }
+            };
+            let archetype = &mut self.archetypes[archetype_index];
+            for (((_, channel), ticks), cloned) in archetype
+                .channels
+                .iter_mut()
+                .zip(archetype.channel_ticks.iter_mut())
+                .zip(&mut channels)
+            {
+                // Tag channels stay empty and have no ticks.
+                let Some(ticks) = ticks else { continue };
+                for _ in 0..cloned.len() {
+                    ticks.push(change_tick);
+                }
+                channel.get_mut().unwrap().append(cloned);
+            }
+            // Groups whose uncloneable channels were dropped can share an `Archetype`.
+            for entity_index in group.entity_indices.iter() {
+                archetype.entity_indices.push(*entity_index);
+                self.entity_manager.update_entity_location(
+                    *entity_index,
+                    EntityLocation {
+                        storage_index: archetype_index,
+                        index_within_storage: archetype.entity_indices.len() - 1,
+                    },
+                );
+            }
+        }
+
+        for (component_id, storage) in &snapshot.sparse_storages {
+            if let Some(storage) = storage.try_clone(change_tick) {
+                self.sparse_storages.insert(*component_id, storage);
+            }
+        }
+    }
+}
+
+/// Keeps the most recent [WorldSnapshot]s up to a fixed capacity, dropping the oldest,
+/// for rolling a [World] back a few frames.
+pub struct SnapshotRing {
+    snapshots: VecDeque<WorldSnapshot>,
+    capacity: usize,
+}
+
+impl SnapshotRing {
+    pub fn new(capacity: usize) -> Self {
+        assert!(
+            capacity > 0,
+            "A `SnapshotRing` must hold at least one snapshot"
+        );
+        Self {
+            snapshots: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Adds a snapshot as the most recent, dropping the oldest if full.
+    pub fn push(&mut self, snapshot: WorldSnapshot) {
+        if self.snapshots.len() == self.capacity {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back(snapshot);
+    }
+
+    /// Takes a snapshot of `world` and adds it. See [SnapshotRing::push].
+    pub fn record(&mut self, world: &mut World) {
+        self.push(world.snapshot());
+    }
+
+    /// Gets the snapshot pushed `frames_ago` pushes before the most recent.
+    pub fn get(&self, frames_ago: usize) -> Option<&WorldSnapshot> {
+        let index = self.snapshots.len().checked_sub(frames_ago + 1)?;
+        self.snapshots.get(index)
+    }
+
+    /// Restores `world` to the snapshot from `frames_ago` and drops the snapshots after it,
+    /// so it's the most recent again. Returns `false` if there isn't one that old.
+    pub fn rollback(&mut self, world: &mut World, frames_ago: usize) -> bool {
+        let Some(snapshot) = self.get(frames_ago) else {
+            return false;
+        };
+        world.restore(snapshot);
+        self.snapshots.truncate(self.snapshots.len() - frames_ago);
+        true
+    }
+
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+    }
+}
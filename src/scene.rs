@@ -0,0 +1,625 @@
+use std::{collections::HashMap, fmt, fmt::Write};
+
+use crate::*;
+
+/// Lets a component be written to and read from scenes as named fields.
+/// Opt in with [ComponentRegistry::register_scene].
+pub trait SceneComponent: ComponentTrait {
+    fn to_fields(&self, fields: &mut SceneFields);
+    fn from_fields(fields: &SceneFields) -> Option<Self>;
+}
+
+/// The value of a field in a scene.
+#[derive(Clone, PartialEq, Debug)]
+pub enum SceneValue {
+    /// Written for [Entity] references that aren't alive.
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+    /// Written as `@` and the scene-local id of the [Entity].
+    Entity(Entity),
+    List(Vec<SceneValue>),
+}
+
+impl SceneValue {
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            SceneValue::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_int(&self) -> Option<i64> {
+        match self {
+            SceneValue::Int(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Also converts [SceneValue::Int]s, since hand-written floats often leave off the `.0`.
+    pub fn as_float(&self) -> Option<f64> {
+        match self {
+            SceneValue::Float(value) => Some(*value),
+            SceneValue::Int(value) => Some(*value as f64),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            SceneValue::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_entity(&self) -> Option<Entity> {
+        match self {
+            SceneValue::Entity(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&[SceneValue]> {
+        match self {
+            SceneValue::List(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Replaces every [Entity] with the result of `map`, or fails if it returns `None`.
+    fn map_entities(&mut self, map: &impl Fn(Entity) -> Option<Entity>) -> Option<()> {
+        match self {
+            SceneValue::Entity(entity) => *entity = map(*entity)?,
+            SceneValue::List(values) => {
+                for value in values {
+                    value.map_entities(map)?;
+                }
+            }
+            _ => {}
+        }
+        Some(())
+    }
+
+    fn write(&self, entity_ids: &HashMap<Entity, usize>, out: &mut String) {
+        match self {
+            SceneValue::Null => out.push_str("null"),
+            SceneValue::Bool(value) => write!(out, "{}", value).unwrap(),
+            SceneValue::Int(value) => write!(out, "{}", value).unwrap(),
+            // `Debug` always writes a `.` or exponent so it's read back as a float.
+            SceneValue::Float(value) => write!(out, "{:?}", value).unwrap(),
+            SceneValue::String(value) => {
+                out.push('"');
+                for c in value.chars() {
+                    match c {
+                        '"' => out.push_str("\\\""),
+                        '\\' => out.push_str("\\\\"),
+                        '\n' => out.push_str("\\n"),
+                        '\r' => out.push_str("\\r"),
+                        '\t' => out.push_str("\\t"),
+                        c => out.push(c),
+                    }
+                }
+                out.push('"');
+            }
+            SceneValue::Entity(entity) => match entity_ids.get(entity) {
+                Some(id) => write!(out, "@{}", id).unwrap(),
+                None => out.push_str("null"),
+            },
+            SceneValue::List(values) => {
+                out.push('[');
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        out.push_str(", ");
+                    }
+                    value.write(entity_ids, out);
+                }
+                out.push(']');
+            }
+        }
+    }
+}
+
+macro_rules! scene_value_from {
+    ($($type: ty => $variant: ident),*) => {
+        $(impl From<$type> for SceneValue {
+            fn from(value: $type) -> Self {
+                SceneValue::$variant(value.into())
+            }
+        })*
+    };
+}
+
+scene_value_from!(
+    bool => Bool, i64 => Int, i32 => Int, u32 => Int, f64 => Float, f32 => Float,
+    String => String, &str => String, Entity => Entity, Vec<SceneValue> => List
+);
+
+/// The named fields of a component in a scene, in the order they're written.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct SceneFields {
+    fields: Vec<(String, SceneValue)>,
+}
+
+impl SceneFields {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the field called `name`, replacing an existing field with that name.
+    pub fn insert(&mut self, name: impl Into<String>, value: impl Into<SceneValue>) {
+        let name = name.into();
+        let value = value.into();
+        match self.fields.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, existing)) => *existing = value,
+            None => self.fields.push((name, value)),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&SceneValue> {
+        self.fields
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &SceneValue)> {
+        self.fields
+            .iter()
+            .map(|(name, value)| (name.as_str(), value))
+    }
+
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+}
+
+/// Why a scene couldn't be loaded, and the line it happened on.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct SceneError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+struct ParsedComponent<'a> {
+    line: usize,
+    name: &'a str,
+    /// The scene-local id of the relation target.
+    target: Option<usize>,
+    /// [Entity]s are scene-local ids stored as indices until they're mapped.
+    fields: SceneFields,
+}
+
+struct ParsedEntity<'a> {
+    line: usize,
+    id: usize,
+    components: Vec<ParsedComponent<'a>>,
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    position: usize,
+    /// The line `position` is on, counted as the text is consumed.
+    line: usize,
+}
+
+impl<'a> Parser<'a> {
+    /// Moves `position` forward by `len` bytes.
+    fn advance(&mut self, len: usize) {
+        let end = self.position + len;
+        self.line += self.text[self.position..end].matches('\n').count();
+        self.position = end;
+    }
+
+    fn error<T>(&self, message: impl Into<String>) -> Result<T, SceneError> {
+        Err(SceneError {
+            line: self.line,
+            message: message.into(),
+        })
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.text[self.position..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    /// Skips whitespace, newlines and `#` comments.
+    fn skip_whitespace(&mut self) {
+        loop {
+            let rest = self.rest();
+            let trimmed = rest.trim_start();
+            self.advance(rest.len() - trimmed.len());
+            if !trimmed.starts_with('#') {
+                return;
+            }
+            self.advance(trimmed.find('\n').unwrap_or(trimmed.len()));
+        }
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_whitespace();
+        if self.peek() == Some(c) {
+            self.advance(c.len_utf8());
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), SceneError> {
+        if self.eat(c) {
+            Ok(())
+        } else {
+            self.error(format!("expected `{}`", c))
+        }
+    }
+
+    /// Takes characters while `f` returns `true`.
+    fn take_while(&mut self, f: impl Fn(char) -> bool) -> &'a str {
+        let rest = self.rest();
+        let len = rest.find(|c| !f(c)).unwrap_or(rest.len());
+        self.advance(len);
+        &rest[..len]
+    }
+
+    fn ident(&mut self) -> Result<&'a str, SceneError> {
+        self.skip_whitespace();
+        let ident = self.take_while(|c| c.is_alphanumeric() || c == '_');
+        if ident.is_empty() {
+            return self.error("expected a name");
+        }
+        Ok(ident)
+    }
+
+    fn id(&mut self) -> Result<usize, SceneError> {
+        self.skip_whitespace();
+        match self.take_while(|c| c.is_ascii_digit()).parse() {
+            Ok(id) => Ok(id),
+            Err(_) => self.error("expected an entity id"),
+        }
+    }
+
+    fn scene(&mut self) -> Result<Vec<ParsedEntity<'a>>, SceneError> {
+        let mut entities = Vec::new();
+        loop {
+            self.skip_whitespace();
+            if self.rest().is_empty() {
+                return Ok(entities);
+            }
+            let line = self.line;
+            if self.ident()? != "entity" {
+                return self.error("expected `entity`");
+            }
+            let id = self.id()?;
+            self.expect('{')?;
+            let mut components = Vec::new();
+            while !self.eat('}') {
+                components.push(self.component()?);
+            }
+            entities.push(ParsedEntity {
+                line,
+                id,
+                components,
+            });
+        }
+    }
+
+    /// A component name, then an optional `@` relation target and `{}` fields.
+    fn component(&mut self) -> Result<ParsedComponent<'a>, SceneError> {
+        self.skip_whitespace();
+        let line = self.line;
+        let name = self
+            .take_while(|c| !matches!(c, '{' | '}' | '@' | '#' | '\n'))
+            .trim();
+        if name.is_empty() {
+            return self.error("expected a component name");
+        }
+        let target = if self.eat('@') {
+            Some(self.id()?)
+        } else {
+            None
+        };
+        let mut fields = SceneFields::new();
+        if self.eat('{') {
+            while !self.eat('}') {
+                let name = self.ident()?;
+                self.expect(':')?;
+                fields.insert(name, self.value()?);
+                if !self.eat(',') {
+                    self.expect('}')?;
+                    break;
+                }
+            }
+        }
+        Ok(ParsedComponent {
+            line,
+            name,
+            target,
+            fields,
+        })
+    }
+
+    fn value(&mut self) -> Result<SceneValue, SceneError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('"') => {
+                self.advance(1);
+                self.string().map(SceneValue::String)
+            }
+            Some('@') => {
+                self.advance(1);
+                Ok(SceneValue::Entity(Entity {
+                    index: self.id()?,
+                    generation: 0,
+                }))
+            }
+            Some('[') => {
+                self.advance(1);
+                let mut values = Vec::new();
+                while !self.eat(']') {
+                    values.push(self.value()?);
+                    if !self.eat(',') {
+                        self.expect(']')?;
+                        break;
+                    }
+                }
+                Ok(SceneValue::List(values))
+            }
+            _ => {
+                let token =
+                    self.take_while(|c| c.is_alphanumeric() || matches!(c, '-' | '+' | '.'));
+                match token {
+                    "null" => Ok(SceneValue::Null),
+                    "true" => Ok(SceneValue::Bool(true)),
+                    "false" => Ok(SceneValue::Bool(false)),
+                    _ => {
+                        if let Ok(value) = token.parse() {
+                            Ok(SceneValue::Int(value))
+                        } else if let Ok(value) = token.parse() {
+                            Ok(SceneValue::Float(value))
+                        } else {
+                            self.error(format!("expected a value, found `{}`", token))
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// The rest of a string after its opening `"`.
+    fn string(&mut self) -> Result<String, SceneError> {
+        let mut string = String::new();
+        let mut chars = self.rest().char_indices();
+        while let Some((i, c)) = chars.next() {
+            let c = match c {
+                '"' => {
+                    self.advance(i + 1);
+                    return Ok(string);
+                }
+                '\\' => match chars.next().map(|(_, c)| c) {
+                    Some('n') => '\n',
+                    Some('r') => '\r',
+                    Some('t') => '\t',
+                    Some(c @ ('"' | '\\')) => c,
+                    _ => {
+                        self.advance(i);
+                        return self.error("invalid escape in string");
+                    }
+                },
+                c => c,
+            };
+            string.push(c);
+        }
+        self.error("unterminated string")
+    }
+}
+
+impl World {
+    /// Spawns the [Entity]s described by a scene and returns them in the order they're written.
+    ///
+    /// A scene is a list of entities, each with a scene-local id and its components:
+    /// ```text
+    /// # Comments start with `#`.
+    /// entity 0 {
+    ///     game::Position { x: 1.5, y: -2 }
+    ///     game::Name { value: "Door", tags: ["red", "locked"] }
+    ///     game::Opens { target: @1 }
+    /// }
+    /// entity 1 {
+    ///     game::Hidden
+    ///     game::ChildOf @0
+    /// }
+    /// ```
+    /// Components are named as in the [ComponentRegistry] and must be registered with
+    /// [ComponentRegistry::register_scene]. `@` and an id refers to another [Entity] in the scene,
+    /// and after a component name makes a relation pair targeting it.
+    /// Nothing is spawned if the scene has an error.
+    pub fn load_scene(&mut self, scene: &str) -> Result<Vec<Entity>, SceneError> {
+        let mut parser = Parser {
+            text: scene,
+            position: 0,
+            line: 1,
+        };
+        let parsed = parser.scene()?;
+        let error = |line, message: String| Err(SceneError { line, message });
+
+        let mut infos = Vec::new();
+        let mut local_ids = std::collections::HashSet::new();
+        for entity in &parsed {
+            if !local_ids.insert(entity.id) {
+                return error(
+                    entity.line,
+                    format!("entity {} is written twice", entity.id),
+                );
+            }
+            for component in &entity.components {
+                match self.components.get_by_name(component.name) {
+                    Some(info) if info.is_scene_component() => infos.push(info.id()),
+                    _ => {
+                        return error(
+                            component.line,
+                            format!("`{}` isn't a registered scene component", component.name),
+                        )
+                    }
+                }
+            }
+        }
+
+        let entities: Vec<Entity> = self.reserve_entities(parsed.len()).collect();
+        self.flush_reserved_entities();
+        let ids: HashMap<usize, Entity> = parsed
+            .iter()
+            .map(|parsed| parsed.id)
+            .zip(entities.iter().copied())
+            .collect();
+        let map = |local: Entity| ids.get(&local.index).copied();
+
+        let mut infos = infos.into_iter();
+        let mut components = Vec::with_capacity(parsed.len());
+        for parsed in &parsed {
+            let mut entity_components = Vec::with_capacity(parsed.components.len());
+            for component in &parsed.components {
+                let mut component_id = infos.next().unwrap();
+                let mut fields = component.fields.clone();
+                let target = component.target.map(|target| ids.get(&target).copied());
+                let mapped = fields
+                    .fields
+                    .iter_mut()
+                    .all(|(_, value)| value.map_entities(&map).is_some());
+                if !mapped || target.is_some_and(|target| target.is_none()) {
+                    self.despawn_batch(&entities);
+                    return error(
+                        component.line,
+                        "reference to an entity not in the scene".into(),
+                    );
+                }
+                component_id.target = target.flatten();
+                if entity_components.iter().any(|(id, _)| *id == component_id) {
+                    self.despawn_batch(&entities);
+                    return error(
+                        component.line,
+                        format!("`{}` is written twice", component.name),
+                    );
+                }
+
+                let info = self.components.get(component_id).unwrap();
+                let mut blob_vec = info.new_blob_vec();
+                // SAFETY: `blob_vec` stores components of `info`'s type.
+                if !unsafe { blob_vec.push_with(|ptr| info.from_fields(&fields, ptr)) } {
+                    self.despawn_batch(&entities);
+                    return error(
+                        component.line,
+                        format!("invalid fields for `{}`", component.name),
+                    );
+                }
+                entity_components.push((component_id, blob_vec));
+            }
+            components.push(entity_components);
+        }
+
+        for (entity, entity_components) in entities.iter().zip(components) {
+            // Hooks may have despawned the `Entity`, in which case there's nothing to do.
+            // SAFETY: Each `BlobVec` holds one component of its `ComponentId`'s type.
+            let _ = unsafe { self.add_blob_vec_components(*entity, entity_components) };
+        }
+        Ok(entities)
+    }
+
+    /// Writes every [Entity] and its scene components in the format read by [World::load_scene].
+    /// [Entity]s are written in order and given scene-local ids counting up from `0`, and
+    /// their components are sorted by name, so saving the same [World] gives the same text.
+    pub fn save_scene(&mut self) -> String {
+        self.flush_reserved_entities();
+        let mut entities: Vec<(Entity, usize, usize)> = Vec::new();
+        for (archetype_index, archetype) in self.archetypes.iter().enumerate() {
+            for (row, index) in archetype.entity_indices.iter().enumerate() {
+                entities.push((self.entity_manager.get_entity(*index), archetype_index, row));
+            }
+        }
+        entities.sort_unstable();
+        let ids: HashMap<Entity, usize> = entities
+            .iter()
+            .enumerate()
+            .map(|(id, (entity, _, _))| (*entity, id))
+            .collect();
+
+        let mut out = String::new();
+        for (id, (entity, archetype_index, row)) in entities.into_iter().enumerate() {
+            let mut lines = Vec::new();
+            let mut write_component =
+                |info: &ComponentInfo, component_id: ComponentId, fields: SceneFields| {
+                    let mut line = format!("    {}", info.name());
+                    if let Some(target) = component_id.target {
+                        // Relations to `Entity`s that aren't alive can't be written.
+                        let Some(target) = ids.get(&target) else {
+                            return;
+                        };
+                        write!(line, " @{}", target).unwrap();
+                    }
+                    if !fields.is_empty() {
+                        line.push_str(" {");
+                        for (i, (name, value)) in fields.iter().enumerate() {
+                            line.push_str(if i == 0 { " " } else { ", " });
+                            line.push_str(name);
+                            line.push_str(": ");
+                            value.write(&ids, &mut line);
+                        }
+                        line.push_str(" }");
+                    }
+                    lines.push(line);
+                };
+
+            for (component_id, channel) in self.archetypes[archetype_index].channels.iter_mut() {
+                let Some(info) = self.components.get(*component_id) else {
+                    continue;
+                };
+                let mut fields = SceneFields::new();
+                // SAFETY: The channel stores components of `info`'s type.
+                if unsafe { info.to_fields(channel.get_mut().unwrap().get(row), &mut fields) } {
+                    write_component(info, *component_id, fields);
+                }
+            }
+            for (component_id, storage) in self.sparse_storages.iter_mut() {
+                let (Some(info), Some(component)) = (
+                    self.components.get(*component_id),
+                    storage.component_ptr(entity.index),
+                ) else {
+                    continue;
+                };
+                let mut fields = SceneFields::new();
+                // SAFETY: The storage stores components of `info`'s type.
+                if unsafe { info.to_fields(component, &mut fields) } {
+                    write_component(info, *component_id, fields);
+                }
+            }
+            lines.sort_unstable();
+
+            if id > 0 {
+                out.push('\n');
+            }
+            writeln!(out, "entity {} {{", id).unwrap();
+            for line in lines {
+                out.push_str(&line);
+                out.push('\n');
+            }
+            out.push_str("}\n");
+        }
+        out
+    }
+}
@@ -0,0 +1,450 @@
+//! A compact binary format for saving and loading whole [World]s, enabled with the
+//! `serde` feature. It's specific to this crate and doesn't use the serde crate.
+//!
+//! All integers are little-endian. The layout is:
+//! - The magic bytes `RECS` and a `u32` format version.
+//! - The [Entity] generation of every index as a `u64` count of `u32`s, then the free
+//!   indices as a `u64` count of `u64`s, so loading restores identical [Entity]s.
+//! - The names of the component types used, as a `u32` count of `u32` length-prefixed strings.
+//! - A `u64` count of [Archetype]s. Each is a `u32` count of components, written as a `u32`
+//!   index into the names followed by a `u8` that's `1` if the relation target follows as a
+//!   `u64` index and `u32` generation. Then a `u64` count of rows, the `u64` [Entity] index of
+//!   each row, and each column's components one after another.
+//! - A `u32` count of sparse component types. Each is a `u32` index into the names and
+//!   optional relation target as above, then a `u64` count of `u64` [Entity] indices each
+//!   followed by its component.
+//!
+//! Components are written with the vtables registered by
+//! [ComponentRegistry::register_serialize]. Components that aren't registered are skipped.
+
+use std::collections::HashMap;
+
+use crate::*;
+
+const MAGIC: &[u8; 4] = b"RECS";
+const VERSION: u32 = 1;
+
+struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn str(&mut self, value: &str) {
+        self.u32(value.len() as u32);
+        self.bytes.extend_from_slice(value.as_bytes());
+    }
+
+    fn component_id(&mut self, name_index: u32, target: Option<Entity>) {
+        self.u32(name_index);
+        match target {
+            Some(target) => {
+                self.u8(1);
+                self.u64(target.index as u64);
+                self.u32(target.generation);
+            }
+            None => self.u8(0),
+        }
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], ECSError> {
+        let (value, rest) = self
+            .bytes
+            .split_first_chunk()
+            .ok_or(ECSError::InvalidSnapshot)?;
+        self.bytes = rest;
+        Ok(*value)
+    }
+
+    fn u8(&mut self) -> Result<u8, ECSError> {
+        Ok(self.take::<1>()?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, ECSError> {
+        Ok(u32::from_le_bytes(self.take()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, ECSError> {
+        Ok(u64::from_le_bytes(self.take()?))
+    }
+
+    fn usize(&mut self) -> Result<usize, ECSError> {
+        usize::try_from(self.u64()?).map_err(|_| ECSError::InvalidSnapshot)
+    }
+
+    fn str(&mut self) -> Result<&'a str, ECSError> {
+        let len = self.u32()? as usize;
+        if self.bytes.len() < len {
+            return Err(ECSError::InvalidSnapshot);
+        }
+        let (value, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        std::str::from_utf8(value).map_err(|_| ECSError::InvalidSnapshot)
+    }
+
+    /// Reads a component id written by [Writer::component_id], looking up its type in `ids`.
+    fn component_id(&mut self, ids: &[ComponentId]) -> Result<ComponentId, ECSError> {
+        let type_id = ids
+            .get(self.u32()? as usize)
+            .ok_or(ECSError::InvalidSnapshot)?
+            .type_id;
+        let target = match self.u8()? {
+            0 => None,
+            1 => Some(Entity {
+                index: self.usize()?,
+                generation: self.u32()?,
+            }),
+            _ => return Err(ECSError::InvalidSnapshot),
+        };
+        Ok(ComponentId { type_id, target })
+    }
+}
+
+impl World {
+    /// Writes every [Entity] and its serializable components to bytes that
+    /// [World::load_binary] reads back. See the [module docs](self) for the format.
+    pub fn save_binary(&mut self) -> Vec<u8> {
+        self.flush_reserved_entities();
+        let mut writer = Writer {
+            bytes: MAGIC.to_vec(),
+        };
+        writer.u32(VERSION);
+
+        writer.u64(self.entity_manager.generations().len() as u64);
+        for generation in self.entity_manager.generations() {
+            writer.u32(generation);
+        }
+        writer.u64(self.entity_manager.free_entities().len() as u64);
+        for index in self.entity_manager.free_entities() {
+            writer.u64(*index as u64);
+        }
+
+        // Name the serializable types that are used, in the order they're first used.
+        let mut name_indices: HashMap<ComponentId, u32> = HashMap::new();
+        let mut names = Vec::new();
+        let mut name_index = |info: &ComponentInfo| {
+            *name_indices.entry(info.id()).or_insert_with(|| {
+                names.push(info.name().to_owned());
+                names.len() as u32 - 1
+            })
+        };
+        let components = &self.components;
+        let serializable = |component_id: &ComponentId| {
+            components
+                .get(*component_id)
+                .filter(|info| info.is_serializable())
+        };
+        let archetypes: Vec<(&mut Archetype, Vec<(usize, &ComponentInfo)>)> = self
+            .archetypes
+            .iter_mut()
+            .filter(|archetype| !archetype.entity_indices.is_empty())
+            .map(|archetype| {
+                let columns = archetype
+                    .channels
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(channel, (component_id, _))| {
+                        Some((channel, serializable(component_id)?))
+                    })
+                    .collect();
+                (archetype, columns)
+            })
+            .collect();
+        let sparse_storages: Vec<(ComponentId, &mut Box<dyn AnySparseStorage>, &ComponentInfo)> =
+            self.sparse_storages
+                .iter_mut()
+                .filter_map(|(component_id, storage)| {
+                    Some((*component_id, storage, serializable(component_id)?))
+                })
+                .collect();
+        for info in archetypes
+            .iter()
+            .flat_map(|(_, columns)| columns.iter().map(|(_, info)| *info))
+            .chain(sparse_storages.iter().map(|(_, _, info)| *info))
+        {
+            name_index(info);
+        }
+        writer.u32(names.len() as u32);
+        for name in &names {
+            writer.str(name);
+        }
+
+        writer.u64(archetypes.len() as u64);
+        for (archetype, columns) in archetypes {
+            writer.u32(columns.len() as u32);
+            for (channel, info) in &columns {
+                writer.component_id(
+                    name_indices[&info.id()],
+                    archetype.channels[*channel].0.target,
+                );
+            }
+            writer.u64(archetype.entity_indices.len() as u64);
+            for index in &archetype.entity_indices {
+                writer.u64(*index as u64);
+            }
+            for (channel, info) in &columns {
+                let channel = archetype.channels[*channel].1.get_mut().unwrap();
+                // Tag channels are empty, so count rows by `Entity`.
+                for row in 0..archetype.entity_indices.len() {
+                    // SAFETY: The channel stores components of `info`'s type.
+                    unsafe { info.serialize(channel.get(row), &mut writer.bytes) };
+                }
+            }
+        }
+
+        writer.u32(sparse_storages.len() as u32);
+        for (component_id, storage, info) in sparse_storages {
+            writer.component_id(name_indices[&info.id()], component_id.target);
+            let entity_indices = storage.entity_indices().to_vec();
+            writer.u64(entity_indices.len() as u64);
+            for index in entity_indices {
+                writer.u64(index as u64);
+                let component = storage.component_ptr(index).unwrap();
+                // SAFETY: The storage stores components of `info`'s type.
+                unsafe { info.serialize(component, &mut writer.bytes) };
+            }
+        }
+        writer.bytes
+    }
+
+    /// Spawns the [Entity]s written by [World::save_binary], with the same ids they had when saved.
+    ///
+    /// Each component type must be registered with [ComponentRegistry::register_serialize]
+    /// under the same name, otherwise this errors with [ECSError::NoMatchingComponent].
+    /// Errors with [ECSError::InvalidSnapshot] if `bytes` are malformed.
+    /// The whole snapshot is read before anything is spawned, so the [World] is left
+    /// unchanged on an error.
+    ///
+    /// Panics if the [World] has any [Entity]s.
+    pub fn load_binary(&mut self, bytes: &[u8]) -> Result<(), ECSError> {
+        self.flush_reserved_entities();
+        assert!(
+            self.entity_manager.generations().len() == self.entity_manager.free_entities().len(),
+            "`World::load_binary` must be called on a `World` without `Entity`s"
+        );
+        let snapshot = Snapshot::read(&self.components, bytes)?;
+
+        self.entity_manager
+            .restore(snapshot.generations, snapshot.free_entities);
+        let change_tick = *self.change_tick.get_mut();
+        for loaded in snapshot.archetypes {
+            let archetype_index = match self
+                .archetype_lookup
+                .get_exact_archetype(&loaded.component_ids)
+            {
+                Some(archetype_index) => archetype_index,
+                None => {
+                    let mut archetype = Archetype::new();
+                    for component_id in &loaded.component_ids {
+                        archetype.push_channel(
+                            *component_id,
+                            self.components.get(*component_id).unwrap(),
+                        );
+                    }
+                    self.push_archetype(&loaded.component_ids, archetype)
+                }
+            };
+            let archetype = &mut self.archetypes[archetype_index];
+            for (((_, channel), ticks), mut loaded_channel) in archetype
+                .channels
+                .iter_mut()
+                .zip(archetype.channel_ticks.iter_mut())
+                .zip(loaded.channels)
+            {
+                // Tags have no ticks and nothing to store.
+                let Some(ticks) = ticks else { continue };
+                for _ in 0..loaded_channel.len() {
+                    ticks.push(change_tick);
+                }
+                channel.get_mut().unwrap().append(&mut loaded_channel);
+            }
+            for index in loaded.entity_indices {
+                archetype.entity_indices.push(index);
+                self.entity_manager.update_entity_location(
+                    index,
+                    EntityLocation {
+                        storage_index: archetype_index,
+                        index_within_storage: archetype.entity_indices.len() - 1,
+                    },
+                );
+            }
+        }
+
+        for (component_id, entity_indices, mut components) in snapshot.sparse_storages {
+            let info = self.components.get(component_id).unwrap();
+            let storage = self
+                .sparse_storages
+                .entry(component_id)
+                .or_insert_with(|| info.new_sparse_storage().unwrap());
+            for (row, index) in entity_indices.iter().enumerate().rev() {
+                // SAFETY: `components` and `storage` store components of `info`'s type, and
+                // `swap_remove_with` forgets the component after it's moved.
+                components.swap_remove_with(row, |component| unsafe {
+                    storage.insert_ptr(*index, component, change_tick)
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The rows of one [Archetype] read by [Snapshot::read].
+struct LoadedArchetype {
+    /// Sorted, matching `channels`.
+    component_ids: Vec<ComponentId>,
+    entity_indices: Vec<usize>,
+    channels: Vec<BlobVec>,
+}
+
+/// A fully read and validated snapshot, so [World::load_binary] only changes the [World]
+/// once it's known to succeed.
+struct Snapshot {
+    generations: Vec<u32>,
+    free_entities: Vec<usize>,
+    archetypes: Vec<LoadedArchetype>,
+    /// Each type's [Entity] indices and their components in the same order.
+    sparse_storages: Vec<(ComponentId, Vec<usize>, BlobVec)>,
+}
+
+impl Snapshot {
+    fn read(components: &ComponentRegistry, bytes: &[u8]) -> Result<Self, ECSError> {
+        let mut reader = Reader { bytes };
+        if reader.take::<4>()? != *MAGIC || reader.u32()? != VERSION {
+            return Err(ECSError::InvalidSnapshot);
+        }
+
+        let generations = (0..reader.usize()?)
+            .map(|_| reader.u32())
+            .collect::<Result<Vec<_>, _>>()?;
+        let free_entities = (0..reader.usize()?)
+            .map(|_| reader.usize())
+            .collect::<Result<Vec<_>, _>>()?;
+        // Every live index must be given exactly one row.
+        let mut needs_row = vec![true; generations.len()];
+        for index in &free_entities {
+            match needs_row.get_mut(*index) {
+                Some(needs_row) if *needs_row => *needs_row = false,
+                _ => return Err(ECSError::InvalidSnapshot),
+            }
+        }
+        let is_live = needs_row.clone();
+
+        let ids = (0..reader.u32()?)
+            .map(|_| {
+                let name = reader.str()?;
+                components
+                    .get_by_name(name)
+                    .filter(|info| info.is_serializable())
+                    .map(|info| info.id())
+                    .ok_or(ECSError::NoMatchingComponent)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut archetypes = Vec::new();
+        for _ in 0..reader.usize()? {
+            let column_ids = (0..reader.u32()?)
+                .map(|_| reader.component_id(&ids))
+                .collect::<Result<Vec<_>, _>>()?;
+            let mut component_ids = column_ids.clone();
+            component_ids.sort();
+            if component_ids.windows(2).any(|ids| ids[0] == ids[1]) {
+                return Err(ECSError::InvalidSnapshot);
+            }
+
+            let mut entity_indices = Vec::new();
+            for _ in 0..reader.usize()? {
+                let index = reader.usize()?;
+                match needs_row.get_mut(index) {
+                    Some(needs_row) if *needs_row => *needs_row = false,
+                    _ => return Err(ECSError::InvalidSnapshot),
+                }
+                entity_indices.push(index);
+            }
+            let mut channels: Vec<Option<BlobVec>> = component_ids.iter().map(|_| None).collect();
+            for component_id in &column_ids {
+                let info = components.get(*component_id).unwrap();
+                if info.storage() == StorageKind::SparseSet {
+                    return Err(ECSError::InvalidSnapshot);
+                }
+                let mut channel = info.new_blob_vec();
+                for _ in 0..entity_indices.len() {
+                    // SAFETY: The channel stores components of `info`'s type.
+                    let read = unsafe {
+                        channel
+                            .push_with(|component| info.deserialize(&mut reader.bytes, component))
+                    };
+                    if !read {
+                        return Err(ECSError::InvalidSnapshot);
+                    }
+                }
+                channels[component_ids.binary_search(component_id).unwrap()] = Some(channel);
+            }
+            archetypes.push(LoadedArchetype {
+                component_ids,
+                entity_indices,
+                channels: channels.into_iter().map(Option::unwrap).collect(),
+            });
+        }
+        if needs_row.contains(&true) {
+            return Err(ECSError::InvalidSnapshot);
+        }
+
+        let mut sparse_storages: Vec<(ComponentId, Vec<usize>, BlobVec)> = Vec::new();
+        for _ in 0..reader.u32()? {
+            let component_id = reader.component_id(&ids)?;
+            let info = components.get(component_id).unwrap();
+            if info.storage() != StorageKind::SparseSet
+                || sparse_storages.iter().any(|(id, _, _)| *id == component_id)
+            {
+                return Err(ECSError::InvalidSnapshot);
+            }
+            let mut has_component = vec![false; generations.len()];
+            let mut entity_indices = Vec::new();
+            let mut values = info.new_blob_vec();
+            for _ in 0..reader.usize()? {
+                let index = reader.usize()?;
+                if !is_live.get(index).copied().unwrap_or(false)
+                    || std::mem::replace(&mut has_component[index], true)
+                {
+                    return Err(ECSError::InvalidSnapshot);
+                }
+                entity_indices.push(index);
+                // SAFETY: `values` stores components of `info`'s type.
+                let read = unsafe {
+                    values.push_with(|component| info.deserialize(&mut reader.bytes, component))
+                };
+                if !read {
+                    return Err(ECSError::InvalidSnapshot);
+                }
+            }
+            sparse_storages.push((component_id, entity_indices, values));
+        }
+        if !reader.bytes.is_empty() {
+            return Err(ECSError::InvalidSnapshot);
+        }
+
+        Ok(Self {
+            generations,
+            free_entities,
+            archetypes,
+            sparse_storages,
+        })
+    }
+}
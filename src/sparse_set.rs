@@ -1,10 +1,25 @@
+/// The number of indices in each page of `indices`.
+const PAGE_SIZE: usize = 256;
+
+type Page = Box<[Option<usize>; PAGE_SIZE]>;
+
+/// Maps `usize` indices to items stored contiguously so they can be iterated quickly.
 #[derive(Clone, Debug)]
 pub struct SparseSet<T> {
-    indices: Vec<Option<usize>>,
+    /// Maps an item's index to its index in `data`.
+    /// Split into pages that are only allocated when an index in them is used
+    /// so that large indices don't allocate a gigantic `Vec`.
+    indices: Vec<Option<Page>>,
     data: Vec<T>,
     data_index_to_item_index: Vec<usize>,
 }
 
+impl<T> Default for SparseSet<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<T> SparseSet<T> {
     pub fn new() -> Self {
         Self {
@@ -14,25 +29,61 @@ impl<T> SparseSet<T> {
         }
     }
 
-    pub fn insert(&mut self, index: usize, data: T) {
-        // This line highlights the weakness of sparse sets.
-        // They use a bunch of memory!
-        if self.indices.len() <= index {
-            self.indices.resize(index + 1, None);
-        }
-
-        let new_index = self.data.len();
-        self.indices[index] = Some(new_index);
+    fn data_index(&self, index: usize) -> Option<usize> {
+        self.indices.get(index / PAGE_SIZE)?.as_ref()?[index % PAGE_SIZE]
+    }
 
-        // Is this a memory leak if an index is reused?
+    fn data_index_mut(&mut self, index: usize) -> &mut Option<usize> {
+        let page = index / PAGE_SIZE;
+        if self.indices.len() <= page {
+            self.indices.resize_with(page + 1, || None);
+        }
+        &mut self.indices[page].get_or_insert_with(|| Box::new([None; PAGE_SIZE]))
+            [index % PAGE_SIZE]
+    }
 
+    /// Inserts `data` at `index`. If there's already an item at `index` it's replaced
+    /// in place and returned.
+    pub fn insert(&mut self, index: usize, data: T) -> Option<T> {
+        if let Some(existing) = self.get_mut(index) {
+            return Some(std::mem::replace(existing, data));
+        }
+        *self.data_index_mut(index) = Some(self.data.len());
         self.data.push(data);
-
         self.data_index_to_item_index.push(index);
+        None
+    }
+
+    pub fn contains(&self, index: usize) -> bool {
+        self.data_index(index).is_some()
     }
 
     pub fn get(&self, index: usize) -> Option<&T> {
-        Some(&self.data[(*self.indices.get(index)?)?])
+        Some(&self.data[self.data_index(index)?])
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        let data_index = self.data_index(index)?;
+        Some(&mut self.data[data_index])
+    }
+
+    /// Removes the item at `index`, moving the last item into its place.
+    pub fn remove(&mut self, index: usize) -> Option<T> {
+        let data_index =
+            self.indices.get_mut(index / PAGE_SIZE)?.as_mut()?[index % PAGE_SIZE].take()?;
+        self.data_index_to_item_index.swap_remove(data_index);
+        if let Some(&moved_index) = self.data_index_to_item_index.get(data_index) {
+            *self.data_index_mut(moved_index) = Some(data_index);
+        }
+        Some(self.data.swap_remove(data_index))
+    }
+
+    /// Iterates each item along with its index. The order changes when items are removed.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &T)> {
+        self.data_index_to_item_index
+            .iter()
+            .copied()
+            .zip(self.data.iter())
     }
 
     pub fn len(&self) -> usize {
@@ -47,3 +98,35 @@ impl<T> SparseSet<T> {
         &self.data_index_to_item_index
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::SparseSet;
+
+    #[test]
+    fn sparse_set() {
+        let mut set = SparseSet::new();
+        assert_eq!(set.insert(3, "a"), None);
+        assert_eq!(set.insert(1_000_000, "b"), None);
+        assert_eq!(set.insert(7, "c"), None);
+        assert_eq!(set.insert(3, "d"), Some("a"));
+        assert_eq!(set.len(), 3);
+        assert_eq!(set.get(3), Some(&"d"));
+
+        // Removing moves the last item into the removed item's place.
+        assert_eq!(set.remove(3), Some("d"));
+        assert_eq!(set.remove(3), None);
+        assert!(!set.contains(3));
+        assert_eq!(set.get(7), Some(&"c"));
+        *set.get_mut(1_000_000).unwrap() = "e";
+        let mut items: Vec<(usize, &str)> = set.iter().map(|(i, v)| (i, *v)).collect();
+        items.sort();
+        assert_eq!(items, vec![(7, "c"), (1_000_000, "e")]);
+        for (data_index, index) in set.data_index_to_item_index().iter().enumerate() {
+            assert_eq!(set.get(*index), Some(&set.values()[data_index]));
+        }
+
+        set.insert(3, "f");
+        assert_eq!(set.get(3), Some(&"f"));
+    }
+}
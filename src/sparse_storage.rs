@@ -0,0 +1,311 @@
+use std::{
+    any::Any,
+    collections::HashMap,
+    ops::{Deref, DerefMut},
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        RwLock, RwLockReadGuard, RwLockWriteGuard,
+    },
+};
+
+use crate::{sparse_set::SparseSet, *};
+
+/// The components of a [StorageKind::SparseSet] type, keyed by [Entity] index.
+/// Each component has its own lock so a query can borrow rows from many [Archetype]s at once.
+pub struct SparseStorage<T> {
+    pub(crate) components: SparseSet<SparseComponent<T>>,
+}
+
+/// A [StorageKind::SparseSet] component and its change ticks, like a row of [ChannelTicks].
+pub(crate) struct SparseComponent<T> {
+    pub(crate) value: RwLock<T>,
+    /// The change tick the component was added at.
+    added: AtomicU32,
+    /// The change tick the component was last mutably accessed at.
+    changed: AtomicU32,
+}
+
+impl<T> SparseComponent<T> {
+    fn new(value: T, change_tick: u32) -> Self {
+        Self {
+            value: RwLock::new(value),
+            added: AtomicU32::new(change_tick),
+            changed: AtomicU32::new(change_tick),
+        }
+    }
+
+    /// Mutably borrows the component and marks it as changed.
+    pub(crate) fn get_mut(&mut self, change_tick: u32) -> &mut T {
+        *self.changed.get_mut() = change_tick;
+        self.value.get_mut().unwrap()
+    }
+}
+
+/// A mutably borrowed [StorageKind::SparseSet] component in a query.
+/// It's marked as changed when it's mutably dereferenced.
+pub struct SparseWriteGuard<'a, T> {
+    guard: RwLockWriteGuard<'a, T>,
+    changed: &'a AtomicU32,
+    change_tick: u32,
+}
+
+impl<T> Deref for SparseWriteGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<T> DerefMut for SparseWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.changed.store(self.change_tick, Ordering::Relaxed);
+        &mut self.guard
+    }
+}
+
+impl<T> SparseStorage<T> {
+    pub(crate) fn new() -> Self {
+        Self {
+            components: SparseSet::new(),
+        }
+    }
+
+    fn insert_value(&mut self, entity_index: usize, component: T, change_tick: u32) -> bool {
+        match self.components.get_mut(entity_index) {
+            Some(existing) => {
+                // Keeps the tick the component was added at, like a replaced table component.
+                *existing.get_mut(change_tick) = component;
+                true
+            }
+            None => {
+                self.components
+                    .insert(entity_index, SparseComponent::new(component, change_tick));
+                false
+            }
+        }
+    }
+}
+
+pub trait AnySparseStorage: Send + Sync {
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+    fn contains(&self, entity_index: usize) -> bool;
+    /// Inserts `component`, replacing an existing component in place.
+    /// Returns `true` if a component was replaced.
+    /// A new component is marked as added at `change_tick`, a replaced one as changed.
+    fn insert(
+        &mut self,
+        entity_index: usize,
+        component: &mut dyn AnyComponentTrait,
+        change_tick: u32,
+    ) -> bool;
+    /// Removes the component and drops it. Returns `false` if there wasn't one.
+    fn remove(&mut self, entity_index: usize) -> bool;
+    /// Removes the component and writes it into `component`.
+    fn remove_into(&mut self, entity_index: usize, component: &mut dyn AnyComponentTrait);
+    /// Returns a pointer to the component so it can be passed to hooks.
+    fn component_ptr(&mut self, entity_index: usize) -> Option<*mut u8>;
+    /// Marks the component as changed at `change_tick`, if there is one.
+    fn mark_changed(&mut self, entity_index: usize, change_tick: u32);
+    /// Returns a pointer to the component if it isn't mutably borrowed.
+    fn try_component_ptr(&self, entity_index: usize) -> Result<*const u8, ECSError>;
+    /// The index of every [Entity] with the component.
+    fn entity_indices(&self) -> &[usize];
+    /// Inserts the component at `component`, replacing an existing component.
+    /// Ticks are set as in [AnySparseStorage::insert].
+    ///
+    /// # Safety
+    /// `component` must point to a component of this type. It must not be used or dropped afterwards.
+    unsafe fn insert_ptr(&mut self, entity_index: usize, component: *const u8, change_tick: u32);
+    /// Clones every component, or returns `None` if the type isn't cloneable.
+    /// The clones are marked as added and changed at `change_tick`.
+    /// Panics if a component is mutably borrowed.
+    fn try_clone(&self, change_tick: u32) -> Option<Box<dyn AnySparseStorage>>;
+}
+
+impl<T: ComponentTrait> AnySparseStorage for SparseStorage<T> {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+    fn contains(&self, entity_index: usize) -> bool {
+        self.components.contains(entity_index)
+    }
+    fn insert(
+        &mut self,
+        entity_index: usize,
+        component: &mut dyn AnyComponentTrait,
+        change_tick: u32,
+    ) -> bool {
+        let component = component
+            .as_any_mut()
+            .downcast_mut::<Option<T>>()
+            .unwrap()
+            .take()
+            .unwrap();
+        self.insert_value(entity_index, component, change_tick)
+    }
+    fn remove(&mut self, entity_index: usize) -> bool {
+        self.components.remove(entity_index).is_some()
+    }
+    fn remove_into(&mut self, entity_index: usize, component: &mut dyn AnyComponentTrait) {
+        *component.as_any_mut().downcast_mut::<Option<T>>().unwrap() = self
+            .components
+            .remove(entity_index)
+            .map(|c| c.value.into_inner().unwrap());
+    }
+    fn component_ptr(&mut self, entity_index: usize) -> Option<*mut u8> {
+        let component = self
+            .components
+            .get_mut(entity_index)?
+            .value
+            .get_mut()
+            .unwrap();
+        Some((component as *mut T).cast())
+    }
+    fn mark_changed(&mut self, entity_index: usize, change_tick: u32) {
+        if let Some(component) = self.components.get_mut(entity_index) {
+            *component.changed.get_mut() = change_tick;
+        }
+    }
+    fn try_component_ptr(&self, entity_index: usize) -> Result<*const u8, ECSError> {
+        let guard = self
+            .components
+            .get(entity_index)
+            .ok_or(ECSError::NoMatchingComponent)?
+            .value
+            .try_read()
+            .map_err(|_| ECSError::ComponentAlreadyBorrowed)?;
+        Ok((&*guard as *const T).cast())
+    }
+    fn entity_indices(&self) -> &[usize] {
+        self.components.data_index_to_item_index()
+    }
+    unsafe fn insert_ptr(&mut self, entity_index: usize, component: *const u8, change_tick: u32) {
+        let component = component.cast::<T>().read();
+        self.insert_value(entity_index, component, change_tick);
+    }
+    fn try_clone(&self, change_tick: u32) -> Option<Box<dyn AnySparseStorage>> {
+        T::clone_vec(&[])?;
+        let mut cloned = Self::new();
+        for (entity_index, component) in self.components.iter() {
+            let component = component
+                .value
+                .try_read()
+                .expect("Components can't be mutably borrowed while cloning");
+            let mut component = T::clone_vec(std::slice::from_ref(&*component))?;
+            assert_eq!(
+                component.len(),
+                1,
+                "`ComponentTrait::clone_vec` must return one clone of each component"
+            );
+            let component = component.pop().unwrap();
+            cloned
+                .components
+                .insert(entity_index, SparseComponent::new(component, change_tick));
+        }
+        Some(Box::new(cloned))
+    }
+}
+
+pub(crate) type SparseStorages = HashMap<ComponentId, Box<dyn AnySparseStorage>>;
+
+pub(crate) fn get_sparse_storage<T: ComponentTrait>(
+    sparse_storages: &SparseStorages,
+) -> Option<&SparseStorage<T>> {
+    sparse_storages
+        .get(&T::component_id())
+        .map(|s| s.as_any().downcast_ref::<SparseStorage<T>>().unwrap())
+}
+
+/// What a query parameter for a [StorageKind::SparseSet] component needs to
+/// find the components of an [Archetype]'s rows.
+pub struct SparseContext<'a, 'b> {
+    pub(crate) sparse_storages: &'a SparseStorages,
+    pub(crate) entity_indices: &'a [usize],
+    /// Set to `false` for each row that is missing a sparse component or that a filter on one
+    /// excludes. `None` until a row is skipped.
+    pub(crate) rows: &'b mut Option<Vec<bool>>,
+}
+
+impl<'a> SparseContext<'a, '_> {
+    pub(crate) fn read<T: ComponentTrait>(
+        &mut self,
+    ) -> Result<Vec<Option<RwLockReadGuard<'a, T>>>, ECSError> {
+        self.lock_rows::<T, _>(|c| {
+            c.value
+                .try_read()
+                .map_err(|_| ECSError::ComponentAlreadyBorrowed)
+        })
+    }
+
+    /// Locks the rows' components, which are marked as changed at `change_tick` when they're
+    /// mutably dereferenced.
+    pub(crate) fn write<T: ComponentTrait>(
+        &mut self,
+        change_tick: u32,
+    ) -> Result<Vec<Option<SparseWriteGuard<'a, T>>>, ECSError> {
+        self.lock_rows::<T, _>(|c| {
+            Ok(SparseWriteGuard {
+                guard: c
+                    .value
+                    .try_write()
+                    .map_err(|_| ECSError::ComponentAlreadyBorrowed)?,
+                changed: &c.changed,
+                change_tick,
+            })
+        })
+    }
+
+    fn lock_rows<T: ComponentTrait, GUARD>(
+        &mut self,
+        lock: impl Fn(&'a SparseComponent<T>) -> Result<GUARD, ECSError>,
+    ) -> Result<Vec<Option<GUARD>>, ECSError> {
+        let storage = get_sparse_storage::<T>(self.sparse_storages);
+        let mut locked = Vec::with_capacity(self.entity_indices.len());
+        for (row, entity_index) in self.entity_indices.iter().enumerate() {
+            match storage.and_then(|s| s.components.get(*entity_index)) {
+                Some(component) => locked.push(Some(lock(component)?)),
+                None => {
+                    locked.push(None);
+                    self.skip(row);
+                }
+            }
+        }
+        Ok(locked)
+    }
+
+    /// Skips the rows whose [Entity] doesn't have a `T`, or does if `with` is `false`.
+    pub(crate) fn filter<T: ComponentTrait>(&mut self, with: bool) {
+        let storage = get_sparse_storage::<T>(self.sparse_storages);
+        for (row, entity_index) in self.entity_indices.iter().enumerate() {
+            if storage.is_some_and(|s| s.components.get(*entity_index).is_some()) != with {
+                self.skip(row);
+            }
+        }
+    }
+
+    /// Skips the rows whose [Entity] doesn't have a `T` that was added, or also changed if
+    /// `changed` is `true`, since the query last ran.
+    pub(crate) fn filter_ticks<T: ComponentTrait>(&mut self, changed: bool, ticks: QueryTicks) {
+        let storage = get_sparse_storage::<T>(self.sparse_storages);
+        for (row, entity_index) in self.entity_indices.iter().enumerate() {
+            let matches = storage
+                .and_then(|s| s.components.get(*entity_index))
+                .is_some_and(|c| {
+                    let tick = if changed { &c.changed } else { &c.added };
+                    ticks.is_changed(tick.load(Ordering::Relaxed))
+                });
+            if !matches {
+                self.skip(row);
+            }
+        }
+    }
+
+    fn skip(&mut self, row: usize) {
+        self.rows
+            .get_or_insert_with(|| vec![true; self.entity_indices.len()])[row] = false;
+    }
+}
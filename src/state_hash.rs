@@ -0,0 +1,233 @@
+use std::{collections::BTreeMap, hash::Hasher, sync::RwLockReadGuard};
+
+use crate::*;
+
+/// A 64-bit FNV-1a hasher. Integers are written as little-endian `u64`s so hashes match
+/// across platforms and Rust versions, unlike [std::collections::hash_map::DefaultHasher].
+pub(crate) struct StateHasher(u64);
+
+impl Default for StateHasher {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl StateHasher {
+    fn entity(&mut self, entity: Entity) {
+        self.write_usize(entity.index);
+        self.write_u32(entity.generation);
+    }
+
+    /// Hashes a component type by name so the hash doesn't depend on [std::any::TypeId]s.
+    fn component_id(&mut self, components: &ComponentRegistry, component_id: ComponentId) {
+        let (name, target) = hash_order(components, component_id);
+        self.write_usize(name.len());
+        self.write(name.as_bytes());
+        match target {
+            Some(target) => {
+                self.write_u8(1);
+                self.entity(target);
+            }
+            None => self.write_u8(0),
+        }
+    }
+}
+
+/// The order components are hashed in: by type name, then relation target. [ComponentId]s
+/// compare by [std::any::TypeId], whose order can differ between builds.
+fn hash_order(components: &ComponentRegistry, component_id: ComponentId) -> (&str, Option<Entity>) {
+    let name = components.get(component_id).map_or("", |info| info.name());
+    (name, component_id.target)
+}
+
+impl Hasher for StateHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+
+    fn write_u16(&mut self, i: u16) {
+        self.write_u64(i as u64);
+    }
+
+    fn write_u32(&mut self, i: u32) {
+        self.write_u64(i as u64);
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        self.write_u64(i as u64);
+    }
+
+    fn write_i16(&mut self, i: i16) {
+        self.write_u64(i as u64);
+    }
+
+    fn write_i32(&mut self, i: i32) {
+        self.write_u64(i as u64);
+    }
+
+    fn write_i64(&mut self, i: i64) {
+        self.write_u64(i as u64);
+    }
+
+    fn write_isize(&mut self, i: isize) {
+        self.write_u64(i as u64);
+    }
+}
+
+/// A hash of a [World]'s state from [World::state_hash], for detecting desyncs between
+/// simulations that should be identical.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct StateHash {
+    /// The hash of every [Entity] and the types of components it has.
+    pub entities: u64,
+    /// The hash of each type of component registered with [ComponentRegistry::register_hash],
+    /// sorted by type name. Relation pairs are included in their relation's hash.
+    pub components: Vec<(ComponentId, u64)>,
+}
+
+impl StateHash {
+    /// A single hash of the whole state.
+    pub fn total(&self) -> u64 {
+        let mut hasher = StateHasher::default();
+        hasher.write_u64(self.entities);
+        for (_, hash) in &self.components {
+            hasher.write_u64(*hash);
+        }
+        hasher.finish()
+    }
+
+    /// The types of components whose hashes differ from `other`'s, to pinpoint a desync.
+    pub fn diverged(&self, other: &StateHash) -> Vec<ComponentId> {
+        let other: BTreeMap<ComponentId, u64> = other.components.iter().copied().collect();
+        let mut diverged: Vec<ComponentId> = self
+            .components
+            .iter()
+            .filter(|(component_id, hash)| other.get(component_id) != Some(hash))
+            .map(|(component_id, _)| *component_id)
+            .collect();
+        for component_id in other.keys() {
+            if !self.components.iter().any(|(id, _)| id == component_id) {
+                diverged.push(*component_id);
+            }
+        }
+        diverged.sort_unstable();
+        diverged
+    }
+}
+
+impl World {
+    /// Hashes every [Entity] and the components registered with [ComponentRegistry::register_hash].
+    /// Components are visited sorted by type name and rows sorted by [Entity], so [World]s with
+    /// the same state hash the same regardless of the order changes were made in, or of how
+    /// [std::any::TypeId]s are ordered in a particular build.
+    ///
+    /// Panics if a component is mutably borrowed by a query.
+    pub fn state_hash(&self) -> StateHash {
+        let order = |component_id: &ComponentId| hash_order(&self.components, *component_id);
+        // Each non-empty `Archetype` with its channels in hash order.
+        let mut archetypes: Vec<_> = self
+            .archetypes
+            .iter()
+            .filter(|archetype| !archetype.entity_indices.is_empty())
+            .map(|archetype| {
+                let mut channels: Vec<_> = archetype.channels.iter().collect();
+                channels.sort_unstable_by_key(|(component_id, _)| order(component_id));
+                (archetype, channels)
+            })
+            .collect();
+        archetypes.sort_unstable_by(|(_, a), (_, b)| {
+            let a = a.iter().map(|(component_id, _)| order(component_id));
+            let b = b.iter().map(|(component_id, _)| order(component_id));
+            a.cmp(b)
+        });
+
+        let mut entities = StateHasher::default();
+        let mut components: BTreeMap<(&str, ComponentId), StateHasher> = BTreeMap::new();
+        for (archetype, sorted_channels) in archetypes {
+            entities.write_usize(sorted_channels.len());
+            for (component_id, _) in &sorted_channels {
+                entities.component_id(&self.components, *component_id);
+            }
+            let channels: Vec<(ComponentId, &ComponentInfo, RwLockReadGuard<BlobVec>)> =
+                sorted_channels
+                    .into_iter()
+                    .filter_map(|(component_id, channel)| {
+                        let info = self.components.get(*component_id)?;
+                        info.is_hashable().then(|| {
+                            let channel = channel
+                                .try_read()
+                                .expect("Components can't be mutably borrowed while hashing");
+                            (*component_id, info, channel)
+                        })
+                    })
+                    .collect();
+
+            let mut rows: Vec<(Entity, usize)> = archetype
+                .entity_indices
+                .iter()
+                .enumerate()
+                .map(|(row, index)| (self.entity_manager.get_entity(*index), row))
+                .collect();
+            rows.sort_unstable();
+            entities.write_usize(rows.len());
+            for (entity, row) in rows {
+                entities.entity(entity);
+                for (component_id, info, channel) in &channels {
+                    let hasher = components.entry((info.name(), info.id())).or_default();
+                    hasher.entity(entity);
+                    hasher.component_id(&self.components, *component_id);
+                    // SAFETY: The channel stores components of `info`'s type.
+                    unsafe { info.hash(channel.get(row), hasher) };
+                }
+            }
+        }
+
+        let mut sparse_storages: Vec<(ComponentId, &ComponentInfo, &dyn AnySparseStorage)> = self
+            .sparse_storages
+            .iter()
+            .filter_map(|(component_id, storage)| {
+                let info = self.components.get(*component_id)?;
+                info.is_hashable()
+                    .then_some((*component_id, info, &**storage))
+            })
+            .collect();
+        sparse_storages.sort_unstable_by_key(|(component_id, _, _)| order(component_id));
+        for (component_id, info, storage) in sparse_storages {
+            let mut sparse_entities: Vec<Entity> = storage
+                .entity_indices()
+                .iter()
+                .map(|index| self.entity_manager.get_entity(*index))
+                .collect();
+            sparse_entities.sort_unstable();
+            let hasher = components.entry((info.name(), info.id())).or_default();
+            for entity in sparse_entities {
+                let component = storage
+                    .try_component_ptr(entity.index)
+                    .expect("Components can't be mutably borrowed while hashing");
+                hasher.entity(entity);
+                hasher.component_id(&self.components, component_id);
+                // SAFETY: The storage stores components of `info`'s type.
+                unsafe { info.hash(component, hasher) };
+            }
+        }
+
+        StateHash {
+            entities: entities.finish(),
+            components: components
+                .into_iter()
+                .map(|((_, component_id), hasher)| (component_id, hasher.finish()))
+                .collect(),
+        }
+    }
+}
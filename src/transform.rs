@@ -0,0 +1,269 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::*;
+
+/// A column-major 4x4 matrix.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Mat4(pub [[f32; 4]; 4]);
+
+impl Mat4 {
+    pub const IDENTITY: Mat4 = Mat4([
+        [1.0, 0.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0, 0.0],
+        [0.0, 0.0, 1.0, 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ]);
+
+    pub fn from_translation(translation: [f32; 3]) -> Self {
+        let mut m = Self::IDENTITY;
+        m.0[3][..3].copy_from_slice(&translation);
+        m
+    }
+
+    pub fn from_scale(scale: [f32; 3]) -> Self {
+        let mut m = Self::IDENTITY;
+        for (i, scale) in scale.iter().enumerate() {
+            m.0[i][i] = *scale;
+        }
+        m
+    }
+
+    /// A rotation of `angle` radians around the Z axis.
+    pub fn from_rotation_z(angle: f32) -> Self {
+        let (sin, cos) = angle.sin_cos();
+        let mut m = Self::IDENTITY;
+        m.0[0][0] = cos;
+        m.0[0][1] = sin;
+        m.0[1][0] = -sin;
+        m.0[1][1] = cos;
+        m
+    }
+
+    pub fn translation(&self) -> [f32; 3] {
+        [self.0[3][0], self.0[3][1], self.0[3][2]]
+    }
+
+    pub fn transform_point(&self, point: [f32; 3]) -> [f32; 3] {
+        let mut out = self.translation();
+        for (column, p) in self.0.iter().zip(point.iter()) {
+            for (out, c) in out.iter_mut().zip(column.iter()) {
+                *out += c * p;
+            }
+        }
+        out
+    }
+}
+
+impl Default for Mat4 {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl std::ops::Mul for Mat4 {
+    type Output = Mat4;
+    fn mul(self, other: Mat4) -> Mat4 {
+        let mut out = [[0.0; 4]; 4];
+        for (out_column, other_column) in out.iter_mut().zip(other.0.iter()) {
+            for (row, out) in out_column.iter_mut().enumerate() {
+                *out = (0..4).map(|k| self.0[k][row] * other_column[k]).sum();
+            }
+        }
+        Mat4(out)
+    }
+}
+
+/// The [Entity] whose [GlobalTransform] this [Entity]'s [LocalTransform] is relative to.
+/// Parents must form a tree.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct TransformParent(pub Entity);
+
+impl ComponentTrait for TransformParent {
+    fn clone_vec(data: &[Self]) -> Option<Vec<Self>> {
+        Some(data.into())
+    }
+}
+
+/// A transform relative to the [TransformParent], or to the world if there is no parent.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct LocalTransform(pub Mat4);
+
+impl ComponentTrait for LocalTransform {
+    fn clone_vec(data: &[Self]) -> Option<Vec<Self>> {
+        Some(data.into())
+    }
+}
+
+/// The transform relative to the world. Updated by [TransformPropagation::propagate].
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct GlobalTransform(Mat4);
+
+impl GlobalTransform {
+    pub fn get(&self) -> Mat4 {
+        self.0
+    }
+}
+
+impl ComponentTrait for GlobalTransform {
+    fn clone_vec(data: &[Self]) -> Option<Vec<Self>> {
+        Some(data.into())
+    }
+}
+
+/// Computes [GlobalTransform]s from [LocalTransform]s and [TransformParent]s.
+/// Only the [Entity]s whose [LocalTransform] or [TransformParent] was added, changed,
+/// or removed since the last call to [TransformPropagation::propagate], and their
+/// descendants, are recomputed.
+#[derive(Default)]
+pub struct TransformPropagation {
+    removed_parents: RemovedComponents<TransformParent>,
+    removed_locals: RemovedComponents<LocalTransform>,
+    changed_parents: QueryState,
+    changed_locals: QueryState,
+    /// The parent of each [Entity] with a [TransformParent], and the children of each parent.
+    /// Updated from the added, changed and removed [TransformParent]s on each call, so the
+    /// cost of a call doesn't grow with the size of the unchanged hierarchy.
+    parents: HashMap<Entity, Entity>,
+    children: HashMap<Entity, Vec<Entity>>,
+    /// Scratch-buffers reused between calls.
+    dirty: HashSet<Entity>,
+    /// The topmost dirty [Entity]s, with their parent's [GlobalTransform].
+    roots: Vec<(Entity, Mat4)>,
+    stack: Vec<(Entity, Mat4)>,
+}
+
+impl TransformPropagation {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Updates the [GlobalTransform] of every [Entity] whose [LocalTransform] or
+    /// [TransformParent] changed since the last call, along with all of their descendants.
+    /// Each dirty subtree is walked from its root, in archetype order. An [Entity] whose
+    /// parent has no [LocalTransform], such as a despawned parent, is treated as a root.
+    pub fn propagate(&mut self, world: &mut World) {
+        self.dirty.clear();
+        // `Entity`s whose parent was removed are now roots.
+        for entity in world.removed(&mut self.removed_parents) {
+            self.unlink(*entity);
+            self.dirty.insert(*entity);
+        }
+        {
+            let query = world.query_with_state::<All<&TransformParent, Changed<TransformParent>>>(
+                &mut self.changed_parents,
+            );
+            for (entity, parent) in query.entities().zip(query.iter()) {
+                self.link(entity, parent.0);
+                self.dirty.insert(entity);
+            }
+        }
+        // The children of an `Entity` whose `LocalTransform` was removed are now roots.
+        for entity in world.removed(&mut self.removed_locals) {
+            if let Some(children) = self.children.get(entity) {
+                self.dirty.extend(children.iter().copied());
+            }
+        }
+        self.dirty.extend(
+            world
+                .query_with_state::<All<&LocalTransform, Changed<LocalTransform>>>(
+                    &mut self.changed_locals,
+                )
+                .entities(),
+        );
+        if self.dirty.is_empty() {
+            return;
+        }
+
+        // Dirty `Entity`s with a dirty ancestor are recomputed with that ancestor's subtree.
+        self.roots.clear();
+        'dirty: for entity in self.dirty.iter() {
+            let mut parent_global = None;
+            let mut ancestor = *entity;
+            while let Ok(parent) = world.get::<TransformParent>(ancestor).map(|p| p.0) {
+                if world.get::<LocalTransform>(parent).is_err() {
+                    break;
+                }
+                if self.dirty.contains(&parent) {
+                    continue 'dirty;
+                }
+                if parent_global.is_none() {
+                    parent_global = Some(
+                        world
+                            .get::<GlobalTransform>(parent)
+                            .map_or(Mat4::IDENTITY, |g| g.0),
+                    );
+                }
+                ancestor = parent;
+            }
+            if world.get::<LocalTransform>(*entity).is_ok() {
+                self.roots
+                    .push((*entity, parent_global.unwrap_or(Mat4::IDENTITY)));
+            }
+        }
+        let entity_manager = &world.entity_manager;
+        self.roots.sort_unstable_by_key(|(entity, _)| {
+            let location = entity_manager.get_entity_location(*entity).unwrap();
+            (location.storage_index, location.index_within_storage)
+        });
+
+        for root in self.roots.iter() {
+            self.stack.push(*root);
+            propagate_subtree(world, &self.children, &mut self.stack);
+        }
+    }
+
+    fn link(&mut self, child: Entity, parent: Entity) {
+        match self.parents.insert(child, parent) {
+            Some(old_parent) if old_parent == parent => return,
+            Some(old_parent) => self.remove_child(old_parent, child),
+            None => {}
+        }
+        self.children.entry(parent).or_default().push(child);
+    }
+
+    fn unlink(&mut self, child: Entity) {
+        if let Some(parent) = self.parents.remove(&child) {
+            self.remove_child(parent, child);
+        }
+    }
+
+    fn remove_child(&mut self, parent: Entity, child: Entity) {
+        if let Some(children) = self.children.get_mut(&parent) {
+            children.retain(|c| *c != child);
+            if children.is_empty() {
+                self.children.remove(&parent);
+            }
+        }
+    }
+}
+
+/// Recomputes the [GlobalTransform] of each [Entity] on `stack` and all of their descendants.
+/// Children are checked against their [TransformParent], so links to children whose parent
+/// was dropped without being recorded as removed, such as by [World::restore], are skipped.
+fn propagate_subtree(
+    world: &mut World,
+    children: &HashMap<Entity, Vec<Entity>>,
+    stack: &mut Vec<(Entity, Mat4)>,
+) {
+    while let Some((entity, parent_global)) = stack.pop() {
+        let Ok(local) = world.get::<LocalTransform>(entity).map(|l| l.0) else {
+            continue;
+        };
+        let global = parent_global * local;
+        if let Ok(global_transform) = world.get_mut::<GlobalTransform>(entity) {
+            global_transform.0 = global;
+        }
+        if let Some(children) = children.get(&entity) {
+            stack.extend(
+                children
+                    .iter()
+                    .filter(|child| {
+                        world
+                            .get::<TransformParent>(**child)
+                            .is_ok_and(|parent| parent.0 == entity)
+                    })
+                    .map(|child| (*child, global)),
+            );
+        }
+    }
+}
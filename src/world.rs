@@ -1,68 +1,186 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        RwLockReadGuard,
+    },
+};
+
 use crate::*;
 
-pub trait ArchetypeComponentChannel {
-    fn as_any(&self) -> &dyn std::any::Any;
-    fn as_any_mut(&mut self) -> &mut dyn std::any::Any;
-    fn migrate(&mut self, other: &mut dyn ArchetypeComponentChannel, index: usize);
-    fn swap_remove(&mut self, index: usize);
-    fn push(&mut self, component: &mut dyn AnyComponentTrait);
+/// The change ticks for each component in a channel, used for change detection.
+/// Ticks are atomic so they can be updated while the channel is borrowed by a query.
+#[derive(Default)]
+pub struct ChannelTicks {
+    /// The change tick each component was added at.
+    pub(crate) added: Vec<AtomicU32>,
+    /// The change tick each component was last mutably accessed at.
+    pub(crate) changed: Vec<AtomicU32>,
 }
 
-impl<COMPONENT: ComponentTrait> ArchetypeComponentChannel for RwLock<Vec<COMPONENT>> {
-    fn as_any(&self) -> &dyn std::any::Any {
-        self
+impl ChannelTicks {
+    pub(crate) fn push(&mut self, change_tick: u32) {
+        self.added.push(AtomicU32::new(change_tick));
+        self.changed.push(AtomicU32::new(change_tick));
     }
-    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
-        self
+
+    fn swap_remove(&mut self, index: usize) {
+        self.added.swap_remove(index);
+        self.changed.swap_remove(index);
     }
-    fn migrate(&mut self, other: &mut dyn ArchetypeComponentChannel, index: usize) {
-        let component = self.get_mut().unwrap().swap_remove(index);
-        other
-            .as_any_mut()
-            .downcast_mut::<RwLock<Vec<COMPONENT>>>()
-            .unwrap()
-            .get_mut()
-            .unwrap()
-            .push(component)
+
+    fn truncate(&mut self, len: usize) {
+        self.added.truncate(len);
+        self.changed.truncate(len);
     }
-    fn swap_remove(&mut self, index: usize) {
-        self.get_mut().unwrap().swap_remove(index);
+
+    fn retain(&mut self, keep: &[bool]) {
+        let mut row = 0..;
+        self.added.retain(|_| keep[row.next().unwrap()]);
+        let mut row = 0..;
+        self.changed.retain(|_| keep[row.next().unwrap()]);
     }
-    fn push(&mut self, component: &mut dyn AnyComponentTrait) {
-        self.get_mut().unwrap().push(
-            component
-                .as_any_mut()
-                .downcast_mut::<Option<COMPONENT>>()
-                .unwrap()
-                .take()
-                .unwrap(),
-        )
+
+    fn migrate(&mut self, other: &mut ChannelTicks, index: usize) {
+        other.added.push(self.added.swap_remove(index));
+        other.changed.push(self.changed.swap_remove(index));
     }
 }
 
 pub struct Archetype {
     pub(crate) entity_indices: Vec<usize>,
-    pub(crate) channels: Vec<(ComponentId, Box<dyn ArchetypeComponentChannel>)>,
+    /// A channel for each component, including [StorageKind::Tag]s so they're part of the
+    /// [Archetype]'s key. Tag channels are always empty.
+    pub(crate) channels: Vec<(ComponentId, RwLock<BlobVec>)>,
+    /// The [ChannelTicks] for each channel, in the same order as `channels`.
+    /// `None` for tags, whose channels are skipped when rows are added, moved or removed.
+    pub(crate) channel_ticks: Vec<Option<ChannelTicks>>,
 }
 
 impl Archetype {
-    fn new() -> Self {
+    pub(crate) fn new() -> Self {
         Self {
             entity_indices: Vec::new(),
             channels: Vec::new(),
+            channel_ticks: Vec::new(),
         }
     }
+
+    pub(crate) fn push_channel(&mut self, component_id: ComponentId, info: &ComponentInfo) {
+        self.channels
+            .push((component_id, RwLock::new(info.new_blob_vec())));
+        self.channel_ticks
+            .push((info.storage() != StorageKind::Tag).then(ChannelTicks::default));
+    }
+
+    /// The channels that store a component for each row, along with their ticks.
+    pub(crate) fn row_channels(
+        &mut self,
+    ) -> impl Iterator<Item = (&mut (ComponentId, RwLock<BlobVec>), &mut ChannelTicks)> {
+        self.channels
+            .iter_mut()
+            .zip(self.channel_ticks.iter_mut())
+            .filter_map(|(channel, ticks)| Some((channel, ticks.as_mut()?)))
+    }
+
     fn remove_entity(
         &mut self,
         entity_manager: &mut entity_manager::EntityManager,
         entity_index_in_archetype: usize,
+    ) {
+        for (channel, ticks) in self.row_channels() {
+            channel
+                .1
+                .get_mut()
+                .unwrap()
+                .swap_remove(entity_index_in_archetype);
+            ticks.swap_remove(entity_index_in_archetype);
+        }
+        self.remove_entity_index(entity_manager, entity_index_in_archetype);
+    }
+
+    /// Removes every row `remove` is `true` for, keeping the order of the rest.
+    /// Columns are truncated if the removed rows are all at the end, otherwise they're compacted.
+    /// Rows past the end of `remove` are kept.
+    pub(crate) fn remove_rows(
+        &mut self,
+        entity_manager: &mut entity_manager::EntityManager,
+        remove: &[bool],
+    ) {
+        let Some(first_removed) = remove.iter().position(|remove| *remove) else {
+            return;
+        };
+        let len = self.entity_indices.len();
+        let keep: Vec<bool> = (0..len)
+            .map(|row| !remove.get(row).copied().unwrap_or(false))
+            .collect();
+        let truncate = keep[first_removed..].iter().all(|keep| !keep);
+        for (channel, ticks) in self.row_channels() {
+            let channel = channel.1.get_mut().unwrap();
+            if truncate {
+                channel.truncate(first_removed);
+                ticks.truncate(first_removed);
+            } else {
+                channel.retain(|row| keep[row]);
+                ticks.retain(&keep);
+            }
+        }
+        if truncate {
+            self.entity_indices.truncate(first_removed);
+        } else {
+            let mut row = 0..;
+            self.entity_indices.retain(|_| keep[row.next().unwrap()]);
+            for (row, entity_index) in self.entity_indices.iter().enumerate().skip(first_removed) {
+                entity_manager.update_entity_index_in_archetype(*entity_index, row);
+            }
+        }
+    }
+
+    /// Drops every row without updating the locations of its [Entity]s.
+    pub(crate) fn clear(&mut self) {
+        for (channel, ticks) in self.row_channels() {
+            channel.1.get_mut().unwrap().truncate(0);
+            ticks.truncate(0);
+        }
+        self.entity_indices.clear();
+    }
+
+    /// Swap removes the [Entity] from `entity_indices` without touching any channels.
+    fn remove_entity_index(
+        &mut self,
+        entity_manager: &mut entity_manager::EntityManager,
+        entity_index_in_archetype: usize,
     ) {
         let to_be_swap_removed = *self.entity_indices.last().unwrap();
         entity_manager
             .update_entity_index_in_archetype(to_be_swap_removed, entity_index_in_archetype);
-        for channel in self.channels.iter_mut() {
-            channel.1.swap_remove(entity_index_in_archetype);
-        }
+        self.entity_indices.swap_remove(entity_index_in_archetype);
+    }
+
+    /// The channel for `component_id` and its ticks, which are `None` for tags.
+    pub(crate) fn channel(
+        &self,
+        component_id: ComponentId,
+    ) -> Option<(&RwLock<BlobVec>, Option<&ChannelTicks>)> {
+        let index = self
+            .channels
+            .binary_search_by_key(&component_id, |c| c.0)
+            .ok()?;
+        Some((&self.channels[index].1, self.channel_ticks[index].as_ref()))
+    }
+
+    pub(crate) fn channel_mut(
+        &mut self,
+        component_id: ComponentId,
+    ) -> Option<(&mut BlobVec, Option<&mut ChannelTicks>)> {
+        let index = self
+            .channels
+            .binary_search_by_key(&component_id, |c| c.0)
+            .ok()?;
+        Some((
+            self.channels[index].1.get_mut().unwrap(),
+            self.channel_ticks[index].as_mut(),
+        ))
     }
 
     pub fn get_corresponding_channels<const COUNT: usize>(
@@ -81,11 +199,17 @@ impl Archetype {
     }
 
     /// Moves an [Entity]'s components from this [Archetype] to another [Archetype]
+    /// Only channels shared by both [Archetype]s are touched.
     pub fn migrate_entity_components(&mut self, other: &mut Archetype, entity_index: usize) {
-        for channel in self.channels.iter_mut() {
-            for other_channel in other.channels.iter_mut() {
+        for (channel, ticks) in self.row_channels() {
+            for (other_channel, other_ticks) in other.row_channels() {
                 if channel.0 == other_channel.0 {
-                    channel.1.migrate(&mut *other_channel.1, entity_index);
+                    channel
+                        .1
+                        .get_mut()
+                        .unwrap()
+                        .migrate(other_channel.1.get_mut().unwrap(), entity_index);
+                    ticks.migrate(other_ticks, entity_index);
                     break;
                 }
             }
@@ -93,24 +217,28 @@ impl Archetype {
     }
 }
 
-pub(crate) fn get_vec_from_channel<COMPONENT: ComponentTrait>(
-    channel: &mut dyn ArchetypeComponentChannel,
-) -> &mut Vec<COMPONENT> {
-    channel
-        .as_any_mut()
-        .downcast_mut::<RwLock<Vec<COMPONENT>>>()
-        .unwrap()
-        .get_mut()
-        .unwrap()
+/// A borrow of one [Entity]'s component. See [World::get].
+pub struct ComponentRef<'a, T> {
+    guard: ComponentGuard<'a, T>,
 }
 
-pub(crate) fn get_rwlock_from_channel<COMPONENT: ComponentTrait>(
-    channel: &dyn ArchetypeComponentChannel,
-) -> &RwLock<Vec<COMPONENT>> {
-    channel
-        .as_any()
-        .downcast_ref::<RwLock<Vec<COMPONENT>>>()
-        .unwrap()
+enum ComponentGuard<'a, T> {
+    Table(ChannelReadGuard<'a, T>, usize),
+    Sparse(RwLockReadGuard<'a, T>),
+    /// Tags aren't stored, so any well-aligned pointer to one is valid.
+    Tag,
+}
+
+impl<'a, T> std::ops::Deref for ComponentRef<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        match &self.guard {
+            ComponentGuard::Table(guard, index) => &guard[*index],
+            ComponentGuard::Sparse(guard) => guard,
+            // SAFETY: `ComponentInfo::new` checked that tags are zero-sized.
+            ComponentGuard::Tag => unsafe { std::ptr::NonNull::<T>::dangling().as_ref() },
+        }
+    }
 }
 
 pub struct World {
@@ -119,6 +247,22 @@ pub struct World {
     pub(crate) archetype_lookup: archetype_lookup::ArchetypeLookup,
     /// A scratch-buffer for ComponentIds
     pub(crate) component_ids_temp: Vec<ComponentId>,
+    /// Incremented each time a query runs with a [QueryState]. Used to detect added and changed
+    /// components.
+    pub(crate) change_tick: AtomicU32,
+    pub(crate) removed_components: HashMap<ComponentId, RemovalLog>,
+    pub(crate) events: HashMap<std::any::TypeId, Box<dyn AnyEventQueue>>,
+    pub(crate) component_hooks: HashMap<ComponentId, ComponentHooks>,
+    /// Structural changes made by hooks, applied after the change that ran the hooks.
+    pub(crate) hook_commands: Commands,
+    pub(crate) sparse_storages: SparseStorages,
+    pub(crate) components: ComponentRegistry,
+}
+
+impl Default for World {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl World {
@@ -128,34 +272,103 @@ impl World {
             archetypes: Vec::new(),
             archetype_lookup: archetype_lookup::ArchetypeLookup::new(),
             component_ids_temp: Vec::new(),
+            change_tick: AtomicU32::new(1),
+            removed_components: HashMap::new(),
+            events: HashMap::new(),
+            component_hooks: HashMap::new(),
+            hook_commands: Commands::new(),
+            sparse_storages: HashMap::new(),
+            components: ComponentRegistry::default(),
+        }
+    }
+
+    /// The change tick that added and replaced components are currently recorded at.
+    pub fn change_tick(&self) -> u32 {
+        self.change_tick.load(Ordering::Relaxed)
+    }
+
+    /// The ticks for a query run without a [QueryState], which sees every component as
+    /// added and changed and doesn't advance the change tick.
+    pub(crate) fn query_ticks(&self) -> QueryTicks {
+        QueryTicks {
+            last_run: 0,
+            this_run: self.change_tick(),
+        }
+    }
+
+    /// Advances the change tick for a new run of the query `state` belongs to, so changes
+    /// made after this run are newer than its `last_run` next time.
+    pub(crate) fn next_query_ticks(&self, state: &mut QueryState) -> QueryTicks {
+        let this_run = self.change_tick.fetch_add(1, Ordering::Relaxed);
+        QueryTicks {
+            last_run: std::mem::replace(&mut state.last_run, this_run),
+            this_run,
         }
     }
 
+    /// Spawns an [Entity] with `components`.
+    /// Panics if `components` has more than one of the same type of component.
     pub fn spawn<COMPONENTS: ComponentBundleTrait>(&mut self, components: COMPONENTS) -> Entity {
-        let mut entity = Entity::from_index_and_generation(0, 0);
+        self.try_spawn(components)
+            .expect("Cannot spawn `Entity`s with multiple of the same component")
+    }
+
+    /// Like [World::spawn] but errors with [ECSError::DuplicateComponent] instead of panicking.
+    pub fn try_spawn<COMPONENTS: ComponentBundleTrait>(
+        &mut self,
+        components: COMPONENTS,
+    ) -> Result<Entity, ECSError> {
+        let mut result = Err(ECSError::DuplicateComponent);
         components.get_components_and_ids(|v| {
-            entity = self.spawn_inner(v);
+            result = self.spawn_inner(v, None);
         });
-        entity
+        self.apply_hook_commands();
+        result
     }
 
-    fn spawn_inner(
+    /// Spawns an [Entity] with the exact index and generation of `entity`,
+    /// for recreating [Entity]s sent over the network or loaded from a save.
+    /// If `entity` is already alive its components are added as with [World::add_components].
+    /// Errors with [ECSError::EntityIndexInUse] if another generation of `entity` is alive
+    /// and [ECSError::EntityNoLongerExists] if `entity` is older than its index's generation.
+    /// Errors with [ECSError::EntityIndexOutOfRange] if `entity`'s index is above
+    /// [MAX_SPAWN_AT_INDEX].
+    pub fn spawn_at<COMPONENTS: ComponentBundleTrait>(
+        &mut self,
+        entity: Entity,
+        components: COMPONENTS,
+    ) -> Result<(), ECSError> {
+        self.flush_reserved_entities();
+        let mut result = Ok(());
+        components.get_components_and_ids(|components_and_ids| {
+            result = if self.entity_manager.get_entity_location(entity).is_ok() {
+                self.add_components_inner(entity, components_and_ids)
+            } else {
+                self.spawn_inner(components_and_ids, Some(entity))
+                    .map(|_| ())
+            };
+        });
+        self.apply_hook_commands();
+        result
+    }
+
+    /// Spawns a new [Entity], or `entity` with its exact index and generation if it's `Some`.
+    pub(crate) fn spawn_inner(
         &mut self,
         components_and_ids: &mut [(&mut dyn AnyComponentTrait, ComponentId)],
-    ) -> Entity {
-        components_and_ids.sort_by_key(|v| v.1);
+        entity: Option<Entity>,
+    ) -> Result<Entity, ECSError> {
+        self.flush_reserved_entities();
+
+        let (components_and_ids, sparse_components_and_ids) =
+            split_sparse_components(components_and_ids)?;
+        if let Some(entity) = entity {
+            self.entity_manager.spawn_at(entity)?;
+        }
         self.component_ids_temp.clear();
         self.component_ids_temp
             .extend(components_and_ids.iter().map(|v| v.1));
 
-        let len = self.component_ids_temp.len();
-        self.component_ids_temp.dedup();
-        assert_eq!(
-            len,
-            self.component_ids_temp.len(),
-            "Cannot spawn `Entity`s with multiple of the same component"
-        );
-
         let archetype_index = if let Some(archetype_index) = self
             .archetype_lookup
             .get_exact_archetype(&self.component_ids_temp)
@@ -165,83 +378,608 @@ impl World {
             // Create a new archetype
             let mut new_archetype = Archetype::new();
             for (component, component_id) in components_and_ids.iter() {
-                new_archetype
-                    .channels
-                    .push((*component_id, component.new_archetype_channel()));
-            }
-            let archetype_index = self.archetypes.len();
-            self.archetypes.push(new_archetype);
-            self.archetype_lookup
-                .new_archetype(&self.component_ids_temp);
+                let info = self
+                    .components
+                    .get_or_register(*component_id, || component.component_info());
+                new_archetype.push_channel(*component_id, info);
+            }
+            let component_ids = std::mem::take(&mut self.component_ids_temp);
+            let archetype_index = self.push_archetype(&component_ids, new_archetype);
+            self.component_ids_temp = component_ids;
             archetype_index
         };
 
+        let change_tick = *self.change_tick.get_mut();
         let archetype = &mut self.archetypes[archetype_index];
-        for ((component, _), (_, channel)) in components_and_ids
+        for (((component, _), (_, channel)), ticks) in components_and_ids
             .iter_mut()
             .zip(archetype.channels.iter_mut())
+            .zip(archetype.channel_ticks.iter_mut())
         {
-            channel.push(*component)
+            if let Some(ticks) = ticks {
+                component.push_into(channel.get_mut().unwrap());
+                ticks.push(change_tick);
+            }
         }
 
-        let entity = self.entity_manager.new_entity(EntityLocation {
+        let entity_location = EntityLocation {
             storage_index: archetype_index,
             index_within_storage: archetype.entity_indices.len(),
-        });
+        };
+        let entity = if let Some(entity) = entity {
+            self.entity_manager
+                .update_entity_location(entity.index, entity_location);
+            entity
+        } else {
+            self.entity_manager.new_entity(entity_location)
+        };
         archetype.entity_indices.push(entity.index);
-        entity
+        for (component, component_id) in sparse_components_and_ids.iter_mut() {
+            self.sparse_storages
+                .entry(*component_id)
+                .or_insert_with(|| {
+                    self.components
+                        .get_or_register(*component_id, || component.component_info());
+                    component.new_sparse_storage()
+                })
+                .insert(entity.index, *component, change_tick);
+        }
+        self.run_hooks_for_entity(&[HookKind::Add, HookKind::Insert], entity);
+        Ok(entity)
+    }
+
+    /// [ComponentId]s passed in must be sorted and match the channels of `archetype`.
+    pub(crate) fn push_archetype(
+        &mut self,
+        component_ids: &[ComponentId],
+        archetype: Archetype,
+    ) -> usize {
+        let archetype_index = self.archetypes.len();
+        self.archetypes.push(archetype);
+        self.archetype_lookup.new_archetype(component_ids);
+        archetype_index
+    }
+
+    /// Gets the [Archetype] with no components, used to store [Entity]s before components are added.
+    fn empty_archetype_index(&mut self) -> usize {
+        if let Some(archetype_index) = self.archetype_lookup.get_exact_archetype(&[]) {
+            archetype_index
+        } else {
+            self.push_archetype(&[], Archetype::new())
+        }
+    }
+
+    /// Reserves an [Entity] without exclusive access to the [World].
+    /// The [Entity] is spawned without components during the next structural change.
+    pub fn reserve_entity(&self) -> Entity {
+        self.entity_manager.reserve_entity()
+    }
+
+    /// Reserves multiple [Entity]s at once. See [World::reserve_entity].
+    pub fn reserve_entities(&self, count: usize) -> impl Iterator<Item = Entity> + '_ {
+        self.entity_manager.reserve_entities(count)
+    }
+
+    /// Makes reserved [Entity]s valid by placing them in the empty [Archetype].
+    pub(crate) fn flush_reserved_entities(&mut self) {
+        if !self.entity_manager.needs_flush() {
+            return;
+        }
+        let storage_index = self.empty_archetype_index();
+        let archetype = &mut self.archetypes[storage_index];
+        self.entity_manager.flush_reserved_entities(|entity_index| {
+            archetype.entity_indices.push(entity_index);
+            EntityLocation {
+                storage_index,
+                index_within_storage: archetype.entity_indices.len() - 1,
+            }
+        });
     }
 
     pub fn despawn(&mut self, entity: Entity) -> Result<(), ECSError> {
+        let result = self.despawn_inner(entity);
+        self.apply_hook_commands();
+        result
+    }
+
+    pub(crate) fn despawn_inner(&mut self, entity: Entity) -> Result<(), ECSError> {
+        self.flush_reserved_entities();
         let entity_location = self.entity_manager.get_entity_location(entity)?;
+        self.run_hooks_for_entity(&[HookKind::Replace, HookKind::Remove], entity);
+        // Hooks may have reserved `Entity`s.
+        self.flush_reserved_entities();
+        let parent = self.get::<Parent>(entity).map(|parent| parent.get()).ok();
+        for i in 0..self.archetypes[entity_location.storage_index]
+            .channels
+            .len()
+        {
+            let component_id = self.archetypes[entity_location.storage_index].channels[i].0;
+            self.record_removal(entity, component_id);
+        }
+        for (component_id, storage) in self.sparse_storages.iter_mut() {
+            if storage.remove(entity.index) {
+                self.removed_components
+                    .entry(*component_id)
+                    .or_default()
+                    .entities
+                    .push(entity);
+            }
+        }
         self.archetypes[entity_location.storage_index].remove_entity(
             &mut self.entity_manager,
             entity_location.index_within_storage,
         );
         self.entity_manager.despawn_entity(entity);
+        if let Some(parent) = parent {
+            self.detach_despawned(&[(entity, parent)]);
+        }
         Ok(())
     }
 
+    /// Adds components to an existing [Entity].
+    /// Components the [Entity] already has are replaced in place and the old values dropped.
+    /// Use [World::insert] to get the old value back.
     pub fn add_components<COMPONENTS: ComponentBundleTrait>(
         &mut self,
         entity: Entity,
         components: COMPONENTS,
     ) -> Result<(), ECSError> {
+        let mut result = Ok(());
+        components.get_components_and_ids(|components_and_ids| {
+            result = self.add_components_inner(entity, components_and_ids);
+        });
+        self.apply_hook_commands();
+        result
+    }
+
+    /// Adds `component` to an [Entity] and returns the `T` it replaced, if there was one.
+    /// An existing `T` is replaced in place without moving the [Entity] to another [Archetype].
+    pub fn insert<T: ComponentTrait>(
+        &mut self,
+        entity: Entity,
+        component: T,
+    ) -> Result<Option<T>, ECSError> {
+        self.flush_reserved_entities();
+        let result = match self.get::<T>(entity).map(drop) {
+            Ok(()) => {
+                self.run_hooks(&[HookKind::Replace], entity, T::component_id());
+                let old = std::mem::replace(self.get_mut::<T>(entity)?, component);
+                self.run_hooks(&[HookKind::Insert], entity, T::component_id());
+                Ok(Some(old))
+            }
+            Err(ECSError::NoMatchingComponent) => self
+                .add_components_inner(entity, &mut [(&mut Some(component), T::component_id())])
+                .map(|()| None),
+            Err(error) => Err(error),
+        };
+        self.apply_hook_commands();
+        result
+    }
+
+    /// Adds `component` to an [Entity].
+    /// Errors with [ECSError::ComponentAlreadyPresent] if the [Entity] already has a `T`.
+    pub fn try_insert<T: ComponentTrait>(
+        &mut self,
+        entity: Entity,
+        component: T,
+    ) -> Result<(), ECSError> {
+        self.flush_reserved_entities();
+        match self.get::<T>(entity).map(drop) {
+            Ok(()) => Err(ECSError::ComponentAlreadyPresent),
+            Err(ECSError::NoMatchingComponent) => self.add_components(entity, component),
+            Err(error) => Err(error),
+        }
+    }
+
+    pub(crate) fn add_components_inner(
+        &mut self,
+        entity: Entity,
+        components_and_ids: &mut [(&mut dyn AnyComponentTrait, ComponentId)],
+    ) -> Result<(), ECSError> {
+        self.flush_reserved_entities();
         let entity_location = self.entity_manager.get_entity_location(entity)?;
-        let archetype = &self.archetypes[entity_location.storage_index];
 
-        // Todo: Merge the added [ComponentID]s with the existing [Archetype]'s IDs.
-        // Then find or create the new [Archetype] to migrate this [Entity] to.
-        todo!()
+        let (components_and_ids, sparse_components_and_ids) =
+            split_sparse_components(components_and_ids)?;
+        self.add_sparse_components(entity, sparse_components_and_ids);
+
+        let change_tick = *self.change_tick.get_mut();
+
+        // Merge the added [ComponentId]s with the existing [Archetype]'s IDs.
+        // Components the [Archetype] already has are replaced in place.
+        let mut component_ids = std::mem::take(&mut self.component_ids_temp);
+        component_ids.clear();
+        component_ids.extend(
+            self.archetypes[entity_location.storage_index]
+                .channels
+                .iter()
+                .map(|c| c.0),
+        );
+        let mut new_component_count = 0;
+        for (component, component_id) in components_and_ids.iter_mut() {
+            if self.archetypes[entity_location.storage_index]
+                .channel_mut(*component_id)
+                .is_some()
+            {
+                self.run_hooks(&[HookKind::Replace], entity, *component_id);
+                let (channel, ticks) = self.archetypes[entity_location.storage_index]
+                    .channel_mut(*component_id)
+                    .unwrap();
+                if let Some(ticks) = ticks {
+                    component.replace_in(channel, entity_location.index_within_storage);
+                    *ticks.changed[entity_location.index_within_storage].get_mut() = change_tick;
+                }
+                self.run_hooks(&[HookKind::Insert], entity, *component_id);
+            } else {
+                component_ids.push(*component_id);
+                new_component_count += 1;
+            }
+        }
+
+        if new_component_count > 0 {
+            component_ids.sort();
+
+            // Find or create the new [Archetype] to migrate this [Entity] to.
+            let new_archetype_index = if let Some(archetype_index) =
+                self.archetype_lookup.get_exact_archetype(&component_ids)
+            {
+                archetype_index
+            } else {
+                let old_archetype = &self.archetypes[entity_location.storage_index];
+                let mut new_archetype = Archetype::new();
+                for component_id in component_ids.iter() {
+                    let info = match old_archetype
+                        .channels
+                        .binary_search_by_key(component_id, |c| c.0)
+                    {
+                        Ok(_) => self.components.get(*component_id).unwrap(),
+                        Err(_) => {
+                            let (component, _) = components_and_ids
+                                .iter()
+                                .find(|(_, id)| id == component_id)
+                                .unwrap();
+                            self.components
+                                .get_or_register(*component_id, || component.component_info())
+                        }
+                    };
+                    new_archetype.push_channel(*component_id, info);
+                }
+                self.push_archetype(&component_ids, new_archetype)
+            };
+
+            let new_archetype = self.migrate_entity(entity, entity_location, new_archetype_index);
+            for (component, component_id) in components_and_ids.iter_mut() {
+                if let Some((channel, Some(ticks))) = new_archetype.channel_mut(*component_id) {
+                    // Components that were replaced in place have already been taken.
+                    if component.is_some() {
+                        component.push_into(channel);
+                        ticks.push(change_tick);
+                    }
+                }
+            }
+
+            if !self.component_hooks.is_empty() {
+                for (_, component_id) in components_and_ids.iter() {
+                    // Replaced components already ran their hooks.
+                    if self.archetypes[entity_location.storage_index]
+                        .channel_mut(*component_id)
+                        .is_none()
+                    {
+                        self.run_hooks(&[HookKind::Add, HookKind::Insert], entity, *component_id);
+                    }
+                }
+            }
+        }
+
+        self.component_ids_temp = component_ids;
+        Ok(())
     }
 
+    fn add_sparse_components(
+        &mut self,
+        entity: Entity,
+        components_and_ids: &mut [(&mut dyn AnyComponentTrait, ComponentId)],
+    ) {
+        let change_tick = *self.change_tick.get_mut();
+        for (component, component_id) in components_and_ids.iter_mut() {
+            let storage = self
+                .sparse_storages
+                .entry(*component_id)
+                .or_insert_with(|| {
+                    self.components
+                        .get_or_register(*component_id, || component.component_info());
+                    component.new_sparse_storage()
+                });
+            if storage.contains(entity.index) {
+                self.run_hooks(&[HookKind::Replace], entity, *component_id);
+                self.sparse_storages.get_mut(component_id).unwrap().insert(
+                    entity.index,
+                    *component,
+                    change_tick,
+                );
+                self.run_hooks(&[HookKind::Insert], entity, *component_id);
+            } else {
+                storage.insert(entity.index, *component, change_tick);
+                self.run_hooks(&[HookKind::Add, HookKind::Insert], entity, *component_id);
+            }
+        }
+    }
+
+    /// Removes components from an [Entity] and returns them.
     pub fn remove_components<COMPONENTS: ComponentBundleTrait>(
         &mut self,
         entity: Entity,
     ) -> Result<COMPONENTS, ECSError> {
+        let result = COMPONENTS::from_components(|components_and_ids| {
+            self.remove_components_inner(entity, components_and_ids)
+        });
+        self.apply_hook_commands();
+        result
+    }
+
+    pub(crate) fn remove_components_inner(
+        &mut self,
+        entity: Entity,
+        components_and_ids: &mut [(&mut dyn AnyComponentTrait, ComponentId)],
+    ) -> Result<(), ECSError> {
+        self.flush_reserved_entities();
         let entity_location = self.entity_manager.get_entity_location(entity)?;
+        let (components_and_ids, sparse_components_and_ids) =
+            split_sparse_components(components_and_ids)?;
+        for (_, component_id) in sparse_components_and_ids.iter() {
+            if !self
+                .sparse_storages
+                .get(component_id)
+                .is_some_and(|s| s.contains(entity.index))
+            {
+                return Err(ECSError::NoMatchingComponent);
+            }
+        }
 
-        // Todo: Remove the components and store them in the component bundle.
-        //  Then find or create the new [Archetype] to migrate this [Entity] to.
-        todo!()
+        if !components_and_ids.is_empty() {
+            self.remove_table_components(entity, entity_location, components_and_ids)?;
+        }
+
+        for (component, component_id) in sparse_components_and_ids.iter_mut() {
+            self.run_hooks(
+                &[HookKind::Replace, HookKind::Remove],
+                entity,
+                *component_id,
+            );
+            self.sparse_storages
+                .get_mut(component_id)
+                .unwrap()
+                .remove_into(entity.index, *component);
+            self.record_removal(entity, *component_id);
+        }
+        Ok(())
+    }
+
+    fn remove_table_components(
+        &mut self,
+        entity: Entity,
+        entity_location: EntityLocation,
+        components_and_ids: &mut [(&mut dyn AnyComponentTrait, ComponentId)],
+    ) -> Result<(), ECSError> {
+        let old_archetype = &self.archetypes[entity_location.storage_index];
+
+        let mut component_ids = std::mem::take(&mut self.component_ids_temp);
+        component_ids.clear();
+        component_ids.extend(old_archetype.channels.iter().map(|c| c.0));
+        for (_, component_id) in components_and_ids.iter() {
+            if let Ok(i) = component_ids.binary_search(component_id) {
+                component_ids.remove(i);
+            } else {
+                self.component_ids_temp = component_ids;
+                return Err(ECSError::NoMatchingComponent);
+            }
+        }
+
+        // Find or create the new [Archetype] to migrate this [Entity] to.
+        let new_archetype_index = if let Some(archetype_index) =
+            self.archetype_lookup.get_exact_archetype(&component_ids)
+        {
+            archetype_index
+        } else {
+            let mut new_archetype = Archetype::new();
+            for (component_id, _) in old_archetype.channels.iter() {
+                if component_ids.binary_search(component_id).is_ok() {
+                    new_archetype
+                        .push_channel(*component_id, self.components.get(*component_id).unwrap());
+                }
+            }
+            self.push_archetype(&component_ids, new_archetype)
+        };
+
+        for (_, component_id) in components_and_ids.iter() {
+            self.run_hooks(
+                &[HookKind::Replace, HookKind::Remove],
+                entity,
+                *component_id,
+            );
+        }
+
+        let old_archetype = &mut self.archetypes[entity_location.storage_index];
+        for (component, component_id) in components_and_ids.iter_mut() {
+            let (channel, ticks) = old_archetype.channel_mut(*component_id).unwrap();
+            match ticks {
+                Some(ticks) => {
+                    channel.swap_remove_with(entity_location.index_within_storage, |ptr| {
+                        // SAFETY: The channel stores the component's type and gives up ownership.
+                        unsafe { component.move_from(ptr) }
+                    });
+                    ticks.swap_remove(entity_location.index_within_storage);
+                }
+                // SAFETY: Tags are zero-sized so any well-aligned pointer is a valid one.
+                None => unsafe { component.move_from(channel.get(0)) },
+            }
+        }
+        self.migrate_entity(entity, entity_location, new_archetype_index);
+        for (_, component_id) in components_and_ids.iter() {
+            self.record_removal(entity, *component_id);
+        }
+
+        self.component_ids_temp = component_ids;
+        Ok(())
+    }
+
+    /// Moves an [Entity] and the components shared with the new [Archetype].
+    /// Components not shared must already have been removed by the caller.
+    fn migrate_entity(
+        &mut self,
+        entity: Entity,
+        entity_location: EntityLocation,
+        new_archetype_index: usize,
+    ) -> &mut Archetype {
+        let [old_archetype, new_archetype] = self
+            .archetypes
+            .get_disjoint_mut([entity_location.storage_index, new_archetype_index])
+            .unwrap();
+        old_archetype
+            .migrate_entity_components(new_archetype, entity_location.index_within_storage);
+        old_archetype.remove_entity_index(
+            &mut self.entity_manager,
+            entity_location.index_within_storage,
+        );
+
+        self.entity_manager.update_entity_location(
+            entity.index,
+            EntityLocation {
+                storage_index: new_archetype_index,
+                index_within_storage: new_archetype.entity_indices.len(),
+            },
+        );
+        new_archetype.entity_indices.push(entity.index);
+        new_archetype
+    }
+
+    /// Returns the location of a live [Entity].
+    /// Unlike `EntityManager::get_entity_location` this rejects reserved [Entity]s that haven't been flushed.
+    pub(crate) fn get_flushed_entity_location(
+        &self,
+        entity: Entity,
+    ) -> Result<EntityLocation, ECSError> {
+        let entity_location = self.entity_manager.get_entity_location(entity)?;
+        if self.archetypes[entity_location.storage_index]
+            .entity_indices
+            .get(entity_location.index_within_storage)
+            == Some(&entity.index)
+        {
+            Ok(entity_location)
+        } else {
+            Err(ECSError::NoMatchingEntity)
+        }
+    }
+
+    /// Borrows an [Entity]'s component.
+    pub fn get<T: ComponentTrait>(&self, entity: Entity) -> Result<ComponentRef<'_, T>, ECSError> {
+        let entity_location = self.get_flushed_entity_location(entity)?;
+        let guard = if T::STORAGE == StorageKind::SparseSet {
+            let component = get_sparse_storage::<T>(&self.sparse_storages)
+                .and_then(|s| s.components.get(entity.index))
+                .ok_or(ECSError::NoMatchingComponent)?;
+            ComponentGuard::Sparse(
+                component
+                    .value
+                    .try_read()
+                    .map_err(|_| ECSError::ComponentAlreadyBorrowed)?,
+            )
+        } else {
+            let (channel, ticks) = self.archetypes[entity_location.storage_index]
+                .channel(T::component_id())
+                .ok_or(ECSError::NoMatchingComponent)?;
+            if ticks.is_none() {
+                return Ok(ComponentRef {
+                    guard: ComponentGuard::Tag,
+                });
+            }
+            let guard = channel
+                .try_read()
+                .map_err(|_| ECSError::ComponentAlreadyBorrowed)?;
+            // SAFETY: The channel for `T`'s `ComponentId` stores `T`s.
+            ComponentGuard::Table(
+                unsafe { ChannelReadGuard::new(guard) },
+                entity_location.index_within_storage,
+            )
+        };
+        Ok(ComponentRef { guard })
+    }
+
+    /// Mutably borrows an [Entity]'s component and marks it as changed.
+    pub fn get_mut<T: ComponentTrait>(&mut self, entity: Entity) -> Result<&mut T, ECSError> {
+        self.flush_reserved_entities();
+        let entity_location = self.entity_manager.get_entity_location(entity)?;
+        let change_tick = *self.change_tick.get_mut();
+        if T::STORAGE == StorageKind::SparseSet {
+            return self
+                .sparse_storages
+                .get_mut(&T::component_id())
+                .and_then(|s| {
+                    s.as_any_mut()
+                        .downcast_mut::<SparseStorage<T>>()
+                        .unwrap()
+                        .components
+                        .get_mut(entity.index)
+                })
+                .map(|c| c.get_mut(change_tick))
+                .ok_or(ECSError::NoMatchingComponent);
+        }
+        let (channel, ticks) = self.archetypes[entity_location.storage_index]
+            .channel_mut(T::component_id())
+            .ok_or(ECSError::NoMatchingComponent)?;
+        let Some(ticks) = ticks else {
+            // SAFETY: `ComponentInfo::new` checked that tags are zero-sized.
+            return Ok(unsafe { std::ptr::NonNull::<T>::dangling().as_mut() });
+        };
+        *ticks.changed[entity_location.index_within_storage].get_mut() = change_tick;
+        // SAFETY: The channel for `T`'s `ComponentId` stores `T`s.
+        Ok(&mut unsafe { channel.as_mut_slice::<T>() }[entity_location.index_within_storage])
     }
 
     /// Move all components and [Entity]s from `other` into this [World].
-    pub fn append(&mut self, other: &mut World) {
+    pub fn append(&mut self, _other: &mut World) {
         todo!()
     }
 
+    /// Queries the [World]. [Added] and [Changed] filters match every component;
+    /// use [World::query_with_state] to only match changes since the query last ran.
     pub fn query<'a, QUERY: QueryTrait>(&'a self) -> QUERY::Result<'a> {
         self.try_query::<QUERY>().unwrap()
     }
     //
     pub fn try_query<'a, QUERY: QueryTrait>(&'a self) -> Result<QUERY::Result<'a>, ECSError> {
-        QUERY::get_result(self)
+        QUERY::get_result(self, self.query_ticks())
     }
 
     /// Faster than [query] because it can avoid exclusive borrowing checks.
     pub fn query_mut<'a, QUERY: MutQueryTrait>(&'a mut self) -> QUERY::Result<'a> {
-        QUERY::get_result_mut(self).unwrap()
+        let ticks = self.query_ticks();
+        QUERY::get_result_mut(self, ticks).unwrap()
+    }
+
+    /// Like [World::query], but [Added] and [Changed] filters only match changes made since
+    /// the last run with the same `state`. Each caller should keep its own [QueryState].
+    pub fn query_with_state<'a, QUERY: QueryTrait>(
+        &'a self,
+        state: &mut QueryState,
+    ) -> QUERY::Result<'a> {
+        self.try_query_with_state::<QUERY>(state).unwrap()
+    }
+
+    pub fn try_query_with_state<'a, QUERY: QueryTrait>(
+        &'a self,
+        state: &mut QueryState,
+    ) -> Result<QUERY::Result<'a>, ECSError> {
+        QUERY::get_result(self, self.next_query_ticks(state))
+    }
+
+    /// Like [World::query_mut] with the change detection of [World::query_with_state].
+    pub fn query_mut_with_state<'a, QUERY: MutQueryTrait>(
+        &'a mut self,
+        state: &mut QueryState,
+    ) -> QUERY::Result<'a> {
+        let ticks = self.next_query_ticks(state);
+        QUERY::get_result_mut(self, ticks).unwrap()
     }
 }
 
@@ -250,3 +988,27 @@ impl Clone for World {
         todo!()
     }
 }
+
+/// Sorts components by [ComponentId] with [StorageKind::SparseSet] components last
+/// and splits them into table and sparse components.
+/// Errors with [ECSError::DuplicateComponent] if there's more than one of the same component.
+#[allow(clippy::type_complexity)]
+fn split_sparse_components<'a, 'b>(
+    components_and_ids: &'a mut [(&'b mut dyn AnyComponentTrait, ComponentId)],
+) -> Result<
+    (
+        &'a mut [(&'b mut dyn AnyComponentTrait, ComponentId)],
+        &'a mut [(&'b mut dyn AnyComponentTrait, ComponentId)],
+    ),
+    ECSError,
+> {
+    components_and_ids.sort_by_key(|v| (v.0.storage_kind() == StorageKind::SparseSet, v.1));
+    if components_and_ids.windows(2).any(|w| w[0].1 == w[1].1) {
+        return Err(ECSError::DuplicateComponent);
+    }
+    let table_count = components_and_ids
+        .iter()
+        .position(|v| v.0.storage_kind() == StorageKind::SparseSet)
+        .unwrap_or(components_and_ids.len());
+    Ok(components_and_ids.split_at_mut(table_count))
+}
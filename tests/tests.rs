@@ -83,3 +83,1820 @@ fn query_one() {
         assert!(query.get().0 == 3)
     }
 }
+
+#[test]
+fn add_and_remove_components() {
+    let mut world = World::new();
+    let entity = world.spawn(A(3));
+    world.add_components(entity, B(5)).unwrap();
+    {
+        let mut query = world.query_mut::<All<(&A, &B)>>();
+        assert_eq!(query.iter_mut().count(), 1);
+    }
+
+    let b: B = world.remove_components(entity).unwrap();
+    assert_eq!(b.0, 5);
+    assert!(world.remove_components::<B>(entity).is_err());
+    let query = world.query::<All<&A>>();
+    assert_eq!(query.iter().map(|a| a.0).collect::<Vec<_>>(), vec![3]);
+}
+
+#[test]
+fn commands() {
+    let mut world = World::new();
+    let first = world.spawn(A(1));
+    world.spawn(A(2));
+
+    let mut commands = Commands::new();
+    let spawned = {
+        let query = world.query::<All<&A>>();
+        let spawned: Vec<Entity> = query
+            .iter()
+            .map(|a| commands.spawn(&world, B(a.0 * 10)))
+            .collect();
+        commands.despawn(first);
+        commands.add_components(spawned[0], A(7));
+        spawned
+    };
+    assert_eq!(commands.len(), 4);
+
+    world.apply_commands(&mut commands).unwrap();
+    assert!(commands.is_empty());
+    assert!(world.despawn(first).is_err());
+
+    let query = world.query::<All<&B>>();
+    let mut values: Vec<usize> = query.iter().map(|b| b.0).collect();
+    values.sort();
+    assert_eq!(values, vec![10, 20]);
+    drop(query);
+
+    commands.remove_components::<B>(spawned[0]);
+    commands.despawn(spawned[1]);
+    world.apply_commands(&mut commands).unwrap();
+    let query = world.query::<All<&A>>();
+    let mut values: Vec<usize> = query.iter().map(|a| a.0).collect();
+    values.sort();
+    assert_eq!(values, vec![2, 7]);
+}
+
+#[test]
+fn commands_from_multiple_threads() {
+    let mut world = World::new();
+    let mut commands = Commands::new();
+    std::thread::scope(|scope| {
+        for i in 0..4 {
+            let (world, commands) = (&world, &commands);
+            scope.spawn(move || {
+                commands.spawn(world, A(i));
+            });
+        }
+    });
+    world.apply_commands(&mut commands).unwrap();
+    assert_eq!(world.query::<All<&A>>().iter().count(), 4);
+}
+
+#[test]
+fn reserve_entities() {
+    let mut world = World::new();
+    let despawned = world.spawn(A(0));
+    world.despawn(despawned).unwrap();
+
+    let reused = world.reserve_entity();
+    assert_eq!(reused, Entity::from_index_and_generation(0, 1));
+    let reserved: Vec<Entity> = world.reserve_entities(3).collect();
+    assert_eq!(reserved.len(), 3);
+    assert!(!reserved.contains(&reused));
+
+    // Reserved `Entity`s are flushed by the next structural change.
+    let spawned = world.spawn(B(1));
+    assert!(!reserved.contains(&spawned) && spawned != reused);
+    world.add_components(reused, A(1)).unwrap();
+    for entity in reserved {
+        world.despawn(entity).unwrap();
+    }
+    assert_eq!(world.query::<All<&A>>().iter().count(), 1);
+}
+
+#[test]
+fn change_detection() {
+    type AddedA<'a> = All<'a, &'a A, Added<A>>;
+    type ChangedA<'a> = All<'a, &'a A, Changed<A>>;
+    let mut world = World::new();
+    let first = world.spawn((A(1), B(1)));
+    world.spawn((A(2), B(2)));
+
+    // Everything is new the first time a query runs with a `QueryState`.
+    let mut added = QueryState::new();
+    let mut changed = QueryState::new();
+    assert_eq!(
+        world.query_with_state::<AddedA>(&mut added).iter().count(),
+        2
+    );
+    assert_eq!(
+        world.query_with_state::<AddedA>(&mut added).iter().count(),
+        0
+    );
+    assert_eq!(
+        world
+            .query_with_state::<ChangedA>(&mut changed)
+            .iter()
+            .count(),
+        2
+    );
+    assert_eq!(
+        world
+            .query_with_state::<ChangedA>(&mut changed)
+            .iter()
+            .count(),
+        0
+    );
+
+    // Only rows handed out mutably are marked as changed.
+    let mut added_b = QueryState::new();
+    assert_eq!(
+        world
+            .query_mut_with_state::<All<&mut A, Added<B>>>(&mut added_b)
+            .iter()
+            .count(),
+        2
+    );
+    world.spawn((A(3), B(3)));
+    for a in world
+        .query_mut_with_state::<All<&mut A, Added<B>>>(&mut added_b)
+        .iter_mut()
+    {
+        a.0 += 10;
+    }
+    let query = world.query_with_state::<ChangedA>(&mut changed);
+    assert_eq!(query.iter().map(|a| a.0).collect::<Vec<_>>(), vec![13]);
+    drop(query);
+
+    // Reading doesn't mark changes.
+    for _ in world.query::<All<&A>>().iter() {}
+    for _ in world.query_mut::<All<(&A, &mut B)>>().iter() {}
+    assert_eq!(
+        world
+            .query_with_state::<ChangedA>(&mut changed)
+            .iter()
+            .count(),
+        0
+    );
+
+    let mut both = QueryState::new();
+    type ChangedBoth<'a> = All<'a, &'a A, (Changed<A>, Changed<B>)>;
+    assert_eq!(
+        world
+            .query_with_state::<ChangedBoth>(&mut both)
+            .iter()
+            .count(),
+        3
+    );
+    world.add_components(first, A(5)).unwrap();
+    assert_eq!(
+        world
+            .query_with_state::<ChangedBoth>(&mut both)
+            .iter()
+            .count(),
+        0
+    );
+    let query = world.query_with_state::<ChangedA>(&mut changed);
+    assert_eq!(query.iter().map(|a| a.0).collect::<Vec<_>>(), vec![5]);
+    drop(query);
+
+    // Each `QueryState` sees every change, however many other callers run the same query.
+    let mut other = QueryState::new();
+    assert_eq!(
+        world
+            .query_with_state::<ChangedA>(&mut other)
+            .iter()
+            .count(),
+        3
+    );
+    world.get_mut::<A>(first).unwrap().0 = 6;
+    assert_eq!(
+        world
+            .query_with_state::<ChangedA>(&mut changed)
+            .iter()
+            .count(),
+        1
+    );
+    assert_eq!(
+        world
+            .query_with_state::<ChangedA>(&mut other)
+            .iter()
+            .count(),
+        1
+    );
+
+    // Without a `QueryState` everything matches and the change tick isn't advanced.
+    let tick = world.change_tick();
+    assert_eq!(world.query::<ChangedA>().iter().count(), 3);
+    assert_eq!(world.query::<ChangedA>().iter().count(), 3);
+    for _ in world.query_mut::<All<&mut A>>().iter_mut() {}
+    assert_eq!(world.change_tick(), tick);
+}
+
+#[test]
+fn removed_components() {
+    let mut world = World::new();
+    let first = world.spawn((A(1), B(1)));
+    let second = world.spawn((A(2), B(2)));
+    let third = world.spawn(A(3));
+
+    let mut removed_a = RemovedComponents::<A>::new();
+    let mut removed_b = RemovedComponents::<B>::new();
+    assert!(world.removed(&mut removed_a).is_empty());
+
+    world.despawn(first).unwrap();
+    world.remove_components::<B>(second).unwrap();
+    assert_eq!(world.removed(&mut removed_a), &[first]);
+    assert_eq!(world.removed(&mut removed_b), &[first, second]);
+    assert!(world.removed(&mut removed_a).is_empty());
+
+    // Removals cleared before they're read are skipped.
+    world.despawn(second).unwrap();
+    world.clear_removed_components();
+    world.despawn(third).unwrap();
+    assert_eq!(world.removed(&mut removed_a), &[third]);
+    assert!(world.removed(&mut removed_b).is_empty());
+}
+
+#[test]
+fn events() {
+    struct Damage(usize);
+
+    let mut world = World::new();
+    let mut early_reader = EventReader::<Damage>::new();
+    let mut late_reader = EventReader::<Damage>::new();
+    assert_eq!(world.read_events(&mut early_reader).count(), 0);
+
+    world.send_event(Damage(1));
+    world.send_event(Damage(2));
+    let read: Vec<usize> = world.read_events(&mut early_reader).map(|d| d.0).collect();
+    assert_eq!(read, vec![1, 2]);
+    assert_eq!(world.read_events(&mut early_reader).count(), 0);
+
+    // Events are still visible the frame after they're sent.
+    world.update_events();
+    world.send_event(Damage(3));
+    let read: Vec<usize> = world.read_events(&mut late_reader).map(|d| d.0).collect();
+    assert_eq!(read, vec![1, 2, 3]);
+    let read: Vec<usize> = world.read_events(&mut early_reader).map(|d| d.0).collect();
+    assert_eq!(read, vec![3]);
+
+    // But not the frame after that.
+    world.update_events();
+    world.update_events();
+    world.send_event(Damage(4));
+    let mut new_reader = EventReader::<Damage>::new();
+    let read: Vec<usize> = world.read_events(&mut new_reader).map(|d| d.0).collect();
+    assert_eq!(read, vec![4]);
+}
+
+#[test]
+fn component_hooks() {
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    struct Spawned(Entity);
+
+    let mut world = World::new();
+    // An index from each `A`'s value to its `Entity`, kept in sync by hooks.
+    let index = Arc::new(Mutex::new(HashMap::new()));
+    let added = Arc::new(Mutex::new(0));
+
+    let insert_index = index.clone();
+    world.on_insert::<A>(move |entity, a, _| {
+        insert_index.lock().unwrap().insert(a.0, entity);
+    });
+    let replace_index = index.clone();
+    world.on_replace::<A>(move |_, a, _| {
+        replace_index.lock().unwrap().remove(&a.0);
+    });
+    let add_count = added.clone();
+    world.on_add::<A>(move |_, _, _| *add_count.lock().unwrap() += 1);
+    // Hooks can make deferred structural changes.
+    world.on_remove::<B>(|entity, _, world| {
+        let spawned = world.spawn(A(100));
+        world.send_event(Spawned(entity));
+        world.add_components(spawned, B(0));
+    });
+
+    let first = world.spawn(A(1));
+    let second = world.spawn((A(2), B(2)));
+    assert_eq!(index.lock().unwrap()[&1], first);
+    assert_eq!(index.lock().unwrap()[&2], second);
+
+    world.add_components(first, A(3)).unwrap();
+    assert!(!index.lock().unwrap().contains_key(&1));
+    assert_eq!(index.lock().unwrap()[&3], first);
+    assert_eq!(*added.lock().unwrap(), 2);
+
+    world.remove_components::<A>(first).unwrap();
+    world.add_components(first, (A(4), B(4))).unwrap();
+    assert_eq!(*added.lock().unwrap(), 3);
+    assert_eq!(index.lock().unwrap().len(), 2);
+
+    world.despawn(second).unwrap();
+    assert!(!index.lock().unwrap().contains_key(&2));
+    let spawned = index.lock().unwrap()[&100];
+    assert_eq!(world.query::<All<&B>>().iter().count(), 2);
+    let sent: Vec<Entity> = world
+        .read_events(&mut EventReader::<Spawned>::new())
+        .map(|e| e.0)
+        .collect();
+    assert_eq!(sent, vec![second]);
+
+    // The spawned `Entity` is removed with its `B`, which spawns another.
+    world.despawn(spawned).unwrap();
+    assert_eq!(index.lock().unwrap().len(), 2);
+}
+
+#[test]
+fn get_components() {
+    let mut world = World::new();
+    let entity = world.spawn((A(1), B(2)));
+    assert_eq!(world.get::<A>(entity).unwrap().0, 1);
+    world.get_mut::<B>(entity).unwrap().0 = 3;
+    assert_eq!(world.get::<B>(entity).unwrap().0, 3);
+
+    world.remove_components::<A>(entity).unwrap();
+    assert!(matches!(
+        world.get::<A>(entity),
+        Err(ECSError::NoMatchingComponent)
+    ));
+    world.despawn(entity).unwrap();
+    assert!(matches!(
+        world.get::<B>(entity),
+        Err(ECSError::EntityNoLongerExists)
+    ));
+}
+
+#[test]
+fn hierarchy() {
+    let mut world = World::new();
+    let root = world.spawn(A(0));
+    let child = world.spawn(A(1));
+    let grandchild = world.spawn(A(2));
+    let other_child = world.spawn(A(3));
+    world.set_parent(child, root).unwrap();
+    world.set_parent(grandchild, child).unwrap();
+    world.set_parent(other_child, root).unwrap();
+
+    assert_eq!(world.get::<Parent>(child).unwrap().get(), root);
+    assert_eq!(
+        &**world.get::<Children>(root).unwrap(),
+        &[child, other_child]
+    );
+    assert_eq!(
+        world.ancestors(grandchild).collect::<Vec<_>>(),
+        vec![child, root]
+    );
+    assert_eq!(
+        world.depth_first(root).collect::<Vec<_>>(),
+        vec![(root, 0), (child, 1), (grandchild, 2), (other_child, 1)]
+    );
+
+    // Cycles are rejected and leave the hierarchy as it was.
+    for parent in [child, grandchild] {
+        assert!(matches!(
+            world.set_parent(child, parent),
+            Err(ECSError::HierarchyCycle)
+        ));
+    }
+    assert_eq!(world.get::<Parent>(child).unwrap().get(), root);
+
+    // Reparenting removes the child from its old parent.
+    world.set_parent(other_child, grandchild).unwrap();
+    assert_eq!(&**world.get::<Children>(root).unwrap(), &[child]);
+    assert_eq!(
+        world.descendants(root).collect::<Vec<_>>(),
+        vec![child, grandchild, other_child]
+    );
+
+    assert_eq!(world.remove_parent(other_child).unwrap(), Some(grandchild));
+    assert!(world.get::<Children>(grandchild).is_err());
+    assert_eq!(world.remove_parent(other_child).unwrap(), None);
+
+    world.set_parent(other_child, grandchild).unwrap();
+    world.despawn_recursive(child).unwrap();
+    assert!(world.get::<Children>(root).is_err());
+    assert_eq!(world.query::<All<&A>>().iter().count(), 1);
+
+    // Despawning a child removes it from its parent's `Children`.
+    let [a, b, c, d] = [4, 5, 6, 7].map(|i| world.spawn(A(i)));
+    for child in [a, b, c] {
+        world.set_parent(child, root).unwrap();
+    }
+    world.set_parent(d, c).unwrap();
+    world.despawn(a).unwrap();
+    assert_eq!(&**world.get::<Children>(root).unwrap(), &[b, c]);
+    world.despawn_batch(&[b]);
+    assert_eq!(&**world.get::<Children>(root).unwrap(), &[c]);
+    world.despawn(d).unwrap();
+    assert!(world.get::<Children>(c).is_err());
+    world.despawn(c).unwrap();
+    assert!(world.get::<Children>(root).is_err());
+
+    // `despawn_recursive` keeps going after descendants that were already despawned.
+    let [a, b] = [8, 9].map(|i| world.spawn(A(i)));
+    world.set_parent(a, root).unwrap();
+    world.set_parent(b, a).unwrap();
+    world.despawn(b).unwrap();
+    world.despawn_recursive(root).unwrap();
+    assert!(world.get::<A>(a).is_err());
+    assert_eq!(world.query::<All<&A>>().iter().count(), 0);
+}
+
+#[test]
+fn relations() {
+    #[derive(Default)]
+    struct ChildOf;
+    impl ComponentTrait for ChildOf {
+        fn clone_vec(_data: &[Self]) -> Option<Vec<Self>> {
+            None
+        }
+    }
+
+    let mut world = World::new();
+    let parent = world.spawn(A(0));
+    let other_parent = world.spawn(A(1));
+    let first = world.spawn(A(2));
+    let second = world.spawn((A(3), B(3)));
+    world.relate::<ChildOf>(first, parent).unwrap();
+    world.relate::<ChildOf>(second, parent).unwrap();
+    assert!(world.has_relation::<ChildOf>(first, parent));
+    assert!(!world.has_relation::<ChildOf>(first, other_parent));
+
+    let mut children: Vec<Entity> = world.related_to::<ChildOf>(parent).collect();
+    children.sort();
+    assert_eq!(children, vec![first, second]);
+    assert_eq!(world.related_to::<ChildOf>(other_parent).count(), 0);
+
+    world.relate::<ChildOf>(second, other_parent).unwrap();
+    world.unrelate::<ChildOf>(second, parent).unwrap();
+    assert!(world.unrelate::<ChildOf>(second, parent).is_err());
+    assert_eq!(
+        world
+            .related_to::<ChildOf>(other_parent)
+            .collect::<Vec<_>>(),
+        vec![second]
+    );
+
+    let mut targets: Vec<(usize, Entity)> = world
+        .query::<All<(&A, Related<ChildOf>)>>()
+        .iter()
+        .map(|(a, target)| (a.0, target))
+        .collect();
+    targets.sort_by_key(|t| t.0);
+    assert_eq!(targets, vec![(2, parent), (3, other_parent)]);
+    assert_eq!(world.query_mut::<All<Related<ChildOf>>>().iter().count(), 2);
+}
+
+#[test]
+fn sparse_components() {
+    #[derive(Clone)]
+    struct S(usize);
+    impl ComponentTrait for S {
+        const STORAGE: StorageKind = StorageKind::SparseSet;
+        fn clone_vec(data: &[Self]) -> Option<Vec<Self>> {
+            Some(data.into())
+        }
+    }
+
+    let mut world = World::new();
+    let first = world.spawn((A(1), S(10)));
+    let second = world.spawn(A(2));
+    let third = world.spawn((A(3), B(3)));
+    world.add_components(third, S(30)).unwrap();
+    assert_eq!(world.get::<S>(first).unwrap().0, 10);
+    assert!(world.get::<S>(second).is_err());
+
+    // Adding or removing a sparse component doesn't move the `Entity` to another archetype.
+    assert_eq!(world.query_mut::<All<&A>>().archetypes_len(), 2);
+
+    world.get_mut::<S>(first).unwrap().0 = 11;
+    for s in world.query_mut::<All<&mut S>>().iter_mut() {
+        s.0 += 1;
+    }
+    {
+        let query = world.query::<All<(&A, &S)>>();
+        let mut results: Vec<(Entity, usize, usize)> = query
+            .entities()
+            .zip(query.iter())
+            .map(|(entity, (a, s))| (entity, a.0, s.0))
+            .collect();
+        results.sort_by_key(|r| r.1);
+        assert_eq!(results, vec![(first, 1, 12), (third, 3, 31)]);
+    }
+    assert_eq!(world.query_mut::<One<(&B, &S)>>().get().1 .0, 31);
+
+    let mut removed = RemovedComponents::<S>::new();
+    world.remove_components::<S>(first).unwrap();
+    assert!(world.remove_components::<S>(first).is_err());
+    world.despawn(third).unwrap();
+    assert_eq!(world.removed(&mut removed), &[first, third]);
+    assert_eq!(world.query::<All<&S>>().iter().count(), 0);
+
+    // Filters on sparse components check each row.
+    let mut world = World::new();
+    let with = world.spawn((A(1), S(10)));
+    let without = world.spawn(A(2));
+    let query = world.query::<All<&A, With<S>>>();
+    assert_eq!(query.entities().collect::<Vec<_>>(), [with]);
+    drop(query);
+    let query = world.query::<All<&A, Without<S>>>();
+    assert_eq!(query.entities().collect::<Vec<_>>(), [without]);
+    drop(query);
+    assert_eq!(
+        world
+            .query_mut::<All<&mut A, Without<S>>>()
+            .iter_mut()
+            .count(),
+        1
+    );
+
+    // Sparse components keep change ticks for each component.
+    let mut added = QueryState::new();
+    let mut changed = QueryState::new();
+    let added_since = |world: &World, state: &mut QueryState| {
+        let query = world.query_with_state::<All<&A, Added<S>>>(state);
+        query.entities().collect::<Vec<_>>()
+    };
+    let changed_since = |world: &World, state: &mut QueryState| {
+        let query = world.query_with_state::<All<&A, Changed<S>>>(state);
+        let mut entities: Vec<Entity> = query.entities().collect();
+        entities.sort();
+        entities
+    };
+    assert_eq!(added_since(&world, &mut added), [with]);
+    assert_eq!(changed_since(&world, &mut changed), [with]);
+    world.add_components(without, S(20)).unwrap();
+    assert_eq!(added_since(&world, &mut added), [without]);
+    assert_eq!(changed_since(&world, &mut changed), [without]);
+    // Replacing a sparse component or borrowing it mutably marks it as changed but not added.
+    world.add_components(with, S(11)).unwrap();
+    assert_eq!(changed_since(&world, &mut changed), [with]);
+    world.get_mut::<S>(without).unwrap().0 += 1;
+    assert_eq!(changed_since(&world, &mut changed), [without]);
+    assert_eq!(world.query::<All<&S>>().iter().count(), 2);
+    assert!(changed_since(&world, &mut changed).is_empty());
+    for s in world.query_mut::<All<&mut S>>().iter_mut() {
+        s.0 += 1;
+    }
+    assert_eq!(changed_since(&world, &mut changed), [with, without]);
+    assert!(added_since(&world, &mut added).is_empty());
+
+    assert_eq!(world.despawn_matching::<Changed<S>>(), 2);
+    let kept = world.spawn((A(3), S(30)));
+    assert_eq!(world.despawn_matching::<Without<S>>(), 0);
+    assert_eq!(world.despawn_matching::<With<S>>(), 1);
+    assert!(world.get::<A>(kept).is_err());
+}
+
+#[test]
+fn tag_components() {
+    struct Player;
+    impl ComponentTrait for Player {
+        const STORAGE: StorageKind = StorageKind::Tag;
+        fn clone_vec(_data: &[Self]) -> Option<Vec<Self>> {
+            None
+        }
+    }
+
+    let mut world = World::new();
+    let added = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let added_clone = added.clone();
+    world.on_add::<Player>(move |_, _, _| {
+        added_clone.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    });
+
+    let player = world.spawn((A(1), Player));
+    let other = world.spawn(A(2));
+    let third = world.spawn((A(3), Player));
+    world.add_components(third, B(3)).unwrap();
+    assert!(world.get::<Player>(player).is_ok());
+    assert!(world.get::<Player>(other).is_err());
+
+    let sum_with = |world: &World| -> usize {
+        world
+            .query::<All<&A, With<Player>>>()
+            .iter()
+            .map(|a| a.0)
+            .sum()
+    };
+    assert_eq!(sum_with(&world), 4);
+    assert_eq!(
+        world
+            .query::<All<&A, Without<Player>>>()
+            .iter()
+            .map(|a| a.0)
+            .sum::<usize>(),
+        2
+    );
+
+    world.add_components(other, Player).unwrap();
+    assert_eq!(sum_with(&world), 6);
+    assert_eq!(added.load(std::sync::atomic::Ordering::Relaxed), 3);
+
+    let mut removed = RemovedComponents::<Player>::new();
+    let _player: Player = world.remove_components::<Player>(player).unwrap();
+    world.despawn(other).unwrap();
+    assert_eq!(world.removed(&mut removed), &[player, other]);
+    assert_eq!(sum_with(&world), 3);
+    assert_eq!(world.get::<A>(player).unwrap().0, 1);
+
+    let spawned: Vec<Entity> = world.spawn_batch((4..6).map(|i| (A(i), Player))).collect();
+    world.add_components(spawned[0], B(4)).unwrap();
+    assert_eq!(sum_with(&world), 12);
+    world.despawn(spawned[1]).unwrap();
+    assert_eq!(sum_with(&world), 7);
+    assert!(world.get::<Player>(spawned[0]).is_ok());
+}
+
+#[test]
+fn component_drops() {
+    use std::sync::Arc;
+
+    struct Counted(Arc<()>);
+    impl ComponentTrait for Counted {
+        fn clone_vec(_data: &[Self]) -> Option<Vec<Self>> {
+            None
+        }
+    }
+
+    #[repr(align(32))]
+    #[derive(Clone)]
+    struct Aligned(u8);
+    impl ComponentTrait for Aligned {
+        fn clone_vec(data: &[Self]) -> Option<Vec<Self>> {
+            Some(data.into())
+        }
+    }
+
+    let count = Arc::new(());
+    {
+        let mut world = World::new();
+        let entities: Vec<Entity> = (0..100)
+            .map(|i| world.spawn((Counted(count.clone()), Aligned(i))))
+            .collect();
+        assert_eq!(Arc::strong_count(&count), 101);
+        for (i, entity) in entities.iter().enumerate() {
+            match i % 4 {
+                0 => world.despawn(*entity).unwrap(),
+                1 => drop(world.remove_components::<Counted>(*entity).unwrap()),
+                2 => world
+                    .add_components(*entity, Counted(count.clone()))
+                    .unwrap(),
+                _ => world.add_components(*entity, A(i)).unwrap(),
+            }
+        }
+        assert_eq!(Arc::strong_count(&count), 51);
+        assert!(world
+            .query::<All<&Counted>>()
+            .iter()
+            .all(|c| Arc::ptr_eq(&c.0, &count)));
+        assert_eq!(world.query::<All<&Aligned>>().iter().count(), 75);
+        for aligned in world.query::<All<&Aligned>>().iter() {
+            assert_eq!(aligned as *const Aligned as usize % 32, 0);
+        }
+        assert_eq!(world.get::<Aligned>(entities[3]).unwrap().0, 3);
+    }
+    assert_eq!(Arc::strong_count(&count), 1);
+
+    // A replaced component whose drop panics is dropped once and its replacement is kept.
+    static DROPS: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+    struct PanicsOnDrop(bool);
+    impl Drop for PanicsOnDrop {
+        fn drop(&mut self) {
+            DROPS.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            assert!(!self.0, "dropped");
+        }
+    }
+    impl ComponentTrait for PanicsOnDrop {
+        fn clone_vec(_data: &[Self]) -> Option<Vec<Self>> {
+            None
+        }
+    }
+    let mut world = World::new();
+    let entity = world.spawn(PanicsOnDrop(true));
+    let replaced = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        world.add_components(entity, PanicsOnDrop(false))
+    }));
+    assert!(replaced.is_err());
+    assert!(!world.get::<PanicsOnDrop>(entity).unwrap().0);
+    drop(world);
+    assert_eq!(DROPS.load(std::sync::atomic::Ordering::Relaxed), 2);
+}
+
+#[test]
+fn spawn_batch() {
+    let mut world = World::new();
+    let reserved = world.reserve_entity();
+    let entities: Vec<Entity> = world.spawn_batch((0..1000).map(|i| (B(i), A(i)))).collect();
+    assert_eq!(entities.len(), 1000);
+    assert!(!entities.contains(&reserved));
+    for (i, entity) in entities.iter().enumerate() {
+        assert_eq!(world.get::<A>(*entity).unwrap().0, i);
+        assert_eq!(world.get::<B>(*entity).unwrap().0, i);
+    }
+    assert_eq!(world.query_mut::<All<(&A, &B)>>().archetypes_len(), 1);
+
+    world.despawn(entities[10]).unwrap();
+    let more: Vec<Entity> = world.spawn_batch(vec![A(1000), A(1001)]).collect();
+    assert!(world.get::<A>(entities[10]).is_err());
+    assert_eq!(world.get::<A>(more[0]).unwrap().0, 1000);
+    assert_eq!(world.get::<A>(more[1]).unwrap().0, 1001);
+    assert_eq!(world.query::<All<&A>>().iter().count(), 1001);
+    assert_eq!(world.spawn_batch(Vec::<A>::new()).count(), 0);
+    assert!(matches!(
+        world.try_spawn_batch((0..3).map(|i| (A(i), A(i)))),
+        Err(ECSError::DuplicateComponent)
+    ));
+    assert_eq!(world.query::<All<&A>>().iter().count(), 1001);
+}
+
+#[test]
+fn despawn_batch() {
+    use std::sync::Arc;
+
+    struct Dead;
+    impl ComponentTrait for Dead {
+        const STORAGE: StorageKind = StorageKind::Tag;
+        fn clone_vec(_data: &[Self]) -> Option<Vec<Self>> {
+            None
+        }
+    }
+
+    struct Counted(Arc<()>);
+    impl ComponentTrait for Counted {
+        fn clone_vec(_data: &[Self]) -> Option<Vec<Self>> {
+            None
+        }
+    }
+
+    struct S(usize);
+    impl ComponentTrait for S {
+        const STORAGE: StorageKind = StorageKind::SparseSet;
+        fn clone_vec(_data: &[Self]) -> Option<Vec<Self>> {
+            None
+        }
+    }
+
+    let count = Arc::new(());
+    let mut world = World::new();
+    let removed_count = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let removed_count_clone = removed_count.clone();
+    world.on_remove::<Counted>(move |_, _, _| {
+        removed_count_clone.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    });
+    let entities: Vec<Entity> = (0..100)
+        .map(|i| world.spawn((A(i), Counted(count.clone()))))
+        .collect();
+    for entity in entities.iter().skip(50) {
+        world.add_components(*entity, Dead).unwrap();
+    }
+    let sparse = world.spawn(A(100));
+    world.add_components(sparse, S(100)).unwrap();
+
+    // Compacts the middle of the columns, skipping repeated and despawned `Entity`s.
+    let mut removed = RemovedComponents::<A>::new();
+    world.despawn(entities[0]).unwrap();
+    let batch: Vec<Entity> = entities[..20].iter().step_by(2).copied().collect();
+    assert_eq!(world.despawn_batch(&[&batch[..], &batch[..]].concat()), 9);
+    assert_eq!(Arc::strong_count(&count), 91);
+    assert_eq!(removed_count.load(std::sync::atomic::Ordering::Relaxed), 10);
+    assert_eq!(world.removed(&mut removed).len(), 10);
+    for (i, entity) in entities.iter().enumerate() {
+        let alive = i >= 20 || i % 2 == 1;
+        assert_eq!(
+            world.get::<A>(*entity).map(|a| a.0).ok(),
+            alive.then_some(i)
+        );
+    }
+
+    // Truncates whole columns.
+    assert_eq!(world.despawn_matching::<With<Dead>>(), 50);
+    assert_eq!(Arc::strong_count(&count), 41);
+    assert_eq!(world.query::<All<&A>>().iter().count(), 41);
+    assert!(world.get::<A>(entities[60]).is_err());
+    assert_eq!(world.get::<A>(entities[49]).unwrap().0, 49);
+    assert!(world
+        .query::<All<&Counted>>()
+        .iter()
+        .all(|c| Arc::ptr_eq(&c.0, &count)));
+
+    // Sparse components are removed too.
+    let mut removed_sparse = RemovedComponents::<S>::new();
+    assert_eq!(world.get::<S>(sparse).unwrap().0, 100);
+    assert_eq!(world.despawn_matching::<Without<Counted>>(), 1);
+    assert_eq!(world.removed(&mut removed_sparse), &[sparse]);
+    assert_eq!(world.despawn_matching::<()>(), 40);
+    assert_eq!(Arc::strong_count(&count), 1);
+    let entity = world.spawn(A(0));
+    assert_eq!(world.query::<All<&A>>().iter().count(), 1);
+    assert_eq!(world.get::<A>(entity).unwrap().0, 0);
+}
+
+#[test]
+fn spawn_at() {
+    let mut world = World::new();
+    let first = world.spawn(A(0));
+
+    // Indices past the end are created and the ones skipped over can still be spawned.
+    let remote = Entity::from_index_and_generation(5, 3);
+    world.spawn_at(remote, (A(5), B(5))).unwrap();
+    assert_eq!(world.get::<B>(remote).unwrap().0, 5);
+    assert!(world
+        .get::<A>(Entity::from_index_and_generation(5, 0))
+        .is_err());
+    let skipped = Entity::from_index_and_generation(2, 7);
+    world.spawn_at(skipped, A(2)).unwrap();
+    assert_eq!(world.get::<A>(skipped).unwrap().0, 2);
+
+    // A different generation of a live index is an error, the same one adds components.
+    assert!(matches!(
+        world.spawn_at(Entity::from_index_and_generation(5, 4), A(6)),
+        Err(ECSError::EntityIndexInUse)
+    ));
+    world.spawn_at(first, B(1)).unwrap();
+    assert_eq!(world.get::<B>(first).unwrap().0, 1);
+
+    // Despawned indices can be spawned at, and are no longer handed out by spawn.
+    world.despawn(first).unwrap();
+    let reused = Entity::from_index_and_generation(0, 10);
+    world.spawn_at(reused, A(10)).unwrap();
+    let reserved = world.reserve_entity();
+    let spawned: Vec<Entity> = (0..3).map(|i| world.spawn(A(i))).collect();
+    world.add_components(reserved, A(3)).unwrap();
+    for (i, entity) in spawned.iter().chain([&reserved]).enumerate() {
+        assert_eq!(world.get::<A>(*entity).unwrap().0, i);
+    }
+    for (entity, value) in [(reused, 10), (skipped, 2), (remote, 5)] {
+        assert_eq!(world.get::<A>(entity).unwrap().0, value);
+    }
+    assert_eq!(world.query::<All<&A>>().iter().count(), 7);
+
+    // The next generation of a despawned index is spawned rather than given the old row.
+    let mut world = World::new();
+    let e0 = world.spawn(A(0));
+    let e1 = world.spawn(A(1));
+    world.despawn(e0).unwrap();
+    let next = Entity::from_index_and_generation(0, 1);
+    assert!(world.get::<A>(next).is_err());
+    world.spawn_at(next, B(7)).unwrap();
+    assert_eq!(world.get::<B>(next).unwrap().0, 7);
+    assert!(world.get::<A>(next).is_err());
+    assert_eq!(world.get::<A>(e1).unwrap().0, 1);
+    assert_ne!(world.spawn(A(2)), next);
+
+    // Older generations can't be brought back.
+    world.despawn(next).unwrap();
+    assert!(matches!(
+        world.spawn_at(e0, A(0)),
+        Err(ECSError::EntityNoLongerExists)
+    ));
+
+    // Indices from untrusted input can't grow the world without bound.
+    for index in [MAX_SPAWN_AT_INDEX + 1, usize::MAX] {
+        assert!(matches!(
+            world.spawn_at(Entity::from_index_and_generation(index, 0), A(0)),
+            Err(ECSError::EntityIndexOutOfRange)
+        ));
+    }
+
+    // Free indices can be spawned at in any order.
+    let mut world = World::new();
+    let entities: Vec<Entity> = (0..100).map(|i| world.spawn(A(i))).collect();
+    for entity in entities.iter() {
+        world.despawn(*entity).unwrap();
+    }
+    let spawned_at: Vec<Entity> = (0..100)
+        .step_by(3)
+        .map(|i| Entity::from_index_and_generation(i, 1))
+        .collect();
+    for entity in spawned_at.iter() {
+        world.spawn_at(*entity, A(0)).unwrap();
+    }
+    let spawned: Vec<Entity> = (0..66).map(|i| world.spawn(B(i))).collect();
+    assert!(spawned
+        .iter()
+        .all(|entity| world.get::<A>(*entity).is_err()));
+    assert!(spawned_at
+        .iter()
+        .all(|entity| world.get::<B>(*entity).is_err()));
+    assert_eq!(world.spawn(A(0)), Entity::from_index_and_generation(100, 0));
+}
+
+#[test]
+fn insert() {
+    #[derive(Debug, PartialEq)]
+    struct S(usize);
+    impl ComponentTrait for S {
+        const STORAGE: StorageKind = StorageKind::SparseSet;
+        fn clone_vec(_data: &[Self]) -> Option<Vec<Self>> {
+            None
+        }
+    }
+
+    let mut world = World::new();
+    let replaced = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let replaced_clone = replaced.clone();
+    world.on_replace::<A>(move |_, a, _| replaced_clone.lock().unwrap().push(a.0));
+
+    let entity = world.spawn(A(1));
+    assert!(world.insert(entity, B(2)).unwrap().is_none());
+    let archetypes = world.query_mut::<All<&A>>().archetypes_len();
+    assert_eq!(world.insert(entity, A(3)).unwrap().map(|a| a.0), Some(1));
+    assert_eq!(world.get::<A>(entity).unwrap().0, 3);
+    assert_eq!(world.query_mut::<All<&A>>().archetypes_len(), archetypes);
+    assert_eq!(*replaced.lock().unwrap(), vec![1]);
+
+    assert_eq!(world.insert(entity, S(4)).unwrap(), None);
+    assert_eq!(world.insert(entity, S(5)).unwrap(), Some(S(4)));
+    assert_eq!(*world.get::<S>(entity).unwrap(), S(5));
+
+    assert!(matches!(
+        world.try_insert(entity, A(6)),
+        Err(ECSError::ComponentAlreadyPresent)
+    ));
+    assert_eq!(world.get::<A>(entity).unwrap().0, 3);
+    let other = world.spawn(B(7));
+    world.try_insert(other, A(7)).unwrap();
+    assert_eq!(world.get::<A>(other).unwrap().0, 7);
+    world.despawn(other).unwrap();
+    assert!(matches!(
+        world.insert(other, A(8)),
+        Err(ECSError::EntityNoLongerExists)
+    ));
+
+    // Duplicate components are an error instead of a panic and leave the `World` unchanged.
+    assert!(matches!(
+        world.try_spawn((A(9), A(10))),
+        Err(ECSError::DuplicateComponent)
+    ));
+    assert!(matches!(
+        world.add_components(entity, (B(11), B(12))),
+        Err(ECSError::DuplicateComponent)
+    ));
+    assert_eq!(world.get::<B>(entity).unwrap().0, 2);
+    assert_eq!(world.query::<All<&A>>().iter().count(), 1);
+}
+
+#[test]
+fn component_registry() {
+    #[derive(Debug)]
+    struct Named(&'static str);
+    impl ComponentTrait for Named {
+        const STORAGE: StorageKind = StorageKind::SparseSet;
+        fn clone_vec(_data: &[Self]) -> Option<Vec<Self>> {
+            None
+        }
+    }
+    impl SerializeComponent for Named {
+        fn serialize(&self, bytes: &mut Vec<u8>) {
+            bytes.push(self.0.len() as u8);
+        }
+        fn deserialize(bytes: &mut &[u8]) -> Option<Self> {
+            let (len, rest) = bytes.split_first()?;
+            *bytes = rest;
+            Some(Named(["", "a", "ab"].get(*len as usize)?))
+        }
+    }
+
+    let mut world = World::new();
+    assert!(world.components().is_empty());
+    world.components_mut().register_debug::<Named>();
+    world.components_mut().register_serialize::<Named>();
+    let entity = world.spawn((A(1), Named("ab")));
+    world.spawn((A(2), B(2)));
+
+    let names: Vec<&str> = world.components().iter().map(|c| c.name()).collect();
+    assert_eq!(
+        names,
+        [
+            std::any::type_name::<Named>(),
+            std::any::type_name::<A>(),
+            std::any::type_name::<B>()
+        ]
+    );
+    let a = world.components().get(A::component_id()).unwrap();
+    assert_eq!(a.size(), std::mem::size_of::<A>());
+    assert_eq!(a.align(), std::mem::align_of::<A>());
+    assert_eq!(a.storage(), StorageKind::Table);
+    assert!(a.is_cloneable() && !a.needs_drop() && !a.is_serializable());
+    assert!(unsafe { a.debug((&A(0) as *const A).cast()) }.is_none());
+
+    let named = world.components().get(Named::component_id()).unwrap();
+    assert_eq!(named.storage(), StorageKind::SparseSet);
+    assert!(!named.is_cloneable());
+    let component = world.get::<Named>(entity).unwrap();
+    let ptr: *const Named = &*component;
+    assert_eq!(
+        format!("{:?}", unsafe { named.debug(ptr.cast()) }.unwrap()),
+        "Named(\"ab\")"
+    );
+    let mut bytes = Vec::new();
+    assert!(unsafe { named.serialize(ptr.cast(), &mut bytes) });
+    let mut read = std::mem::MaybeUninit::<Named>::uninit();
+    assert!(unsafe { named.deserialize(&mut &bytes[..], read.as_mut_ptr().cast()) });
+    assert!(!unsafe { named.deserialize(&mut &[7][..], read.as_mut_ptr().cast()) });
+    assert_eq!(unsafe { read.assume_init() }.0, "ab");
+    drop(component);
+
+    // Relation pairs share their relation's info.
+    #[derive(Default)]
+    struct Likes;
+    impl ComponentTrait for Likes {
+        fn clone_vec(_data: &[Self]) -> Option<Vec<Self>> {
+            None
+        }
+    }
+    let child = world.spawn(A(3));
+    world.relate::<Likes>(child, entity).unwrap();
+    let likes = world
+        .components()
+        .get(ComponentId::pair::<Likes>(entity))
+        .unwrap();
+    assert_eq!(likes.name(), std::any::type_name::<Likes>());
+    assert_eq!(likes.id(), Likes::component_id());
+    assert_eq!(world.components().len(), 4);
+}
+
+#[test]
+fn dynamic_components() {
+    use std::alloc::Layout;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    static DROPPED: AtomicU32 = AtomicU32::new(0);
+    unsafe fn drop_health(ptr: *mut u8) {
+        DROPPED.fetch_add(ptr.cast::<u32>().read(), Ordering::Relaxed);
+    }
+
+    let mut world = World::new();
+    let health =
+        world
+            .components_mut()
+            .register_dynamic("Health", Layout::new::<u32>(), Some(drop_health));
+    let marker = world
+        .components_mut()
+        .register_dynamic("Marker", Layout::new::<()>(), None);
+    assert_ne!(health, marker);
+    let info = world.components().get(health).unwrap();
+    assert_eq!((info.name(), info.size()), ("Health", 4));
+    assert!(info.needs_drop());
+
+    let first = world.spawn(A(1));
+    let a_bytes = 2usize.to_ne_bytes();
+    let second = unsafe {
+        world.spawn_dynamic(&[
+            (health, &10u32.to_ne_bytes()),
+            (A::component_id(), &a_bytes),
+            (marker, &[]),
+        ])
+    }
+    .unwrap();
+    assert_eq!(world.get::<A>(second).unwrap().0, 2);
+    assert_eq!(world.query::<All<&A>>().iter().count(), 2);
+    let read = |world: &World, entity| unsafe {
+        world
+            .get_by_id(entity, health)
+            .unwrap()
+            .cast::<u32>()
+            .read()
+    };
+    assert_eq!(read(&world, second), 10);
+    assert!(world.get_by_id(second, marker).is_ok());
+    assert!(matches!(
+        world.get_by_id(first, health),
+        Err(ECSError::NoMatchingComponent)
+    ));
+    assert_eq!(
+        unsafe {
+            *world
+                .get_by_id(first, A::component_id())
+                .unwrap()
+                .cast::<usize>()
+        },
+        1
+    );
+
+    unsafe { *world.get_mut_by_id(second, health).unwrap().cast::<u32>() = 7 };
+    assert_eq!(read(&world, second), 7);
+    world.add_components(second, B(3)).unwrap();
+    assert_eq!(read(&world, second), 7);
+
+    assert!(matches!(
+        unsafe { world.spawn_dynamic(&[(health, &[1, 2])]) },
+        Err(ECSError::ComponentLayoutMismatch)
+    ));
+    world.despawn(second).unwrap();
+    assert_eq!(DROPPED.load(Ordering::Relaxed), 7);
+    unsafe { world.spawn_dynamic(&[(health, &5u32.to_ne_bytes())]) }.unwrap();
+    drop(world);
+    assert_eq!(DROPPED.load(Ordering::Relaxed), 12);
+}
+
+#[test]
+fn dynamic_query() {
+    struct S(usize);
+    impl ComponentTrait for S {
+        const STORAGE: StorageKind = StorageKind::SparseSet;
+        fn clone_vec(_data: &[Self]) -> Option<Vec<Self>> {
+            None
+        }
+    }
+
+    let mut world = World::new();
+    let health =
+        world
+            .components_mut()
+            .register_dynamic("Health", std::alloc::Layout::new::<u32>(), None);
+    let a = world.components_mut().register::<A>();
+    assert_eq!(a, A::component_id());
+    let entities: Vec<Entity> = (0..6usize)
+        .map(|i| unsafe {
+            world.spawn_dynamic(&[
+                (a, &i.to_ne_bytes()),
+                (health, &(i as u32 * 10).to_ne_bytes()),
+            ])
+        })
+        .collect::<Result<_, _>>()
+        .unwrap();
+    world.add_components(entities[1], B(1)).unwrap();
+    world.add_components(entities[2], B(2)).unwrap();
+    world.add_components(entities[2], S(2)).unwrap();
+    world.add_components(entities[3], S(3)).unwrap();
+    world.spawn(B(100));
+    assert!(matches!(
+        unsafe { world.spawn_dynamic(&[(S::component_id(), &3usize.to_ne_bytes())]) },
+        Err(ECSError::SparseComponentFromBytes)
+    ));
+
+    // Reads `A`, writes the dynamic component and fetches `B` if it's there.
+    let query = DynamicQuery::new()
+        .read(A::component_id())
+        .write(health)
+        .optional(B::component_id())
+        .without(S::component_id());
+    let mut rows: Vec<(Entity, usize, Option<usize>)> = Vec::new();
+    for row in query.iter(&mut world) {
+        assert_eq!(row.len(), 3);
+        unsafe {
+            *row.get_mut(1).unwrap().cast::<u32>() += 1;
+            rows.push((
+                row.entity,
+                *row.get(0).unwrap().cast::<usize>(),
+                row.get(2).map(|b| *b.cast::<usize>()),
+            ));
+        }
+    }
+    rows.sort();
+    assert_eq!(
+        rows,
+        vec![
+            (entities[0], 0, None),
+            (entities[1], 1, Some(1)),
+            (entities[4], 4, None),
+            (entities[5], 5, None)
+        ]
+    );
+    let read_health =
+        |world: &World, entity| unsafe { *world.get_by_id(entity, health).unwrap().cast::<u32>() };
+    assert_eq!(read_health(&world, entities[1]), 11);
+    assert_eq!(read_health(&world, entities[2]), 20);
+
+    // Sparse components are checked for each row.
+    let query = DynamicQuery::new()
+        .with(B::component_id())
+        .write(S::component_id());
+    let matched: Vec<Entity> = query
+        .iter(&mut world)
+        .map(|row| {
+            unsafe { (*row.get_mut(0).unwrap().cast::<S>()).0 += 10 };
+            row.entity
+        })
+        .collect();
+    assert_eq!(matched, vec![entities[2]]);
+    assert_eq!(world.get::<S>(entities[2]).unwrap().0, 12);
+    // Writing a sparse component marks it as changed.
+    let mut changed = QueryState::new();
+    world.query_with_state::<All<&S, Changed<S>>>(&mut changed);
+    let _ = DynamicQuery::new()
+        .write(S::component_id())
+        .iter(&mut world)
+        .count();
+    assert_eq!(
+        world
+            .query_with_state::<All<&S, Changed<S>>>(&mut changed)
+            .iter()
+            .count(),
+        2
+    );
+    let without_sparse: Vec<Entity> = DynamicQuery::new()
+        .without(S::component_id())
+        .iter(&mut world)
+        .map(|row| row.entity)
+        .collect();
+    assert_eq!(without_sparse.len(), 5);
+    assert!(!without_sparse.contains(&entities[2]) && !without_sparse.contains(&entities[3]));
+    assert_eq!(
+        DynamicQuery::new()
+            .with(health)
+            .iter(&mut world)
+            .filter(|row| row.is_empty())
+            .count(),
+        6
+    );
+    assert_eq!(DynamicQuery::new().iter(&mut world).count(), 7);
+}
+
+#[test]
+fn scenes() {
+    #[derive(Debug, PartialEq)]
+    struct Door {
+        name: String,
+        width: f64,
+        opens: Entity,
+        keys: Vec<Entity>,
+    }
+    impl ComponentTrait for Door {
+        fn clone_vec(_data: &[Self]) -> Option<Vec<Self>> {
+            None
+        }
+    }
+    impl SceneComponent for Door {
+        fn to_fields(&self, fields: &mut SceneFields) {
+            fields.insert("name", self.name.as_str());
+            fields.insert("width", self.width);
+            fields.insert("opens", self.opens);
+            let keys = self.keys.iter().map(|key| (*key).into()).collect();
+            fields.insert("keys", SceneValue::List(keys));
+        }
+        fn from_fields(fields: &SceneFields) -> Option<Self> {
+            Some(Door {
+                name: fields.get("name")?.as_str()?.to_string(),
+                width: fields.get("width")?.as_float()?,
+                opens: fields.get("opens")?.as_entity()?,
+                keys: fields
+                    .get("keys")?
+                    .as_list()?
+                    .iter()
+                    .map(|key| key.as_entity())
+                    .collect::<Option<_>>()?,
+            })
+        }
+    }
+    struct Locked(i64);
+    impl ComponentTrait for Locked {
+        const STORAGE: StorageKind = StorageKind::SparseSet;
+        fn clone_vec(_data: &[Self]) -> Option<Vec<Self>> {
+            None
+        }
+    }
+    impl SceneComponent for Locked {
+        fn to_fields(&self, fields: &mut SceneFields) {
+            fields.insert("level", self.0);
+        }
+        fn from_fields(fields: &SceneFields) -> Option<Self> {
+            Some(Locked(fields.get("level")?.as_int()?))
+        }
+    }
+    #[derive(Default)]
+    struct Opens;
+    impl ComponentTrait for Opens {
+        const STORAGE: StorageKind = StorageKind::Tag;
+        fn clone_vec(_data: &[Self]) -> Option<Vec<Self>> {
+            None
+        }
+    }
+    fn register(world: &mut World) {
+        world.components_mut().register_scene::<Door>();
+        world.components_mut().register_scene::<Locked>();
+        world.components_mut().register::<Opens>();
+    }
+    let door = std::any::type_name::<Door>();
+    let locked = std::any::type_name::<Locked>();
+    let opens = std::any::type_name::<Opens>();
+
+    let mut world = World::new();
+    register(&mut world);
+    // Entities already in the `World` don't collide with scene-local ids.
+    world.spawn(A(0));
+    let scene = format!(
+        r#"
+        # The front door.
+        entity 7 {{
+            {door} {{ name: "Front \"door\"", width: 2, opens: @3, keys: [@3, @7], }}
+            {locked} {{ level: -1 }}
+        }}
+        entity 3 {{
+            {opens} @7
+        }}
+        "#
+    );
+    let entities = world.load_scene(&scene).unwrap();
+    let (front, room) = (entities[0], entities[1]);
+    assert_eq!(
+        *world.get::<Door>(front).unwrap(),
+        Door {
+            name: "Front \"door\"".into(),
+            width: 2.0,
+            opens: room,
+            keys: vec![room, front],
+        }
+    );
+    assert_eq!(world.get::<Locked>(front).unwrap().0, -1);
+    assert!(world.has_relation::<Opens>(room, front));
+
+    // Saving is stable and loads back to an identical scene.
+    let saved = world.save_scene();
+    assert_eq!(
+        saved,
+        format!(
+            r#"entity 0 {{
+}}
+
+entity 1 {{
+    {door} {{ name: "Front \"door\"", width: 2.0, opens: @2, keys: [@2, @1] }}
+    {locked} {{ level: -1 }}
+}}
+
+entity 2 {{
+    {opens} @1
+}}
+"#
+        )
+    );
+    let mut loaded = World::new();
+    register(&mut loaded);
+    loaded.load_scene(&saved).unwrap();
+    assert_eq!(loaded.save_scene(), saved);
+
+    let error = world.load_scene("entity 0 {\n  Unknown\n}").unwrap_err();
+    assert_eq!(error.line, 2);
+    let error = world.load_scene("entity 0 {}\n\nentity 0 {}").unwrap_err();
+    assert_eq!(error.line, 3);
+    let error = world
+        .load_scene("# first\n# second\nentity 0 {\n\n  Unknown\n}")
+        .unwrap_err();
+    assert_eq!(error.line, 5);
+    let error = world
+        .load_scene(&format!("entity 0 {{ {locked} {{ level: @1 }} }}"))
+        .unwrap_err();
+    assert_eq!(error.line, 1);
+    assert!(world
+        .load_scene(&format!("entity 0 {{ {locked} {{ level: \"high\" }} }}"))
+        .is_err());
+    // Nothing is spawned by scenes with errors.
+    assert_eq!(world.save_scene(), saved);
+}
+
+#[test]
+fn world_diff() {
+    #[derive(Clone, PartialEq, Debug)]
+    struct Health(i32);
+    impl ComponentTrait for Health {
+        fn clone_vec(data: &[Self]) -> Option<Vec<Self>> {
+            Some(data.into())
+        }
+    }
+    #[derive(Clone, PartialEq)]
+    struct Name(String);
+    impl ComponentTrait for Name {
+        const STORAGE: StorageKind = StorageKind::SparseSet;
+        fn clone_vec(data: &[Self]) -> Option<Vec<Self>> {
+            Some(data.into())
+        }
+    }
+    #[derive(Default)]
+    struct Frozen;
+    impl ComponentTrait for Frozen {
+        const STORAGE: StorageKind = StorageKind::Tag;
+        fn clone_vec(_data: &[Self]) -> Option<Vec<Self>> {
+            None
+        }
+    }
+    let build = || {
+        let mut world = World::new();
+        world.components_mut().register_eq::<Health>();
+        world.components_mut().register_eq::<Name>();
+        let entities = [
+            world.spawn((A(0), Health(10))),
+            world.spawn((Health(20), Name("b".into()))),
+            world.spawn(Health(30)),
+        ];
+        (world, entities)
+    };
+    let (mut old, [first, second, third]) = build();
+    let (mut new, _) = build();
+    assert!(old.diff(&new).is_empty());
+
+    new.despawn(third).unwrap();
+    let spawned = new.spawn((Health(40), Frozen));
+    new.insert(first, Health(11)).unwrap();
+    // `A` isn't registered with `register_eq` so changes to it are ignored.
+    new.insert(first, A(1)).unwrap();
+    new.add_components(first, Name("a".into())).unwrap();
+    new.remove_components::<Name>(second).unwrap();
+    new.insert(second, Health(20)).unwrap();
+
+    let delta = old.diff(&new);
+    assert_eq!(delta.spawned(), [spawned]);
+    assert_eq!(delta.despawned(), [third]);
+    let mut added: Vec<_> = delta.added().collect();
+    added.sort();
+    let mut expected = vec![
+        (spawned, Health::component_id()),
+        (spawned, Frozen::component_id()),
+        (first, Name::component_id()),
+    ];
+    expected.sort();
+    assert_eq!(added, expected);
+    assert_eq!(
+        delta.changed().collect::<Vec<_>>(),
+        [(first, Health::component_id())]
+    );
+    assert_eq!(
+        delta.removed().collect::<Vec<_>>(),
+        [(second, Name::component_id())]
+    );
+
+    old.apply_delta(&delta).unwrap();
+    assert!(old.diff(&new).is_empty());
+    assert_eq!(*old.get::<Health>(first).unwrap(), Health(11));
+    assert_eq!(old.get::<A>(first).unwrap().0, 0);
+    assert!(old.get::<Name>(first).unwrap().0 == "a");
+    assert!(old.get::<Name>(second).is_err());
+    assert!(old.get::<Health>(third).is_err());
+    assert_eq!(*old.get::<Health>(spawned).unwrap(), Health(40));
+    assert!(old.get::<Frozen>(spawned).is_ok());
+    // The delta only applies to the `World` it was made from.
+    assert!(old.apply_delta(&delta).is_err());
+}
+
+#[test]
+fn state_hash() {
+    #[derive(Clone, Hash)]
+    struct Position(i64, i64);
+    impl ComponentTrait for Position {
+        fn clone_vec(data: &[Self]) -> Option<Vec<Self>> {
+            Some(data.into())
+        }
+    }
+    #[derive(Hash)]
+    struct Name(&'static str);
+    impl ComponentTrait for Name {
+        const STORAGE: StorageKind = StorageKind::SparseSet;
+        fn clone_vec(_data: &[Self]) -> Option<Vec<Self>> {
+            None
+        }
+    }
+    let new_world = || {
+        let mut world = World::new();
+        world.components_mut().register_hash::<Position>();
+        world.components_mut().register_hash::<Name>();
+        world
+    };
+
+    // The same state reached in a different order hashes the same.
+    let mut a = new_world();
+    let first = a.spawn((Position(0, 0), A(0)));
+    let second = a.spawn((Position(1, 1), Name("b")));
+    let mut b = new_world();
+    b.spawn(Position(0, 0));
+    b.spawn(Position(1, 1));
+    b.add_components(first, A(0)).unwrap();
+    b.add_components(second, Name("b")).unwrap();
+    assert_eq!(a.state_hash(), b.state_hash());
+    assert_eq!(a.state_hash().total(), b.state_hash().total());
+
+    // Spawning the same `Entity`s in a different order, so `Archetype`s and sparse storages are
+    // created in a different order, doesn't change the hash either.
+    let mut c = new_world();
+    c.spawn_at(second, (Position(1, 1), Name("b"))).unwrap();
+    c.spawn_at(first, (Position(0, 0), A(0))).unwrap();
+    assert_eq!(a.state_hash(), c.state_hash());
+    assert_eq!(a.state_hash().total(), c.state_hash().total());
+
+    // Components that aren't registered only affect the hash of the `Entity`s.
+    b.insert(first, A(1)).unwrap();
+    assert_eq!(a.state_hash(), b.state_hash());
+    b.insert(first, B(1)).unwrap();
+    let (hash_a, hash_b) = (a.state_hash(), b.state_hash());
+    assert_ne!(hash_a.entities, hash_b.entities);
+    assert!(hash_a.diverged(&hash_b).is_empty());
+    b.remove_components::<B>(first).unwrap();
+
+    b.insert(second, Position(1, 2)).unwrap();
+    let (hash_a, hash_b) = (a.state_hash(), b.state_hash());
+    assert_ne!(hash_a.total(), hash_b.total());
+    assert_eq!(hash_a.diverged(&hash_b), [Position::component_id()]);
+
+    b.insert(second, Position(1, 1)).unwrap();
+    b.insert(second, Name("c")).unwrap();
+    assert_eq!(
+        a.state_hash().diverged(&b.state_hash()),
+        [Name::component_id()]
+    );
+}
+
+#[test]
+fn rollback() {
+    #[derive(Clone)]
+    struct Health(u32);
+    impl ComponentTrait for Health {
+        const STORAGE: StorageKind = StorageKind::SparseSet;
+        fn clone_vec(data: &[Self]) -> Option<Vec<Self>> {
+            Some(data.into())
+        }
+    }
+    struct Player;
+    impl ComponentTrait for Player {
+        const STORAGE: StorageKind = StorageKind::Tag;
+        fn clone_vec(_data: &[Self]) -> Option<Vec<Self>> {
+            None
+        }
+    }
+    struct Name(String);
+    impl ComponentTrait for Name {
+        fn clone_vec(_data: &[Self]) -> Option<Vec<Self>> {
+            None
+        }
+    }
+
+    let mut world = World::new();
+    let a = world.spawn((A(0), Player));
+    world.add_components(a, Health(10)).unwrap();
+    let b = world.spawn((A(1), B(1)));
+    let c = world.spawn((A(2), Name("c".into())));
+    let dead = world.spawn(B(3));
+    world.despawn(dead).unwrap();
+    let snapshot = world.snapshot();
+    assert_eq!(snapshot.len(), 3);
+    assert_eq!(world.get::<Name>(c).unwrap().0, "c");
+
+    *world.get_mut::<A>(a).unwrap() = A(5);
+    world.remove_components::<Health>(a).unwrap();
+    world.despawn(b).unwrap();
+    let spawned = world.spawn(A(6));
+    world.restore(&snapshot);
+
+    assert_eq!(world.get::<A>(a).unwrap().0, 0);
+    assert!(world.get::<Player>(a).is_ok());
+    assert_eq!(world.get::<Health>(a).unwrap().0, 10);
+    assert_eq!(world.get::<B>(b).unwrap().0, 1);
+    assert!(world.get::<A>(spawned).is_err());
+    assert!(world.get::<B>(dead).is_err());
+    // `Name` can't be cloned so it's dropped, leaving `c` with only `A`.
+    assert!(world.get::<Name>(c).is_err());
+    assert_eq!(world.get::<A>(c).unwrap().0, 2);
+    let mut values: Vec<usize> = world.query::<All<&A>>().iter().map(|a| a.0).collect();
+    values.sort_unstable();
+    assert_eq!(values, [0, 1, 2]);
+    // Free indices are restored too, so the next spawn reuses `dead`'s index.
+    assert_eq!(world.spawn(A(7)), Entity::from_index_and_generation(3, 1));
+
+    let mut ring = SnapshotRing::new(2);
+    assert!(!ring.rollback(&mut world, 0));
+    for value in 10..13 {
+        *world.get_mut::<A>(a).unwrap() = A(value);
+        ring.record(&mut world);
+    }
+    assert_eq!(ring.len(), 2);
+    assert!(ring.get(2).is_none());
+    assert!(ring.rollback(&mut world, 1));
+    assert_eq!(world.get::<A>(a).unwrap().0, 11);
+    assert_eq!(ring.len(), 1);
+
+    // A component that `clone_vec` only sometimes clones is left out instead of panicking.
+    struct Flaky(u32);
+    impl ComponentTrait for Flaky {
+        fn clone_vec(data: &[Self]) -> Option<Vec<Self>> {
+            data.iter()
+                .map(|f| (f.0 != 0).then_some(Flaky(f.0)))
+                .collect()
+        }
+    }
+    let mut world = World::new();
+    let plain = world.spawn(A(1));
+    let flaky = world.spawn((A(0), Flaky(0)));
+    let snapshot = world.snapshot();
+    world.despawn(flaky).unwrap();
+    world.restore(&snapshot);
+    assert_eq!(world.get::<A>(flaky).unwrap().0, 0);
+    assert!(world.get::<Flaky>(flaky).is_err());
+    assert_eq!(world.get::<A>(plain).unwrap().0, 1);
+
+    // A channel that clones into the snapshot but not back out of it is dropped on restore,
+    // so its group shares an `Archetype` with another without their rows colliding.
+    static CLONES: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+    struct Once;
+    impl ComponentTrait for Once {
+        fn clone_vec(data: &[Self]) -> Option<Vec<Self>> {
+            (data.is_empty() || CLONES.fetch_add(1, std::sync::atomic::Ordering::Relaxed) == 0)
+                .then(|| data.iter().map(|_| Once).collect())
+        }
+    }
+    let mut world = World::new();
+    let plain = world.spawn(A(1));
+    let once = world.spawn((A(2), Once));
+    let snapshot = world.snapshot();
+    world.restore(&snapshot);
+    assert!(world.get::<Once>(once).is_err());
+    assert_eq!(world.get::<A>(plain).unwrap().0, 1);
+    assert_eq!(world.get::<A>(once).unwrap().0, 2);
+}
+
+#[cfg(feature = "serde")]
+impl SerializeComponent for A {
+    fn serialize(&self, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(&(self.0 as u64).to_le_bytes());
+    }
+    fn deserialize(bytes: &mut &[u8]) -> Option<Self> {
+        let (value, rest) = bytes.split_first_chunk()?;
+        *bytes = rest;
+        Some(A(u64::from_le_bytes(*value) as usize))
+    }
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn binary_snapshot() {
+    struct Name(String);
+    impl ComponentTrait for Name {
+        const STORAGE: StorageKind = StorageKind::SparseSet;
+        fn clone_vec(_data: &[Self]) -> Option<Vec<Self>> {
+            None
+        }
+    }
+    impl SerializeComponent for Name {
+        fn serialize(&self, bytes: &mut Vec<u8>) {
+            bytes.push(self.0.len() as u8);
+            bytes.extend_from_slice(self.0.as_bytes());
+        }
+        fn deserialize(bytes: &mut &[u8]) -> Option<Self> {
+            let (len, rest) = bytes.split_first()?;
+            let name = rest.get(..*len as usize)?;
+            *bytes = &rest[*len as usize..];
+            Some(Name(String::from_utf8(name.to_vec()).ok()?))
+        }
+    }
+    #[derive(Default)]
+    struct ChildOf;
+    impl ComponentTrait for ChildOf {
+        const STORAGE: StorageKind = StorageKind::Tag;
+        fn clone_vec(_data: &[Self]) -> Option<Vec<Self>> {
+            None
+        }
+    }
+    fn register(world: &mut World) {
+        world.components_mut().register_serialize::<A>();
+        world.components_mut().register_serialize::<Name>();
+        world.components_mut().register::<ChildOf>();
+    }
+
+    let mut world = World::new();
+    register(&mut world);
+    let parent = world.spawn((A(1), Name("parent".into())));
+    let despawned = world.spawn(A(2));
+    let child = world.spawn((A(3), B(3)));
+    let only_b = world.spawn(B(4));
+    world.despawn(despawned).unwrap();
+    world.relate::<ChildOf>(child, parent).unwrap();
+    let bytes = world.save_binary();
+
+    let mut loaded = World::new();
+    register(&mut loaded);
+    loaded.load_binary(&bytes).unwrap();
+    assert_eq!(loaded.get::<A>(parent).unwrap().0, 1);
+    assert_eq!(loaded.get::<Name>(parent).unwrap().0, "parent");
+    assert_eq!(loaded.get::<A>(child).unwrap().0, 3);
+    assert!(loaded.has_relation::<ChildOf>(child, parent));
+    // `B` isn't serializable so it's skipped.
+    assert!(loaded.get::<B>(child).is_err());
+    assert!(loaded.get::<A>(despawned).is_err());
+    // The despawned index is reused with the next generation, like in the saved `World`.
+    assert_eq!(loaded.spawn(A(4)), world.spawn(A(4)));
+    // Entities without serializable components still exist.
+    assert!(loaded.despawn(only_b).is_ok());
+    assert_eq!(loaded.query::<All<&A>>().iter().count(), 3);
+
+    let mut unregistered = World::new();
+    assert!(matches!(
+        unregistered.load_binary(&bytes),
+        Err(ECSError::NoMatchingComponent)
+    ));
+    let mut truncated = World::new();
+    register(&mut truncated);
+    for cut in [1, 6] {
+        assert!(matches!(
+            truncated.load_binary(&bytes[..bytes.len() - cut]),
+            Err(ECSError::InvalidSnapshot)
+        ));
+    }
+    // Nothing is loaded from a snapshot that fails partway.
+    assert!(truncated.get::<A>(child).is_err());
+    assert_eq!(truncated.query::<All<&A>>().iter().count(), 0);
+    assert_eq!(
+        truncated.spawn(A(0)),
+        Entity::from_index_and_generation(0, 0)
+    );
+
+    // Rows can't alias a live `Entity` or give a row to a despawned one.
+    let u64s =
+        |values: &[u64]| -> Vec<u8> { values.iter().flat_map(|v| v.to_le_bytes()).collect() };
+    let replace = |bytes: &[u8], from: &[u8], to: &[u8]| {
+        let at = bytes.windows(from.len()).position(|w| w == from).unwrap();
+        let mut bytes = bytes.to_vec();
+        bytes[at..at + to.len()].copy_from_slice(to);
+        bytes
+    };
+    let mut small = World::new();
+    register(&mut small);
+    small.spawn(A(0));
+    small.spawn(A(1));
+    let bytes = small.save_binary();
+    let duplicate = replace(&bytes, &u64s(&[2, 0, 1]), &u64s(&[2, 0, 0]));
+    let entity = small.spawn(A(2));
+    small.despawn(entity).unwrap();
+    let bytes = small.save_binary();
+    let freed = replace(&bytes, &u64s(&[2, 0, 1]), &u64s(&[2, 0, 2]));
+    for bytes in [duplicate, freed] {
+        let mut loaded = World::new();
+        register(&mut loaded);
+        assert!(matches!(
+            loaded.load_binary(&bytes),
+            Err(ECSError::InvalidSnapshot)
+        ));
+    }
+}
+
+#[cfg(feature = "transform")]
+#[test]
+fn transform_propagation() {
+    let mut world = World::new();
+    let translation = |x| LocalTransform(Mat4::from_translation([x, 0.0, 0.0]));
+    let root = world.spawn((translation(1.0), GlobalTransform::default()));
+    let child = world.spawn((translation(2.0), GlobalTransform::default()));
+    let grandchild = world.spawn((translation(3.0), GlobalTransform::default()));
+    world.add_components(child, TransformParent(root)).unwrap();
+    world
+        .add_components(grandchild, TransformParent(child))
+        .unwrap();
+
+    let global_x = |world: &World, entity| {
+        world
+            .get::<GlobalTransform>(entity)
+            .unwrap()
+            .get()
+            .translation()[0]
+    };
+    let mut propagation = TransformPropagation::new();
+    propagation.propagate(&mut world);
+    assert_eq!(global_x(&world, grandchild), 6.0);
+    type ChangedGlobals<'a> = All<'a, &'a GlobalTransform, Changed<GlobalTransform>>;
+    let mut changed_globals = QueryState::new();
+    world.query_with_state::<ChangedGlobals>(&mut changed_globals);
+
+    // Only the dirty subtree is recomputed.
+    world.get_mut::<LocalTransform>(child).unwrap().0 = Mat4::from_translation([5.0, 0.0, 0.0]);
+    propagation.propagate(&mut world);
+    let mut changed: Vec<Entity> = world
+        .query_with_state::<ChangedGlobals>(&mut changed_globals)
+        .entities()
+        .collect();
+    changed.sort();
+    assert_eq!(changed, vec![child, grandchild]);
+    assert_eq!(global_x(&world, grandchild), 9.0);
+
+    world.remove_components::<TransformParent>(child).unwrap();
+    propagation.propagate(&mut world);
+    assert_eq!(global_x(&world, grandchild), 8.0);
+
+    world
+        .add_components(grandchild, TransformParent(root))
+        .unwrap();
+    propagation.propagate(&mut world);
+    assert_eq!(global_x(&world, grandchild), 4.0);
+    assert_eq!(global_x(&world, child), 5.0);
+
+    // Children of a parent whose `LocalTransform` is removed, or that is despawned, become roots.
+    let global = |world: &World, entity| world.get::<GlobalTransform>(entity).unwrap().get();
+    for despawn in [false, true] {
+        let parent = world.spawn((translation(10.0), GlobalTransform::default()));
+        let child = world.spawn((translation(1.0), GlobalTransform::default()));
+        world
+            .add_components(child, TransformParent(parent))
+            .unwrap();
+        propagation.propagate(&mut world);
+        assert_eq!(global(&world, child).translation(), [11.0, 0.0, 0.0]);
+        if despawn {
+            world.despawn(parent).unwrap();
+        } else {
+            world.remove_components::<LocalTransform>(parent).unwrap();
+        }
+        propagation.propagate(&mut world);
+        assert_eq!(global(&world, child).translation(), [1.0, 0.0, 0.0]);
+    }
+}